@@ -4,17 +4,34 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::collections::hash_map::RandomState;
 use std::collections::BTreeMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use thiserror::Error;
 
-use crate::config::{Account, Filter, QueryTarget};
-use crate::todo::TodoItem;
+use crate::cache::Cache;
+use crate::config::{Account, BackoffPolicy, DueSource, Profile, WriteBack};
+use crate::secret::{self, SecretError};
+use crate::todo::{Attachment, Dependencies, Due, Estimate, TodoItem, TodoKind, TodoStatus};
 
+mod capture;
 mod prelude;
 
+pub use self::capture::HttpCapture;
+
+#[cfg(feature = "bitbucket")]
+mod bitbucket;
 #[cfg(feature = "github")]
 mod github;
+#[cfg(feature = "sourcehut")]
+mod sourcehut;
 
 #[derive(Debug, Error)]
 #[error("failed to fetch items")]
@@ -26,35 +43,463 @@ pub enum ItemError {
         service: &'static str,
         message: String,
     },
+    #[error("request budget exhausted for {}", service)]
+    BudgetExhausted { service: &'static str },
+    #[error("unsupported operation: {}", operation)]
+    Unsupported { operation: &'static str },
+    #[error(
+        "insufficient rate limit for {}: {} points needed, {} remaining",
+        service,
+        needed,
+        remaining
+    )]
+    InsufficientRateLimit {
+        service: &'static str,
+        needed: i64,
+        remaining: i64,
+    },
+}
+
+impl ItemError {
+    /// Whether the query may succeed if tried again in a later run.
+    pub fn is_deferrable(&self) -> bool {
+        matches!(
+            self,
+            ItemError::BudgetExhausted { .. } | ItemError::InsufficientRateLimit { .. },
+        )
+    }
+}
+
+/// A limit on the number of requests made to a service in a single run.
+#[derive(Debug, Default)]
+pub struct RequestBudget {
+    limit: Option<usize>,
+    used: AtomicUsize,
+}
+
+impl RequestBudget {
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record a request against the budget.
+    ///
+    /// Returns `false` if the budget does not allow for the request.
+    pub fn spend(&self) -> bool {
+        let used = self.used.fetch_add(1, Ordering::SeqCst);
+        match self.limit {
+            Some(limit) => used < limit,
+            None => true,
+        }
+    }
+
+    /// The number of requests made against the budget.
+    pub fn used(&self) -> usize {
+        let used = self.used.load(Ordering::SeqCst);
+        self.limit.map_or(used, |limit| used.min(limit))
+    }
+
+    /// Whether the budget has been used up.
+    pub fn is_exhausted(&self) -> bool {
+        match self.limit {
+            Some(limit) => self.used.load(Ordering::SeqCst) >= limit,
+            None => false,
+        }
+    }
+}
+
+/// Errors from services which may be retried.
+pub trait RetryableError {
+    /// Whether the request should be retried after a delay.
+    fn should_backoff(&self) -> bool;
+
+    /// How long the service asked to wait before retrying, if it did.
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+
+    /// The error to use when retries have been exhausted.
+    fn backoff_exhausted() -> Self;
 }
 
+// The longest delay between retries (scaling the delay stops here).
+const MAX_BACKOFF_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// A random amount of extra delay for a retry.
+fn jitter(delay: Duration, fraction: f64) -> Duration {
+    if fraction <= 0. {
+        return Duration::ZERO;
+    }
+
+    // Randomly seeded hashers are a cheap source of randomness.
+    let random = RandomState::new().build_hasher().finish();
+    delay.mul_f64(fraction.min(1.) * (random as f64 / u64::MAX as f64))
+}
+
+/// Retry a request with exponential backoff.
+pub async fn retry_with_backoff<F, R, K, E>(policy: &BackoffPolicy, mut go: F) -> Result<K, E>
+where
+    F: FnMut() -> R,
+    R: Future<Output = Result<K, E>>,
+    E: RetryableError,
+{
+    let start = Instant::now();
+    let max_elapsed = policy
+        .max_elapsed
+        .and_then(|max_elapsed| max_elapsed.0.to_std().ok());
+    let mut timeout = policy
+        .start
+        .0
+        .to_std()
+        .unwrap_or_default()
+        .min(MAX_BACKOFF_DELAY);
+    for attempt in 1..=policy.limit {
+        match go().await {
+            Ok(r) => return Ok(r),
+            Err(err) => {
+                if !err.should_backoff() {
+                    return Err(err);
+                }
+                // There is no point in waiting if there will not be another attempt.
+                if attempt == policy.limit {
+                    break;
+                }
+
+                let delay = err
+                    .retry_after()
+                    .unwrap_or_else(|| timeout + jitter(timeout, policy.jitter));
+                if max_elapsed.is_some_and(|max_elapsed| start.elapsed() + delay > max_elapsed) {
+                    break;
+                }
+                tokio::time::sleep(delay).await;
+                timeout = timeout
+                    .checked_mul(policy.scale)
+                    .map_or(MAX_BACKOFF_DELAY, |timeout| timeout.min(MAX_BACKOFF_DELAY));
+            },
+        }
+    }
+
+    Err(E::backoff_exhausted())
+}
+
+/// Existing items indexed by their URL.
 pub type ItemLookup<'a> = BTreeMap<String, &'a mut TodoItem>;
 
-pub trait ItemSource {
-    fn fetch_items(
-        &self,
-        target: &QueryTarget,
-        filters: &[Filter],
-        existing_items: &mut ItemLookup,
-    ) -> Result<Vec<TodoItem>, ItemError>;
+/// The due dates of an item and of the things it belongs to.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DueDates {
+    pub item: Option<Due>,
+    pub milestone: Option<Due>,
+    pub iteration: Option<Due>,
+    pub epic: Option<Due>,
+}
+
+impl DueDates {
+    /// The first due date available from the given sources.
+    pub fn resolve(&self, fallback: &[DueSource]) -> Option<Due> {
+        fallback.iter().find_map(|source| {
+            match source {
+                DueSource::Item => self.item,
+                DueSource::Milestone => self.milestone,
+                DueSource::Iteration => self.iteration,
+                DueSource::Epic => self.epic,
+            }
+        })
+    }
+}
+
+/// An item as reported by a service.
+pub struct SourceItem {
+    /// The due date of the item.
+    ///
+    /// Set from `due_dates` using the fallback of the profile which fetched the item.
+    pub due: Option<Due>,
+    /// The due dates the item's due date may be taken from.
+    pub due_dates: DueDates,
+    pub estimate: Option<Estimate>,
+    pub summary: String,
+    pub description: String,
+    pub kind: TodoKind,
+    pub status: TodoStatus,
+    pub url: String,
+    pub attachments: Vec<Attachment>,
+    /// How the item depends on other items.
+    pub dependencies: Dependencies,
+    /// The user created the item, but someone else is working on it.
+    pub delegated: bool,
+    /// Labels on the item.
+    ///
+    /// These are replaced by the categories derived from them before updating items.
+    pub labels: Vec<String>,
+    /// The item is only visible to some users (e.g., it is in a private repository).
+    pub confidential: bool,
+    /// Mark the item as private.
+    pub private: bool,
+    /// An identifier for the item on the service which is kept when the item moves.
+    pub source_id: Option<String>,
+    /// The title of the milestone the item belongs to.
+    pub milestone: Option<String>,
+    /// The priority of the item (1 is the highest, 9 the lowest).
+    pub priority: Option<u8>,
+    /// Extra properties to record on the item (by name without the `X-` prefix).
+    pub extra_properties: BTreeMap<String, String>,
+    /// The logins of the users assigned to the item.
+    ///
+    /// Only kept for team queries, where they are turned into categories.
+    pub assignees: Vec<String>,
+    /// When the item was last updated on the service.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl SourceItem {
+    /// Replace the details of the item with a generic summary.
+    ///
+    /// The summary only includes the kind and number of the item (e.g., "Confidential issue
+    /// #123").
+    pub fn redact(&mut self) {
+        let noun = self.kind.noun();
+        let number = short_reference(&self.url)
+            .and_then(|reference| {
+                reference
+                    .rfind(['#', '!'])
+                    .map(|idx| reference[idx..].to_string())
+            })
+            .unwrap_or_default();
+        self.summary = format!("Confidential {} {}", noun, number)
+            .trim_end()
+            .into();
+        self.description.clear();
+        self.attachments.clear();
+        self.labels.clear();
+    }
+
+    /// Update an existing item with the information from the service.
+    pub fn update(self, item: &mut TodoItem) {
+        if let Some(due) = self.due {
+            item.set_due(due);
+        }
+        item.set_estimate(self.estimate);
+        // The kind may change (e.g., an issue is assigned to the user).
+        item.set_kind(self.kind);
+        item.set_status(self.status);
+        item.set_summary(self.summary);
+        item.set_description(self.description);
+        item.set_attachments(self.attachments);
+        item.set_dependencies(self.dependencies);
+        item.set_delegated(self.delegated);
+        item.set_label_categories(self.labels);
+        item.set_private(self.private);
+        item.set_milestone(self.milestone);
+        item.set_priority(self.priority);
+        item.set_extra_properties(self.extra_properties);
+        // The item may have moved (e.g., it was transferred to another repository).
+        item.set_url(self.url);
+        if let Some(source_id) = self.source_id {
+            item.set_source_id(source_id);
+        }
+    }
+
+    /// Create a new item from the information from the service.
+    pub fn into_item(self) -> TodoItem {
+        let mut item = TodoItem::builder();
+
+        item.kind(self.kind)
+            .status(self.status)
+            .url(self.url)
+            .summary(self.summary)
+            .description(self.description)
+            .attachments(self.attachments)
+            .dependencies(self.dependencies)
+            .delegated(self.delegated)
+            .label_categories(self.labels)
+            .extra_properties(self.extra_properties)
+            .private(self.private);
+
+        if let Some(due) = self.due {
+            item.due(due);
+        }
+        if let Some(estimate) = self.estimate {
+            item.estimate(estimate);
+        }
+        if let Some(source_id) = self.source_id {
+            item.source_id(source_id);
+        }
+        if let Some(milestone) = self.milestone {
+            item.milestone(milestone);
+        }
+        if let Some(priority) = self.priority {
+            item.priority(priority);
+        }
+
+        item.build().expect("all item fields should be provided")
+    }
+}
+
+/// The identity an account is connected as.
+pub struct Identity {
+    /// The login of the user.
+    pub login: String,
+    /// The number of requests remaining in the service's rate limit.
+    pub rate_limit_remaining: Option<i64>,
+}
+
+/// The state of a service's rate limit as of the last response.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// The number of requests remaining.
+    pub remaining: i64,
+    /// When the rate limit resets.
+    pub reset_at: Option<DateTime<Utc>>,
+}
+
+/// The result of an operation on an item source.
+pub type ItemFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ItemError>> + Send + 'a>>;
+
+/// A source of items.
+///
+/// Operations are asynchronous so that accounts may be fetched from concurrently.
+pub trait ItemSource: Send + Sync {
+    fn fetch_items<'a>(&'a self, profile: &'a Profile) -> ItemFuture<'a, Vec<SourceItem>>;
+
+    /// Query the identity of the account using a cheap request.
+    fn identity(&self) -> ItemFuture<'_, Identity>;
+
+    /// The number of requests made to the service so far.
+    fn requests_made(&self) -> usize {
+        0
+    }
+
+    /// Whether the account's request budget has been used up.
+    fn is_exhausted(&self) -> bool {
+        false
+    }
+
+    /// The service's rate limit as of the last response.
+    fn rate_limit(&self) -> Option<RateLimit> {
+        None
+    }
+
+    /// Reflect the local completion of an item on the service.
+    ///
+    /// The `comment` is used when commenting on the item.
+    fn write_back<'a>(
+        &'a self,
+        _url: &'a str,
+        _action: WriteBack,
+        _comment: &'a str,
+    ) -> ItemFuture<'a, ()> {
+        Box::pin(async {
+            Err(ItemError::Unsupported {
+                operation: "writing back completed items",
+            })
+        })
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum AccountError {
-    #[cfg(not(any(feature = "github")))]
+    #[cfg(not(all(feature = "bitbucket", feature = "github", feature = "sourcehut")))]
     #[error("unsupported service: {}", service)]
     UnsupportedService { service: &'static str },
     #[error("unknown service: {}", service)]
     UnknownService { service: String },
+    #[error("failed to get the secret")]
+    Secret {
+        #[from]
+        source: SecretError,
+    },
+}
+
+// Path segments which precede the number of an item in its URL and the sigil used to
+// reference items of that kind.
+const REFERENCE_SIGILS: &[(&str, char)] = &[
+    ("issues", '#'),
+    ("pull", '#'),
+    ("pull-requests", '#'),
+    ("merge_requests", '!'),
+];
+
+/// A short reference to an item from its URL (e.g., `owner/repo#123`).
+///
+/// Merge requests use `!` as is conventional on GitLab.
+pub fn short_reference(url: &str) -> Option<String> {
+    let path = url.split_once("://").map_or(url, |(_, rest)| rest);
+    // Skip the host.
+    let segments = path.split('/').skip(1).collect::<Vec<_>>();
+    segments.windows(2).enumerate().find_map(|(idx, window)| {
+        let (kind, number) = (window[0], window[1]);
+        let sigil = REFERENCE_SIGILS
+            .iter()
+            .find_map(|&(name, sigil)| Some(sigil).filter(|_| name == kind))?;
+        if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        // GitLab separates the project from the rest of the path with `-`.
+        let project = segments[..idx]
+            .iter()
+            .copied()
+            .filter(|&segment| segment != "-")
+            .collect::<Vec<_>>();
+        if project.is_empty() {
+            return None;
+        }
+
+        Some(format!("{}{}{}", project.join("/"), sigil, number))
+    })
+}
+
+/// The services which accounts may use.
+pub const SERVICES: &[&str] = &["bitbucket", "github", "sourcehut"];
+
+/// Facilities shared by connections to services.
+#[derive(Clone, Default)]
+pub struct ConnectContext {
+    /// Where to record HTTP exchanges.
+    pub capture: Option<Arc<HttpCapture>>,
+    /// The cache for service responses.
+    pub cache: Option<Arc<Cache>>,
+}
+
+/// The host secrets for an account are looked up for.
+pub fn secret_host(account: &Account) -> &str {
+    let default_host = match account.service.as_ref() {
+        "bitbucket" => "bitbucket.org",
+        "sourcehut" => "todo.sr.ht",
+        _ => "github.com",
+    };
+    account.hostname.as_deref().unwrap_or(default_host)
 }
 
-pub fn connect(account: Account) -> Result<Box<dyn ItemSource>, AccountError> {
+pub fn connect(
+    account: Account,
+    context: &ConnectContext,
+) -> Result<Box<dyn ItemSource>, AccountError> {
     match account.service.as_ref() {
+        #[cfg(feature = "bitbucket")]
+        "bitbucket" => {
+            let tokens = secret::resolve(&account.secret, secret_host(&account))?;
+            Ok(Box::new(bitbucket::BitbucketQuery::new(
+                account,
+                tokens,
+                context.clone(),
+            )))
+        },
+        #[cfg(not(feature = "bitbucket"))]
+        "bitbucket" => {
+            Err(AccountError::UnsupportedService {
+                service: "bitbucket",
+            })
+        },
         #[cfg(feature = "github")]
         "github" => {
+            let tokens = secret::resolve(&account.secret, secret_host(&account))?;
             Ok(Box::new(github::GithubQuery::new(
-                account.hostname,
-                account.secret,
+                account,
+                tokens,
+                context.clone(),
             )))
         },
         #[cfg(not(feature = "github"))]
@@ -63,6 +508,21 @@ pub fn connect(account: Account) -> Result<Box<dyn ItemSource>, AccountError> {
                 service: "github",
             })
         },
+        #[cfg(feature = "sourcehut")]
+        "sourcehut" => {
+            let tokens = secret::resolve(&account.secret, secret_host(&account))?;
+            Ok(Box::new(sourcehut::SourcehutQuery::new(
+                account,
+                tokens,
+                context.clone(),
+            )))
+        },
+        #[cfg(not(feature = "sourcehut"))]
+        "sourcehut" => {
+            Err(AccountError::UnsupportedService {
+                service: "sourcehut",
+            })
+        },
 
         service => {
             Err(AccountError::UnknownService {
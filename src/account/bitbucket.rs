@@ -0,0 +1,517 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{SecondsFormat, Utc};
+use futures_util::{future, stream, StreamExt, TryStreamExt};
+use lazy_init::LazyTransform;
+use log::{error, warn};
+use once_cell::sync::OnceCell;
+
+use crate::account::prelude::*;
+use crate::account::{ConnectContext, RequestBudget};
+use crate::config::{Account, BackoffPolicy};
+use crate::todo::{Attachment, Dependencies, TodoKind, TodoStatus};
+
+mod client;
+mod types;
+
+// The API host for Bitbucket Cloud.
+const BITBUCKET_API_HOST: &str = "api.bitbucket.org";
+
+// The number of results in each page.
+const PAGE_LENGTH: usize = 50;
+// The number of repositories to fetch items from at once.
+const CONCURRENT_REPOSITORIES: usize = 8;
+// How long closed items are still fetched for so that their new status is noticed.
+const RECENTLY_CLOSED_DAYS: i64 = 7;
+
+struct ConnInfo {
+    api_url: String,
+    tokens: Vec<String>,
+    backoff: BackoffPolicy,
+    context: ConnectContext,
+    budget: Arc<RequestBudget>,
+    timeout: Option<Duration>,
+    proxy: Option<String>,
+}
+
+pub struct BitbucketQuery {
+    client: LazyTransform<ConnInfo, client::BitbucketResult<client::Bitbucket>>,
+    init_error_cell: OnceCell<()>,
+    /// The UUID of the user.
+    viewer: OnceCell<String>,
+    budget: Arc<RequestBudget>,
+}
+
+/// Whether an item satisfies the filters.
+fn matches_filters(item: &SourceItem, filters: &[Filter]) -> bool {
+    filters.iter().all(|filter| {
+        match filter {
+            Filter::Label(label) => item.labels.iter().any(|name| name == label),
+            Filter::NotLabel(label) => !item.labels.iter().any(|name| name == label),
+            Filter::Milestone(milestone) => item.milestone.as_ref() == Some(milestone),
+        }
+    })
+}
+
+/// The iCalendar priority for a Bitbucket issue priority.
+fn issue_priority(priority: &str) -> Option<u8> {
+    match priority {
+        "blocker" => Some(1),
+        "critical" => Some(3),
+        "major" => Some(5),
+        "minor" => Some(7),
+        "trivial" => Some(9),
+        priority => {
+            warn!("unknown bitbucket issue priority: {:?}", priority);
+            None
+        },
+    }
+}
+
+/// Convert an issue into an item.
+///
+/// Bitbucket issues do not have labels, so their kind (e.g., `bug`) and component are used
+/// instead.
+fn issue_item(repo: &types::Repository, issue: types::Issue, viewer: &str) -> SourceItem {
+    let is_assigned = issue
+        .assignee
+        .as_ref()
+        .is_some_and(|assignee| assignee.uuid == viewer);
+    let is_author = issue
+        .reporter
+        .as_ref()
+        .is_some_and(|reporter| reporter.uuid == viewer);
+    let has_assignee = issue.assignee.is_some();
+    let kind = if is_assigned {
+        TodoKind::AssignedIssue
+    } else {
+        TodoKind::Issue
+    };
+    let status = match issue.state.as_str() {
+        "resolved" | "closed" => TodoStatus::Completed,
+        "invalid" | "duplicate" | "wontfix" => TodoStatus::Cancelled,
+        "new" | "open" | "on hold" | "submitted" => {
+            if has_assignee {
+                TodoStatus::InProcess
+            } else {
+                TodoStatus::NeedsAction
+            }
+        },
+        state => {
+            warn!("unknown bitbucket issue state: {:?}", state);
+            TodoStatus::NeedsAction
+        },
+    };
+    let is_open = matches!(status, TodoStatus::NeedsAction | TodoStatus::InProcess);
+    let delegated = is_open && is_author && has_assignee && !is_assigned;
+    let labels = Some(issue.kind)
+        .into_iter()
+        .chain(issue.component.map(|component| component.name))
+        .collect();
+
+    SourceItem {
+        due: None,
+        due_dates: DueDates::default(),
+        estimate: None,
+        summary: issue.title,
+        description: issue.content.raw.unwrap_or_default(),
+        kind,
+        status,
+        url: issue.links.html.href,
+        attachments: Vec::new(),
+        dependencies: Dependencies::default(),
+        delegated,
+        labels,
+        confidential: repo.is_private,
+        private: false,
+        source_id: None,
+        milestone: issue.milestone.map(|milestone| milestone.name),
+        priority: issue.priority.as_deref().and_then(issue_priority),
+        assignees: Vec::new(),
+        extra_properties: BTreeMap::new(),
+        updated_at: Some(issue.updated_on),
+    }
+}
+
+/// Convert a pull request into an item.
+fn pull_request_item(repo: &types::Repository, pr: types::PullRequest, viewer: &str) -> SourceItem {
+    let is_review_requested = pr.reviewers.iter().any(|reviewer| reviewer.uuid == viewer);
+    let is_author = pr.author.uuid == viewer;
+    let has_reviewers = !pr.reviewers.is_empty();
+    let kind = if is_review_requested {
+        TodoKind::ReviewRequest
+    } else {
+        TodoKind::PullRequest
+    };
+    let status = match pr.state.as_str() {
+        "MERGED" => TodoStatus::Completed,
+        "DECLINED" | "SUPERSEDED" => TodoStatus::Cancelled,
+        "OPEN" => {
+            if has_reviewers {
+                TodoStatus::InProcess
+            } else {
+                TodoStatus::NeedsAction
+            }
+        },
+        state => {
+            warn!("unknown bitbucket pr state: {:?}", state);
+            TodoStatus::NeedsAction
+        },
+    };
+    let delegated = pr.state == "OPEN" && is_author && !is_review_requested && has_reviewers;
+    let attachments = pr
+        .links
+        .diff
+        .map(|diff| Attachment::new(diff.href).with_format("text/x-diff"))
+        .into_iter()
+        .collect();
+
+    SourceItem {
+        due: None,
+        due_dates: DueDates::default(),
+        estimate: None,
+        summary: pr.title,
+        description: pr.description,
+        kind,
+        status,
+        url: pr.links.html.href,
+        attachments,
+        dependencies: Dependencies::default(),
+        delegated,
+        labels: Vec::new(),
+        confidential: repo.is_private,
+        private: false,
+        source_id: None,
+        milestone: None,
+        priority: None,
+        assignees: Vec::new(),
+        extra_properties: BTreeMap::new(),
+        updated_at: Some(pr.updated_on),
+    }
+}
+
+impl BitbucketQuery {
+    pub fn new(account: Account, tokens: Vec<String>, context: ConnectContext) -> Self {
+        let budget = Arc::new(RequestBudget::new(account.max_requests_per_run));
+        let host = account
+            .hostname
+            .unwrap_or_else(|| BITBUCKET_API_HOST.into());
+        let path = account.api_path.unwrap_or_else(|| "2.0".into());
+        let scheme = if account.insecure_http {
+            warn!("using plain HTTP to communicate with {}", host);
+            "http"
+        } else {
+            "https"
+        };
+        let api_url = format!("{}://{}/{}", scheme, host, path.trim_start_matches('/'));
+
+        BitbucketQuery {
+            client: LazyTransform::new(ConnInfo {
+                api_url,
+                tokens,
+                backoff: account.backoff,
+                context,
+                budget: budget.clone(),
+                timeout: account.timeout.and_then(|timeout| timeout.0.to_std().ok()),
+                proxy: account.proxy,
+            }),
+            init_error_cell: OnceCell::new(),
+            viewer: OnceCell::new(),
+            budget,
+        }
+    }
+
+    /// The client for the instance.
+    fn client(&self) -> Result<&client::Bitbucket, ItemError> {
+        self.client
+            .get_or_create(|info| {
+                client::Bitbucket::new(
+                    &info.api_url,
+                    info.tokens,
+                    info.backoff,
+                    info.budget,
+                    info.context,
+                    info.timeout,
+                    info.proxy.as_deref(),
+                )
+            })
+            .as_ref()
+            .map_err(|err| {
+                self.init_error_cell.get_or_init(|| {
+                    error!("failed to connect to bitbucket: {:?}", err);
+                });
+                ItemError::ServiceError {
+                    service: "bitbucket",
+                }
+            })
+    }
+
+    /// Convert an error from a request into an item error.
+    fn query_error(name: &str, err: client::BitbucketError) -> ItemError {
+        if let client::BitbucketError::BudgetExhausted {} = err {
+            return ItemError::BudgetExhausted {
+                service: "bitbucket",
+            };
+        }
+
+        error!("failed to fetch {}: {:?}", name, err);
+        let message = format!("failed to fetch {}: {}", name, err);
+        ItemError::QueryError {
+            service: "bitbucket",
+            message,
+        }
+    }
+
+    /// The authenticated user.
+    async fn query_user(client: &client::Bitbucket) -> Result<types::User, ItemError> {
+        let url = client
+            .endpoint("user")
+            .map_err(|err| Self::query_error("user", err))?;
+        client
+            .get(&url)
+            .await
+            .map_err(|err| Self::query_error("user", err))
+    }
+
+    /// The UUID of the authenticated user.
+    async fn viewer(&self, client: &client::Bitbucket) -> Result<&str, ItemError> {
+        if let Some(viewer) = self.viewer.get() {
+            return Ok(viewer);
+        }
+
+        let user = Self::query_user(client).await?;
+        Ok(self.viewer.get_or_init(|| user.uuid))
+    }
+
+    /// The repositories the user is a member of.
+    async fn query_member_repositories(
+        client: &client::Bitbucket,
+    ) -> Result<Vec<types::Repository>, ItemError> {
+        let mut url = client
+            .endpoint("repositories")
+            .map_err(|err| Self::query_error("repositories", err))?;
+        url.query_pairs_mut()
+            .append_pair("role", "member")
+            .append_pair("pagelen", &PAGE_LENGTH.to_string());
+        client
+            .get_paged(url)
+            .await
+            .map_err(|err| Self::query_error("repositories", err))
+    }
+
+    /// Look up a repository by its full name (e.g., `workspace/repo`).
+    async fn query_repository(
+        client: &client::Bitbucket,
+        full_name: &str,
+    ) -> Result<types::Repository, ItemError> {
+        let url = client
+            .endpoint(&format!("repositories/{}", full_name))
+            .map_err(|err| Self::query_error(full_name, err))?;
+        client
+            .get(&url)
+            .await
+            .map_err(|err| Self::query_error(full_name, err))
+    }
+
+    /// Fetch issues and pull requests in a repository assigned to or created by the user or
+    /// awaiting their review.
+    async fn query_repository_items(
+        client: &client::Bitbucket,
+        repo: &types::Repository,
+        viewer: &str,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let mut items = Vec::new();
+        // Closed items are only fetched for a while to avoid fetching the entire history of
+        // the repository.
+        let recent = (Utc::now() - chrono::Duration::days(RECENTLY_CLOSED_DAYS))
+            .to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        if repo.has_issues {
+            let mut url = client
+                .endpoint(&format!("repositories/{}/issues", repo.full_name))
+                .map_err(|err| Self::query_error("issues", err))?;
+            url.query_pairs_mut()
+                .append_pair(
+                    "q",
+                    &format!(
+                        "(assignee.uuid=\"{viewer}\" OR reporter.uuid=\"{viewer}\") AND \
+                         (state=\"new\" OR state=\"open\" OR state=\"on hold\" OR \
+                         state=\"submitted\" OR updated_on>={recent})",
+                        viewer = viewer,
+                        recent = recent,
+                    ),
+                )
+                .append_pair("pagelen", &PAGE_LENGTH.to_string());
+            match client.get_paged(url).await {
+                Ok(issues) => {
+                    items.extend(
+                        issues
+                            .into_iter()
+                            .map(|issue| issue_item(repo, issue, viewer)),
+                    );
+                },
+                // The issue tracker may have been disabled since the repository was listed.
+                Err(err) if err.is_not_found() => (),
+                Err(err) => return Err(Self::query_error("issues", err)),
+            }
+        }
+
+        let mut url = client
+            .endpoint(&format!("repositories/{}/pullrequests", repo.full_name))
+            .map_err(|err| Self::query_error("pull requests", err))?;
+        url.query_pairs_mut()
+            .append_pair(
+                "q",
+                &format!(
+                    "(author.uuid=\"{viewer}\" OR reviewers.uuid=\"{viewer}\") AND \
+                     (state=\"OPEN\" OR updated_on>={recent})",
+                    viewer = viewer,
+                    recent = recent,
+                ),
+            )
+            // Only open pull requests are listed by default; the query limits the others.
+            .append_pair("state", "OPEN")
+            .append_pair("state", "MERGED")
+            .append_pair("state", "DECLINED")
+            .append_pair("state", "SUPERSEDED")
+            // Reviewers are not included in listings by default.
+            .append_pair("fields", "+values.reviewers")
+            .append_pair("pagelen", &PAGE_LENGTH.to_string());
+        let prs = client
+            .get_paged(url)
+            .await
+            .map_err(|err| Self::query_error("pull requests", err))?;
+        items.extend(
+            prs.into_iter()
+                .map(|pr| pull_request_item(repo, pr, viewer)),
+        );
+
+        items.retain(|item| matches_filters(item, filters));
+
+        Ok(items)
+    }
+
+    async fn query_repositories_items(
+        &self,
+        client: &client::Bitbucket,
+        repos: &[types::Repository],
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let viewer = self.viewer(client).await?;
+
+        // Repositories are paginated independently, so fetch a few of them at a time.
+        let queries = repos
+            .iter()
+            .map(|repo| Self::query_repository_items(client, repo, viewer, filters))
+            .collect::<Vec<_>>();
+        let items = stream::iter(queries)
+            .buffered(CONCURRENT_REPOSITORIES)
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(items.into_iter().flatten().collect())
+    }
+
+    async fn query_self(
+        &self,
+        client: &client::Bitbucket,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        // There is no way to search across repositories, so look through those the user is a
+        // member of.
+        let repos = Self::query_member_repositories(client).await?;
+        self.query_repositories_items(client, &repos, filters).await
+    }
+
+    async fn query_projects(
+        &self,
+        client: &client::Bitbucket,
+        projects: &[String],
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let repos = future::try_join_all(
+            projects
+                .iter()
+                .map(|project| Self::query_repository(client, project)),
+        )
+        .await?;
+        self.query_repositories_items(client, &repos, filters).await
+    }
+
+    async fn fetch_items_impl(&self, profile: &Profile) -> Result<Vec<SourceItem>, ItemError> {
+        let client = self.client()?;
+
+        let filters = &profile.filters;
+        match &profile.target {
+            QueryTarget::SelfUser => self.query_self(client, filters).await,
+            QueryTarget::Projects(projects) => self.query_projects(client, projects, filters).await,
+            QueryTarget::Mentioned => {
+                Err(ItemError::Unsupported {
+                    operation: "querying mentions on bitbucket",
+                })
+            },
+            QueryTarget::ReviewRequested => {
+                Err(ItemError::Unsupported {
+                    operation: "querying review requests on bitbucket",
+                })
+            },
+            QueryTarget::Participating => {
+                Err(ItemError::Unsupported {
+                    operation: "querying participation on bitbucket",
+                })
+            },
+            QueryTarget::Starred => {
+                Err(ItemError::Unsupported {
+                    operation: "querying starred repositories on bitbucket",
+                })
+            },
+            QueryTarget::HelpWanted(_) => {
+                Err(ItemError::Unsupported {
+                    operation: "querying items looking for contributors on bitbucket",
+                })
+            },
+            QueryTarget::Team(_) => {
+                Err(ItemError::Unsupported {
+                    operation: "querying team workloads on bitbucket",
+                })
+            },
+        }
+    }
+
+    async fn identity_impl(&self) -> Result<Identity, ItemError> {
+        let client = self.client()?;
+
+        let user = Self::query_user(client).await?;
+
+        Ok(Identity {
+            login: user.nickname.unwrap_or(user.display_name),
+            rate_limit_remaining: None,
+        })
+    }
+}
+
+impl ItemSource for BitbucketQuery {
+    fn fetch_items<'a>(&'a self, profile: &'a Profile) -> ItemFuture<'a, Vec<SourceItem>> {
+        Box::pin(self.fetch_items_impl(profile))
+    }
+
+    fn identity(&self) -> ItemFuture<'_, Identity> {
+        Box::pin(self.identity_impl())
+    }
+
+    fn requests_made(&self) -> usize {
+        self.budget.used()
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.budget.is_exhausted()
+    }
+}
@@ -0,0 +1,363 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{info, warn};
+use reqwest::header;
+use reqwest::{self, Client, Proxy, RequestBuilder, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use crate::account::bitbucket::types::Page;
+use crate::account::{self, ConnectContext, RequestBudget, RetryableError};
+use crate::config::BackoffPolicy;
+
+// How long to wait after hitting the rate limit without a `Retry-After` header.
+const RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
+// The cache namespace for Bitbucket responses.
+const CACHE_NAMESPACE: &str = "bitbucket";
+
+#[derive(Debug, Error)]
+pub enum BitbucketError {
+    #[error("url parse error: {}", source)]
+    UrlParse {
+        #[from]
+        source: url::ParseError,
+    },
+    #[error("failed to send request to {}: {}", endpoint, source)]
+    SendRequest {
+        endpoint: Url,
+        source: reqwest::Error,
+    },
+    #[error("failed to create the http client: {}", source)]
+    BuildClient { source: reqwest::Error },
+    #[error("bitbucket error ({}): {}", status, response)]
+    Bitbucket {
+        status: reqwest::StatusCode,
+        response: String,
+    },
+    #[error("bitbucket rejected the token ({}): {}", status, response)]
+    TokenRejected {
+        status: reqwest::StatusCode,
+        response: String,
+    },
+    #[error("deserialize error: {}", source)]
+    Deserialize {
+        #[from]
+        source: serde_json::Error,
+    },
+    #[error("bitbucket rate limit hit; retry after {:?}", retry_after)]
+    RateLimit { retry_after: Duration },
+    #[error("bitbucket service error: {}", status)]
+    BitbucketService { status: reqwest::StatusCode },
+    #[error("response read error: {}", source)]
+    ReadResponse { source: reqwest::Error },
+    #[error("failure even after exponential backoff")]
+    BitbucketBackoff {},
+    #[error("the request budget has been exhausted")]
+    BudgetExhausted {},
+}
+
+impl RetryableError for BitbucketError {
+    fn should_backoff(&self) -> bool {
+        matches!(
+            self,
+            BitbucketError::BitbucketService { .. } | BitbucketError::RateLimit { .. },
+        )
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        if let BitbucketError::RateLimit {
+            retry_after,
+        } = self
+        {
+            Some(*retry_after)
+        } else {
+            None
+        }
+    }
+
+    fn backoff_exhausted() -> Self {
+        BitbucketError::BitbucketBackoff {}
+    }
+}
+
+impl BitbucketError {
+    /// Whether the resource does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            BitbucketError::Bitbucket {
+                status: StatusCode::NOT_FOUND,
+                ..
+            },
+        )
+    }
+
+    pub fn build_client(source: reqwest::Error) -> Self {
+        BitbucketError::BuildClient {
+            source,
+        }
+    }
+
+    fn send_request(endpoint: Url, source: reqwest::Error) -> Self {
+        BitbucketError::SendRequest {
+            endpoint,
+            source,
+        }
+    }
+
+    fn bitbucket(status: reqwest::StatusCode, response: String) -> Self {
+        BitbucketError::Bitbucket {
+            status,
+            response,
+        }
+    }
+
+    fn token_rejected(status: reqwest::StatusCode, response: String) -> Self {
+        BitbucketError::TokenRejected {
+            status,
+            response,
+        }
+    }
+
+    fn rate_limit(retry_after: Duration) -> Self {
+        BitbucketError::RateLimit {
+            retry_after,
+        }
+    }
+
+    fn bitbucket_service(status: reqwest::StatusCode) -> Self {
+        BitbucketError::BitbucketService {
+            status,
+        }
+    }
+
+    fn read_response(source: reqwest::Error) -> Self {
+        BitbucketError::ReadResponse {
+            source,
+        }
+    }
+
+    fn budget_exhausted() -> Self {
+        BitbucketError::BudgetExhausted {}
+    }
+}
+
+pub type BitbucketResult<T> = Result<T, BitbucketError>;
+
+// The user agent for all requests.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), " v", env!("CARGO_PKG_VERSION"));
+
+/// A client for communicating with Bitbucket Cloud.
+pub struct Bitbucket {
+    /// The client used to communicate with Bitbucket.
+    client: Client,
+    /// The base URL of the REST API.
+    api_url: Url,
+
+    /// The tokens for the client.
+    tokens: Vec<String>,
+    /// The index of the token currently in use.
+    current_token: AtomicUsize,
+    /// The policy for retrying failed requests.
+    backoff: BackoffPolicy,
+    /// Facilities shared with other connections.
+    context: ConnectContext,
+    /// The budget for requests made by the client.
+    budget: Arc<RequestBudget>,
+}
+
+impl Bitbucket {
+    pub fn new(
+        api_url: &str,
+        tokens: Vec<String>,
+        backoff: BackoffPolicy,
+        budget: Arc<RequestBudget>,
+        context: ConnectContext,
+        timeout: Option<Duration>,
+        proxy: Option<&str>,
+    ) -> BitbucketResult<Self> {
+        // Make sure that joining paths onto the URL keeps its path.
+        let api_url = Url::parse(&format!("{}/", api_url.trim_end_matches('/')))?;
+        let mut builder = Client::builder();
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(Proxy::all(proxy).map_err(BitbucketError::build_client)?);
+        }
+        let client = builder.build().map_err(BitbucketError::build_client)?;
+
+        Ok(Bitbucket {
+            client,
+            api_url,
+            tokens,
+            current_token: AtomicUsize::new(0),
+            backoff,
+            budget,
+            context,
+        })
+    }
+
+    /// The URL for an API endpoint.
+    pub fn endpoint(&self, path: &str) -> BitbucketResult<Url> {
+        Ok(self.api_url.join(path)?)
+    }
+
+    /// The token currently in use.
+    fn token(&self) -> &str {
+        let index = self.current_token.load(Ordering::SeqCst);
+        &self.tokens[index % self.tokens.len()]
+    }
+
+    /// Switch to the next token.
+    fn rotate_token(&self) {
+        let index = self.current_token.fetch_add(1, Ordering::SeqCst) + 1;
+        info!(
+            target: "bitbucket",
+            "rotating to token {} of {}",
+            index % self.tokens.len() + 1,
+            self.tokens.len(),
+        );
+    }
+
+    /// Add authentication to a request.
+    ///
+    /// Tokens of the form `username:app-password` use basic authentication while others are
+    /// used as bearer tokens (e.g., repository or workspace access tokens).
+    fn authenticate(&self, request: RequestBuilder) -> RequestBuilder {
+        let token = self.token();
+        if let Some((username, password)) = token.split_once(':') {
+            request.basic_auth(username, Some(password))
+        } else {
+            request.bearer_auth(token)
+        }
+    }
+
+    /// Fetch a resource.
+    async fn get_impl<T>(&self, url: &Url) -> BitbucketResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let cache_key = (url.as_str(), self.token());
+        if let Some(cache) = self.context.cache.as_ref() {
+            if let Some(body) = cache.get(CACHE_NAMESPACE, &cache_key) {
+                match serde_json::from_str(&body) {
+                    Ok(data) => return Ok(data),
+                    Err(err) => {
+                        warn!(target: "bitbucket", "ignoring invalid cache entry: {}", err);
+                    },
+                }
+            }
+        }
+
+        if !self.budget.spend() {
+            return Err(BitbucketError::budget_exhausted());
+        }
+
+        info!(target: "bitbucket", "fetching {}", url);
+        let rsp = self
+            .authenticate(self.client.get(url.clone()))
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await
+            .map_err(|err| BitbucketError::send_request(url.clone(), err))?;
+        let status = rsp.status();
+        let retry_after = rsp
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_secs);
+        let body = rsp.text().await;
+        if let Some(capture) = self.context.capture.as_ref() {
+            let exchange = serde_json::json!({
+                "endpoint": url.as_str(),
+                "response": {
+                    "status": status.as_u16(),
+                    "body": body.as_ref().map(String::as_str).unwrap_or("<failed to read body>"),
+                },
+            });
+            capture.record("bitbucket", &exchange);
+        }
+
+        if status.is_server_error() {
+            warn!(
+                target: "bitbucket",
+                "service error {} for request; retrying with backoff",
+                status.as_u16(),
+            );
+            return Err(BitbucketError::bitbucket_service(status));
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = retry_after.unwrap_or(RATE_LIMIT_DELAY);
+            warn!(
+                target: "bitbucket",
+                "rate limit hit; retrying after {} seconds",
+                retry_after.as_secs(),
+            );
+            return Err(BitbucketError::rate_limit(retry_after));
+        }
+        if !status.is_success() {
+            let err = body
+                .unwrap_or_else(|text_err| format!("failed to extract error body: {:?}", text_err));
+            if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+                return Err(BitbucketError::token_rejected(status, err));
+            }
+            return Err(BitbucketError::bitbucket(status, err));
+        }
+
+        let body = body.map_err(BitbucketError::read_response)?;
+        let data = serde_json::from_str(&body)?;
+        if let Some(cache) = self.context.cache.as_ref() {
+            cache.put(CACHE_NAMESPACE, &cache_key, &body);
+        }
+        Ok(data)
+    }
+
+    /// Fetch a resource.
+    pub async fn get<T>(&self, url: &Url) -> BitbucketResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        // Give each token a chance before giving up.
+        let mut attempts = self.tokens.len();
+        loop {
+            let get = || self.get_impl(url);
+            match account::retry_with_backoff(&self.backoff, get).await {
+                Err(
+                    err @ BitbucketError::TokenRejected {
+                        ..
+                    },
+                ) if attempts > 1 => {
+                    warn!(target: "bitbucket", "token unusable: {}", err);
+                    attempts -= 1;
+                    self.rotate_token();
+                },
+                res => return res,
+            }
+        }
+    }
+
+    /// Fetch all pages of a paginated resource.
+    pub async fn get_paged<T>(&self, url: Url) -> BitbucketResult<Vec<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut values = Vec::new();
+        let mut next = Some(url);
+        while let Some(url) = next {
+            let page: Page<T> = self.get(&url).await?;
+            values.extend(page.values);
+            next = page.next.map(|next| Url::parse(&next)).transpose()?;
+        }
+        Ok(values)
+    }
+}
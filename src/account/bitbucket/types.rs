@@ -0,0 +1,106 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+/// A page of results.
+#[derive(Debug, Deserialize)]
+pub struct Page<T> {
+    pub values: Vec<T>,
+    /// The URL of the next page.
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub uuid: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub nickname: Option<String>,
+}
+
+/// A reference to a user from another object.
+#[derive(Debug, Deserialize)]
+pub struct UserRef {
+    pub uuid: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Link {
+    pub href: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Repository {
+    pub full_name: String,
+    pub is_private: bool,
+    #[serde(default)]
+    pub has_issues: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IssueLinks {
+    pub html: Link,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Content {
+    #[serde(default)]
+    pub raw: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Component {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Milestone {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Issue {
+    pub links: IssueLinks,
+    pub title: String,
+    pub content: Content,
+    pub state: String,
+    pub kind: String,
+    #[serde(default)]
+    pub priority: Option<String>,
+    #[serde(default)]
+    pub component: Option<Component>,
+    #[serde(default)]
+    pub milestone: Option<Milestone>,
+    #[serde(default)]
+    pub assignee: Option<UserRef>,
+    #[serde(default)]
+    pub reporter: Option<UserRef>,
+    pub updated_on: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PullRequestLinks {
+    pub html: Link,
+    #[serde(default)]
+    pub diff: Option<Link>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PullRequest {
+    pub links: PullRequestLinks,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub state: String,
+    pub author: UserRef,
+    /// Only included if requested using the `fields` parameter.
+    #[serde(default)]
+    pub reviewers: Vec<UserRef>,
+    pub updated_on: DateTime<Utc>,
+}
@@ -0,0 +1,63 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{debug, warn};
+
+use crate::redact;
+
+/// Records HTTP exchanges with services for debugging.
+///
+/// Each exchange is written as a JSON file into the capture directory. Any known secrets are
+/// redacted before writing.
+pub struct HttpCapture {
+    directory: PathBuf,
+    count: AtomicUsize,
+}
+
+impl HttpCapture {
+    /// Create a capture which writes into the given directory.
+    pub fn new(directory: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory,
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    /// Record an exchange.
+    ///
+    /// Failures are only logged since captures are a debugging aid.
+    pub fn record(&self, service: &str, exchange: &serde_json::Value) {
+        let contents = match serde_json::to_string_pretty(exchange) {
+            Ok(contents) => contents,
+            Err(err) => {
+                warn!("failed to serialize the {} exchange: {:?}", service, err);
+                return;
+            },
+        };
+        let contents = redact::redact(&contents);
+
+        let index = self.count.fetch_add(1, Ordering::SeqCst);
+        let path = self
+            .directory
+            .join(format!("{:04}-{}.json", index, service));
+        debug!("recording {} exchange to {}", service, path.display());
+        if let Err(err) = fs::write(&path, contents.as_bytes()) {
+            warn!(
+                "failed to record the {} exchange to {}: {:?}",
+                service,
+                path.display(),
+                err,
+            );
+        }
+    }
+}
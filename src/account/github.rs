@@ -4,71 +4,397 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures_util::future;
 use graphql_client::GraphQLQuery;
+use itertools::Itertools;
 use lazy_init::LazyTransform;
 use log::{error, warn};
 use once_cell::sync::OnceCell;
 
 use crate::account::prelude::*;
-use crate::todo::{Due, TodoKind, TodoStatus};
+use crate::account::{ConnectContext, RequestBudget};
+use crate::config::{Account, BackoffPolicy, HelpWanted};
+use crate::todo::{Attachment, Dependencies, Due, Estimate, TodoKind, TodoStatus};
 
 mod client;
 mod queries;
 
+// The API host for github.com.
+const GITHUB_API_HOST: &str = "api.github.com";
+
 struct ConnInfo {
-    host: String,
-    token: String,
+    endpoint: String,
+    tokens: Vec<String>,
+    backoff: BackoffPolicy,
+    context: ConnectContext,
+    budget: Arc<RequestBudget>,
+    timeout: Option<Duration>,
+    proxy: Option<String>,
 }
 
 pub struct GithubQuery {
     client: LazyTransform<ConnInfo, client::GithubResult<client::Github>>,
     init_error_cell: OnceCell<()>,
+    budget: Arc<RequestBudget>,
 }
 
-struct GithubItem {
-    due: Option<Due>,
-    summary: String,
-    description: String,
-    kind: TodoKind,
-    status: TodoStatus,
-    url: String,
+/// An item which may be updated.
+enum ItemNode {
+    Issue(String),
+    PullRequest(String),
 }
 
-macro_rules! impl_issue_filter {
-    ($type:path) => {
-        impl $type {
-            fn add_filter(&mut self, filter: &Filter) {
-                match filter {
-                    Filter::Label(label) => {
-                        self.labels.get_or_insert_with(Vec::new).push(label.into())
-                    },
+impl ItemNode {
+    fn id(&self) -> &str {
+        match self {
+            ItemNode::Issue(id) | ItemNode::PullRequest(id) => id,
+        }
+    }
+}
+
+// The number of items in each page of search results.
+const SEARCH_PAGE_SIZE: i64 = 100;
+// The maximum length of a search query.
+const SEARCH_QUERY_LENGTH: usize = 256;
+// The maximum number of results GitHub returns for a search.
+const SEARCH_RESULT_LIMIT: i64 = 1000;
+
+// URL prefixes used by GitHub for files uploaded into issue and pull request bodies.
+const ATTACHMENT_URL_PREFIXES: &[&str] = &[
+    "https://github.com/user-attachments/",
+    "https://user-images.githubusercontent.com/",
+    "https://private-user-images.githubusercontent.com/",
+];
+
+// The label prefix used to indicate an estimate for an item (e.g., `estimate:2h`).
+const ESTIMATE_LABEL_PREFIX: &str = "estimate:";
+
+/// Find an estimate from the labels on an item.
+fn label_estimate<'a, I>(labels: I) -> Option<Estimate>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    labels
+        .into_iter()
+        .filter_map(|label| label.strip_prefix(ESTIMATE_LABEL_PREFIX))
+        .find_map(|spec| Estimate::from_human(spec.trim()))
+}
+
+// Labels used to mark items as blocked or blocking.
+const BLOCKED_LABEL: &str = "blocked";
+const BLOCKING_LABEL: &str = "blocking";
+
+// Phrases in bodies which precede references to items blocking this one.
+const BLOCKED_BY_PHRASES: &[&str] = &["blocked by ", "depends on "];
+// Phrases in bodies which precede references to items blocked by this one.
+const BLOCKING_PHRASES: &[&str] = &["blocks ", "blocking "];
+
+/// Whether a body references another item right after one of the given phrases.
+fn references_after(body: &str, phrases: &[&str]) -> bool {
+    phrases.iter().any(|phrase| {
+        body.match_indices(phrase).any(|(idx, _)| {
+            let is_word = !body[..idx].ends_with(char::is_alphanumeric);
+            let reference = body[idx + phrase.len()..]
+                .split_whitespace()
+                .next()
+                .unwrap_or_default();
+            is_word && (reference.contains('#') || reference.starts_with("https://"))
+        })
+    })
+}
+
+/// Determine how an item depends on other items from its labels and body.
+///
+/// GitHub does not expose dependencies between issues, so the conventional `blocked` and
+/// `blocking` labels and phrases such as "blocked by #123" are used instead.
+fn item_dependencies(body: &str, labels: &[&str]) -> Dependencies {
+    let body = body.to_lowercase();
+    let has_label = |name: &str| labels.iter().any(|label| label.eq_ignore_ascii_case(name));
+
+    Dependencies {
+        blocked: has_label(BLOCKED_LABEL) || references_after(&body, BLOCKED_BY_PHRASES),
+        blocking: has_label(BLOCKING_LABEL) || references_after(&body, BLOCKING_PHRASES),
+    }
+}
+
+/// Find files uploaded to GitHub which are referenced from a body.
+fn body_attachments(body: &str) -> Vec<Attachment> {
+    body.split(|c: char| c.is_whitespace() || "()<>[]\"'".contains(c))
+        .filter(|word| {
+            ATTACHMENT_URL_PREFIXES
+                .iter()
+                .any(|prefix| word.starts_with(prefix))
+        })
+        .unique()
+        .map(Attachment::new)
+        .collect()
+}
+
+/// Whether a body mentions a user.
+fn mentions(body: &str, login: &str) -> bool {
+    let mention = format!("@{}", login);
+    body.match_indices(&mention).any(|(idx, _)| {
+        // Make sure that the mention is not a prefix of another user's name.
+        !body[idx + mention.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '-')
+    })
+}
+
+/// Add label and milestone filters to a search query.
+fn search_query(query: &str, filters: &[Filter]) -> String {
+    let mut search = query.to_string();
+    for filter in filters {
+        match filter {
+            Filter::Label(label) => search.push_str(&format!(" label:\"{}\"", label)),
+            Filter::NotLabel(label) => search.push_str(&format!(" -label:\"{}\"", label)),
+            Filter::Milestone(milestone) => {
+                search.push_str(&format!(" milestone:\"{}\"", milestone))
+            },
+        }
+    }
+    search
+}
+
+/// Search queries covering open items in a set of repositories.
+///
+/// Repositories are split across queries to keep each within the search query length limit.
+fn repository_queries(repos: &[String], filters: &[Filter]) -> Vec<String> {
+    let suffix = search_query("", filters);
+    let mut queries = Vec::new();
+    let mut query = String::new();
+    for repo in repos {
+        let term = format!(" repo:{}", repo);
+        if !query.is_empty() && query.len() + term.len() + suffix.len() > SEARCH_QUERY_LENGTH {
+            queries.push(format!("{}{}", query, suffix));
+            query.clear();
+        }
+        if query.is_empty() {
+            query.push_str("is:open");
+        }
+        query.push_str(&term);
+    }
+    if !query.is_empty() {
+        queries.push(format!("{}{}", query, suffix));
+    }
+    queries
+}
+
+/// Search queries for items looking for contributors.
+///
+/// Each topic and organization is searched separately.
+fn help_wanted_queries(help_wanted: &HelpWanted, filters: &[Filter]) -> Vec<String> {
+    let mut base = "is:issue is:open archived:false no:assignee sort:updated-desc".to_string();
+    if !help_wanted.labels.is_empty() {
+        // Comma-separated labels match items with any of them.
+        let labels = help_wanted
+            .labels
+            .iter()
+            .map(|label| format!("\"{}\"", label))
+            .join(",");
+        base.push_str(&format!(" label:{}", labels));
+    }
+
+    help_wanted
+        .topics
+        .iter()
+        .map(|topic| format!("topic:{}", topic))
+        .chain(help_wanted.orgs.iter().map(|org| format!("org:{}", org)))
+        .map(|scope| search_query(&format!("{} {}", base, scope), filters))
+        .collect()
+}
+
+/// Search queries for items assigned to members of a team.
+///
+/// Search qualifiers for assignees must all match, so each user is searched separately.
+fn team_queries(users: &[String], filters: &[Filter]) -> Vec<String> {
+    users
+        .iter()
+        .map(|user| {
+            search_query(
+                &format!("is:open archived:false assignee:{}", user),
+                filters,
+            )
+        })
+        .collect()
+}
+
+// The maximum number of nodes which may be looked up at once.
+const NODES_PAGE_SIZE: usize = 100;
+
+/// A query for extra fields of issues and pull requests.
+///
+/// Each field is selected under an alias (`f0`, `f1`, …) in the order of `fields`.
+fn extra_fields_query(fields: &[(String, Vec<&str>)]) -> String {
+    let selections = fields
+        .iter()
+        .enumerate()
+        .map(|(idx, (_, path))| {
+            let (last, parents) = path.split_last().expect("field paths should not be empty");
+            let selection = parents
+                .iter()
+                .rev()
+                .fold(last.to_string(), |inner, parent| {
+                    format!("{} {{ {} }}", parent, inner)
+                });
+            format!("f{}: {}", idx, selection)
+        })
+        .join(" ");
+
+    format!(
+        "query ExtraFields($ids: [ID!]!) {{ nodes(ids: $ids) {{ \
+         ... on Issue {{ id {selections} }} \
+         ... on PullRequest {{ id {selections} }} }} }}",
+        selections = selections,
+    )
+}
+
+/// The values at a path within a field value.
+///
+/// Lists contribute each of their values.
+fn field_values(value: &serde_json::Value, path: &[&str], values: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Null => (),
+        serde_json::Value::Array(elements) => {
+            for element in elements {
+                field_values(element, path, values);
+            }
+        },
+        value => {
+            if let Some((first, rest)) = path.split_first() {
+                if let Some(value) = value.get(first) {
+                    field_values(value, rest, values);
                 }
+            } else if let serde_json::Value::String(s) = value {
+                values.push(s.clone());
+            } else {
+                values.push(value.to_string());
             }
-        }
-    };
+        },
+    }
 }
 
-impl_issue_filter!(queries::viewer_issues::IssueFilters);
+/// Select a rotating subset of candidate items.
+///
+/// Candidates are ordered by URL and a different window of them is selected for each rotation
+/// period.
+fn rotating_selection(
+    mut items: Vec<SourceItem>,
+    limit: usize,
+    rotation: chrono::Duration,
+    now: DateTime<Utc>,
+) -> Vec<SourceItem> {
+    items.sort_by(|a, b| a.url.cmp(&b.url));
+    items.dedup_by(|a, b| a.url == b.url);
+    if items.len() <= limit {
+        return items;
+    }
+
+    let period = rotation.num_seconds().max(1);
+    let round = now.timestamp().div_euclid(period) as usize;
+    let start = round.wrapping_mul(limit) % items.len();
+    items.rotate_left(start);
+    items.truncate(limit);
+    items
+}
+
+/// The search query for pull requests awaiting the user's review.
+fn review_requested_query(team_reviews: bool) -> &'static str {
+    // `review-requested` includes requests made to teams the user is a member of while
+    // `user-review-requested` only includes requests made to the user directly.
+    if team_reviews {
+        "is:pr review-requested:@me"
+    } else {
+        "is:pr user-review-requested:@me"
+    }
+}
+
+/// The number of pages of search results after the first.
+fn remaining_pages(count: i64) -> i64 {
+    (count.min(SEARCH_RESULT_LIMIT) - 1).max(0) / SEARCH_PAGE_SIZE
+}
+
+/// Warn about searches with more results than GitHub returns.
+fn check_search_limit(search: &str, count: i64) {
+    if count > SEARCH_RESULT_LIMIT {
+        warn!(
+            "the search `{}` matches {} items, but only the first {} are returned; use filters \
+             to narrow it down",
+            search, count, SEARCH_RESULT_LIMIT,
+        );
+    }
+}
+
+/// Conversion of query results into items.
+///
+/// The login of the viewer is used to determine how the viewer is related to the item.
+trait IntoSourceItem {
+    fn into_item(self, viewer: &str) -> SourceItem;
+}
 
 macro_rules! impl_issue {
     ($type:path, $state:path) => {
-        impl From<$type> for GithubItem {
-            fn from(issue: $type) -> Self {
-                let due = issue.milestone.and_then(|m| m.due_on).map(Due::DateTime);
-                // TODO: Determine whether this is assigned or not.
-                let kind = TodoKind::Issue;
+        impl IntoSourceItem for $type {
+            fn into_item(self, viewer: &str) -> SourceItem {
+                let issue = self;
+                let due_dates = DueDates {
+                    milestone: issue
+                        .milestone
+                        .as_ref()
+                        .and_then(|m| m.due_on)
+                        .map(Due::DateTime),
+                    ..DueDates::default()
+                };
+                let labels = issue
+                    .labels
+                    .iter()
+                    .flat_map(|labels| labels.labels.iter().flatten())
+                    .flatten()
+                    .map(|label| label.name.as_str())
+                    .collect::<Vec<_>>();
+                let estimate = label_estimate(labels.iter().copied());
+                let dependencies = item_dependencies(&issue.body, &labels);
+                let is_assigned = issue
+                    .assignees
+                    .assignees
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .any(|assignee| assignee.login == viewer);
+                let is_author = issue
+                    .author
+                    .as_ref()
+                    .is_some_and(|author| author.login == viewer);
+                let assignees = issue
+                    .assignees
+                    .assignees
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .map(|assignee| assignee.login.clone())
+                    .collect::<Vec<_>>();
+                let has_assignees = !assignees.is_empty();
+                let kind = if is_assigned {
+                    TodoKind::AssignedIssue
+                } else if mentions(&issue.body, viewer) {
+                    TodoKind::Mention
+                } else {
+                    TodoKind::Issue
+                };
+                let delegated = matches!(issue.state, <$state>::OPEN)
+                    && is_author
+                    && has_assignees
+                    && !is_assigned;
                 let status = match issue.state {
                     <$state>::CLOSED => TodoStatus::Completed,
                     <$state>::OPEN => {
-                        if issue
-                            .assignees
-                            .assignees
-                            .map(|v| v.is_empty())
-                            .unwrap_or(true)
-                        {
-                            TodoStatus::NeedsAction
-                        } else {
+                        if has_assignees {
                             TodoStatus::InProcess
+                        } else {
+                            TodoStatus::NeedsAction
                         }
                     },
                     state => {
@@ -77,13 +403,29 @@ macro_rules! impl_issue {
                     },
                 };
 
-                Self {
-                    due,
+                let attachments = body_attachments(&issue.body);
+
+                SourceItem {
+                    due: None,
+                    due_dates,
+                    estimate,
                     summary: issue.title,
                     description: issue.body,
                     kind,
                     status,
                     url: issue.url,
+                    attachments,
+                    dependencies,
+                    delegated,
+                    labels: labels.into_iter().map(Into::into).collect(),
+                    confidential: issue.repository.is_private,
+                    private: false,
+                    source_id: Some(issue.id),
+                    milestone: issue.milestone.map(|m| m.title),
+                    priority: None,
+                    assignees,
+                    extra_properties: BTreeMap::new(),
+                    updated_at: Some(issue.updated_at),
                 }
             }
         }
@@ -91,25 +433,98 @@ macro_rules! impl_issue {
 }
 
 impl_issue!(
-    queries::viewer_issues::IssueInfo,
-    queries::viewer_issues::IssueState
+    queries::viewer_items::IssueInfo,
+    queries::viewer_items::IssueState
+);
+impl_issue!(
+    queries::viewer_search::IssueInfo,
+    queries::viewer_search::IssueState
 );
 
 macro_rules! impl_pull_request {
-    ($type:path, $state:path) => {
-        impl From<$type> for GithubItem {
-            fn from(pr: $type) -> Self {
-                let due = pr.milestone.and_then(|m| m.due_on).map(Due::DateTime);
-                // TODO: Determine whether this is assigned or not.
-                let kind = TodoKind::PullRequest;
+    ($type:path, $state:path, $reviewer:path) => {
+        impl IntoSourceItem for $type {
+            fn into_item(self, viewer: &str) -> SourceItem {
+                type Reviewer = $reviewer;
+
+                let pr = self;
+                let due_dates = DueDates {
+                    milestone: pr
+                        .milestone
+                        .as_ref()
+                        .and_then(|m| m.due_on)
+                        .map(Due::DateTime),
+                    ..DueDates::default()
+                };
+                let labels = pr
+                    .labels
+                    .iter()
+                    .flat_map(|labels| labels.labels.iter().flatten())
+                    .flatten()
+                    .map(|label| label.name.as_str())
+                    .collect::<Vec<_>>();
+                let estimate = label_estimate(labels.iter().copied());
+                let dependencies = item_dependencies(&pr.body, &labels);
+                let is_review_requested = pr
+                    .review_requests
+                    .iter()
+                    .flat_map(|requests| requests.reviewers.iter().flatten())
+                    .flatten()
+                    .filter_map(|request| request.requested_reviewer.as_ref())
+                    .any(|reviewer| {
+                        match reviewer {
+                            Reviewer::User(user) => user.login == viewer,
+                            Reviewer::Mannequin(mannequin) => mannequin.login == viewer,
+                            Reviewer::Team => false,
+                        }
+                    });
+                let is_assigned = pr
+                    .assignees
+                    .assignees
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .any(|assignee| assignee.login == viewer);
+                let is_author = pr
+                    .author
+                    .as_ref()
+                    .is_some_and(|author| author.login == viewer);
+                let assignees = pr
+                    .assignees
+                    .assignees
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .map(|assignee| assignee.login.clone())
+                    .collect::<Vec<_>>();
+                let has_assignees = !assignees.is_empty();
+                // Reviews requested from others are also work handed off by the author.
+                let has_other_reviewers = pr
+                    .review_requests
+                    .iter()
+                    .flat_map(|requests| requests.reviewers.iter().flatten())
+                    .flatten()
+                    .any(|request| request.requested_reviewer.is_some());
+                let kind = if is_review_requested {
+                    TodoKind::ReviewRequest
+                } else if is_assigned {
+                    TodoKind::AssignedPullRequest
+                } else {
+                    TodoKind::PullRequest
+                };
+                let delegated = matches!(pr.state, <$state>::OPEN)
+                    && is_author
+                    && !is_assigned
+                    && !is_review_requested
+                    && (has_assignees || has_other_reviewers);
                 let status = match pr.state {
                     <$state>::CLOSED => TodoStatus::Cancelled,
                     <$state>::MERGED => TodoStatus::Completed,
                     <$state>::OPEN => {
-                        if pr.assignees.assignees.map(|v| v.is_empty()).unwrap_or(true) {
-                            TodoStatus::NeedsAction
-                        } else {
+                        if has_assignees {
                             TodoStatus::InProcess
+                        } else {
+                            TodoStatus::NeedsAction
                         }
                     },
                     state => {
@@ -118,13 +533,31 @@ macro_rules! impl_pull_request {
                     },
                 };
 
-                Self {
-                    due,
+                let mut attachments = body_attachments(&pr.body);
+                attachments
+                    .push(Attachment::new(format!("{}.patch", pr.url)).with_format("text/x-patch"));
+
+                SourceItem {
+                    due: None,
+                    due_dates,
+                    estimate,
                     summary: pr.title,
                     description: pr.body,
                     kind,
                     status,
                     url: pr.url,
+                    attachments,
+                    dependencies,
+                    delegated,
+                    labels: labels.into_iter().map(Into::into).collect(),
+                    confidential: pr.repository.is_private,
+                    private: false,
+                    source_id: Some(pr.id),
+                    milestone: pr.milestone.map(|m| m.title),
+                    priority: None,
+                    assignees,
+                    extra_properties: BTreeMap::new(),
+                    updated_at: Some(pr.updated_at),
                 }
             }
         }
@@ -132,18 +565,89 @@ macro_rules! impl_pull_request {
 }
 
 impl_pull_request!(
-    queries::viewer_pull_requests::PullRequestInfo,
-    queries::viewer_pull_requests::PullRequestState
+    queries::viewer_items::PullRequestInfo,
+    queries::viewer_items::PullRequestState,
+    queries::viewer_items::PullRequestInfoReviewRequestsReviewersRequestedReviewer
+);
+impl_pull_request!(
+    queries::viewer_search::PullRequestInfo,
+    queries::viewer_search::PullRequestState,
+    queries::viewer_search::PullRequestInfoReviewRequestsReviewersRequestedReviewer
 );
 
 impl GithubQuery {
-    pub fn new(host: Option<String>, token: String) -> Self {
+    pub fn new(account: Account, tokens: Vec<String>, context: ConnectContext) -> Self {
+        let budget = Arc::new(RequestBudget::new(account.max_requests_per_run));
+        let host = account.hostname.unwrap_or_else(|| GITHUB_API_HOST.into());
+        // GitHub Enterprise Server instances serve the API under `/api`.
+        let path = account.api_path.unwrap_or_else(|| {
+            if host == GITHUB_API_HOST {
+                "graphql".into()
+            } else {
+                "api/graphql".into()
+            }
+        });
+        let scheme = if account.insecure_http {
+            warn!("using plain HTTP to communicate with {}", host);
+            "http"
+        } else {
+            "https"
+        };
+        let endpoint = format!("{}://{}/{}", scheme, host, path.trim_start_matches('/'),);
+
         GithubQuery {
             client: LazyTransform::new(ConnInfo {
-                host: host.unwrap_or_else(|| "api.github.com".into()),
-                token,
+                endpoint,
+                tokens,
+                backoff: account.backoff,
+                context,
+                budget: budget.clone(),
+                timeout: account.timeout.and_then(|timeout| timeout.0.to_std().ok()),
+                proxy: account.proxy,
             }),
             init_error_cell: OnceCell::new(),
+            budget,
+        }
+    }
+
+    /// The client for the instance.
+    fn client(&self) -> Result<&client::Github, ItemError> {
+        self.client
+            .get_or_create(|info| {
+                client::Github::new(
+                    &info.endpoint,
+                    info.tokens,
+                    info.backoff,
+                    info.budget,
+                    info.context,
+                    info.timeout,
+                    info.proxy.as_deref(),
+                )
+            })
+            .as_ref()
+            .map_err(|err| {
+                self.init_error_cell.get_or_init(|| {
+                    error!("failed to connect to github instance: {:?}", err);
+                });
+                ItemError::ServiceError {
+                    service: "github",
+                }
+            })
+    }
+
+    /// Convert an error from sending a query into an item error.
+    fn query_error(name: &str, err: client::GithubError) -> ItemError {
+        if let client::GithubError::BudgetExhausted {} = err {
+            return ItemError::BudgetExhausted {
+                service: "github",
+            };
+        }
+
+        error!("failed to send {} query: {:?}", name, err);
+        let message = format!("failed to send {} query: {}", name, err);
+        ItemError::QueryError {
+            service: "github",
+            message,
         }
     }
 
@@ -157,54 +661,165 @@ impl GithubQuery {
         }
     }
 
-    fn query_user(
+    /// Fetch items related to the user.
+    ///
+    /// Assigned, authored, mentioned, and review-requested items are fetched in a single
+    /// query. Connections with more than one page of results are continued separately.
+    async fn query_user(
         client: &client::Github,
         filters: &[Filter],
-    ) -> Result<Vec<GithubItem>, ItemError> {
-        let mut issue_filters = queries::viewer_issues::IssueFilters {
-            assignee: None,
-            created_by: None,
-            labels: None,
-            mentioned: None,
-            milestone: None,
-            since: None,
-            states: None,
-            viewer_subscribed: None,
+        team_reviews: bool,
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        use queries::viewer_items::SearchResultsItems as SearchItem;
+
+        let input = queries::viewer_items::Variables {
+            assigned: search_query("assignee:@me", filters),
+            authored: search_query("author:@me", filters),
+            mentioned: search_query("mentions:@me", filters),
+            review_requested: search_query(review_requested_query(team_reviews), filters),
         };
-        for filter in filters {
-            issue_filters.add_filter(filter);
+        let query = queries::ViewerItems::build_query(input.clone());
+        let rsp = client
+            .send::<queries::ViewerItems>(&query)
+            .await
+            .map_err(|err| Self::query_error("viewer items", err))?;
+
+        Self::check_rate_limits(
+            &rsp.rate_limit_info.rate_limit,
+            queries::ViewerItems::name(),
+        );
+
+        // Earlier connections take precedence for items which appear in more than one.
+        let connections = [
+            (
+                input.review_requested,
+                rsp.review_requested,
+                Some(TodoKind::ReviewRequest),
+            ),
+            (input.assigned, rsp.assigned, None),
+            (input.mentioned, rsp.mentioned, Some(TodoKind::Mention)),
+            (input.authored, rsp.authored, None),
+        ];
+        for (search, results, _) in &connections {
+            check_search_limit(search, results.issue_count);
         }
+        let pages = connections
+            .iter()
+            .map(|(_, results, _)| remaining_pages(results.issue_count))
+            .sum();
+        Self::check_search_cost(&rsp.rate_limit_info.rate_limit, pages)?;
 
-        let mut input = queries::viewer_issues::Variables {
-            filter_by: issue_filters,
-            cursor: None,
+        let viewer = rsp.viewer.login;
+        let mut seen = HashSet::new();
+        let mut items = Vec::new();
+        for (search, results, kind) in connections {
+            let mut connection_items = results
+                .items
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|result| {
+                    match result {
+                        SearchItem::Issue(issue) => Some(issue.into_item(&viewer)),
+                        SearchItem::PullRequest(pr) => Some(pr.into_item(&viewer)),
+                        _ => None,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let page_info = results.page_info;
+            if page_info.has_next_page {
+                assert!(
+                    page_info.end_cursor.is_some(),
+                    "GitHub lied to us and said there is another page, but didn't give us an end \
+                     cursor. Bailing to avoid an infinite loop.",
+                );
+                connection_items.extend(
+                    Self::query_search_pages(client, search, page_info.end_cursor, None).await?,
+                );
+            }
+
+            for mut item in connection_items {
+                if !seen.insert(item.url.clone()) {
+                    continue;
+                }
+                if let Some(kind) = kind {
+                    item.kind = kind;
+                }
+                items.push(item);
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Search for issues and pull requests.
+    ///
+    /// The `query` uses GitHub's search syntax. Filters are added to the query.
+    async fn query_search(
+        client: &client::Github,
+        query: &str,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        Self::query_search_pages(client, search_query(query, filters), None, None).await
+    }
+
+    /// Fetch the pages of a search starting after the given cursor.
+    ///
+    /// At most `max_pages` pages are fetched if given.
+    async fn query_search_pages(
+        client: &client::Github,
+        search: String,
+        cursor: Option<String>,
+        max_pages: Option<usize>,
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let mut input = queries::viewer_search::Variables {
+            query: search,
+            cursor,
         };
 
         let mut items = Vec::new();
+        let mut pages = 0;
 
-        // Query for issue information.
         loop {
-            let query = queries::ViewerIssues::build_query(input.clone());
+            pages += 1;
+            let query = queries::ViewerSearch::build_query(input.clone());
             let rsp = client
-                .send::<queries::ViewerIssues>(&query)
-                .map_err(|err| {
-                    error!("failed to send viewer issue query: {:?}", err);
-                    let message = format!("failed to send viewer issue query: {}", err);
-                    ItemError::QueryError {
-                        service: "github",
-                        message,
-                    }
-                })?;
+                .send::<queries::ViewerSearch>(&query)
+                .await
+                .map_err(|err| Self::query_error("viewer search", err))?;
 
             Self::check_rate_limits(
                 &rsp.rate_limit_info.rate_limit,
-                queries::ViewerIssues::name(),
+                queries::ViewerSearch::name(),
             );
-            let (issues, page_info) = (rsp.viewer.issues.items, rsp.viewer.issues.page_info);
-            if let Some(issues) = issues {
-                items.extend(issues.into_iter().flatten().map(|issue| issue.into()));
+            // Use the first page to estimate the cost of the rest of the search and avoid
+            // spending the rate limit on a search which cannot complete.
+            if input.cursor.is_none() && max_pages.is_none() {
+                check_search_limit(&input.query, rsp.search.issue_count);
+                Self::check_search_cost(
+                    &rsp.rate_limit_info.rate_limit,
+                    remaining_pages(rsp.search.issue_count),
+                )?;
+            }
+
+            let viewer = rsp.viewer.login;
+            let (results, page_info) = (rsp.search.items, rsp.search.page_info);
+            if let Some(results) = results {
+                items.extend(results.into_iter().flatten().filter_map(|result| {
+                    use queries::viewer_search::ViewerSearchSearchItems as SearchItem;
+
+                    match result {
+                        SearchItem::Issue(issue) => Some(issue.into_item(&viewer)),
+                        SearchItem::PullRequest(pr) => Some(pr.into_item(&viewer)),
+                        _ => None,
+                    }
+                }));
             }
 
+            if max_pages.is_some_and(|max_pages| pages >= max_pages) {
+                break;
+            }
             if page_info.has_next_page {
                 assert!(
                     page_info.end_cursor.is_some(),
@@ -217,47 +832,126 @@ impl GithubQuery {
             }
         }
 
-        let mut input = queries::viewer_pull_requests::Variables {
-            labels: None,
-            cursor: None,
+        Ok(items)
+    }
+
+    /// Look up the item at a URL and the ID of the viewer.
+    async fn query_item_node(
+        client: &client::Github,
+        url: &str,
+    ) -> Result<(ItemNode, String), ItemError> {
+        use queries::item_node::ItemNodeResource as Resource;
+
+        let query = queries::ItemNode::build_query(queries::item_node::Variables {
+            url: url.into(),
+        });
+        let rsp = client
+            .send::<queries::ItemNode>(&query)
+            .await
+            .map_err(|err| Self::query_error("item node", err))?;
+        Self::check_rate_limits(&rsp.rate_limit_info.rate_limit, queries::ItemNode::name());
+
+        let node = match rsp.resource {
+            Some(Resource::Issue(issue)) => ItemNode::Issue(issue.id),
+            Some(Resource::PullRequest(pr)) => ItemNode::PullRequest(pr.id),
+            _ => {
+                return Err(ItemError::QueryError {
+                    service: "github",
+                    message: format!("no issue or pull request at {}", url),
+                });
+            },
         };
-        for filter in filters {
-            match filter {
-                Filter::Label(label) => {
-                    input
-                        .labels
-                        .get_or_insert_with(Vec::new)
-                        .push(label.clone())
-                },
-            }
+
+        Ok((node, rsp.viewer.id))
+    }
+
+    /// Check that the rate limit allows for fetching the rest of a search.
+    ///
+    /// Searches which cannot complete are deferred to a later run rather than fetching fewer
+    /// pages: partial results would look like the skipped items were no longer returned (and
+    /// close them for profiles using `close_missing`).
+    fn check_search_cost<R>(rate_limit: &Option<R>, pages: i64) -> Result<(), ItemError>
+    where
+        R: Into<queries::RateLimitInfo> + Clone,
+    {
+        let rate_limit = if let Some(rate_limit) = rate_limit {
+            rate_limit.clone().into()
+        } else {
+            return Ok(());
+        };
+
+        let needed = pages * rate_limit.cost;
+        if needed > rate_limit.remaining {
+            return Err(ItemError::InsufficientRateLimit {
+                service: "github",
+                needed,
+                remaining: rate_limit.remaining,
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn query_mentioned(
+        client: &client::Github,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let mut items = Self::query_search(client, "mentions:@me -assignee:@me", filters).await?;
+        for item in &mut items {
+            item.kind = TodoKind::Mention;
+        }
+
+        Ok(items)
+    }
+
+    async fn query_review_requested(
+        client: &client::Github,
+        filters: &[Filter],
+        team_reviews: bool,
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let query = review_requested_query(team_reviews);
+        let mut items = Self::query_search(client, query, filters).await?;
+        for item in &mut items {
+            item.kind = TodoKind::ReviewRequest;
         }
 
-        // Query for pull requests information.
+        Ok(items)
+    }
+
+    async fn query_participating(
+        client: &client::Github,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        // `involves` covers commenters and mentions in addition to authors and assignees.
+        Self::query_search(client, "involves:@me -author:@me -assignee:@me", filters).await
+    }
+
+    /// The repositories the viewer has starred.
+    async fn query_starred_repositories(client: &client::Github) -> Result<Vec<String>, ItemError> {
+        let mut input = queries::viewer_starred::Variables {
+            cursor: None,
+        };
+
+        let mut repos = Vec::new();
+
         loop {
-            let query = queries::ViewerPullRequests::build_query(input.clone());
+            let query = queries::ViewerStarred::build_query(input.clone());
             let rsp = client
-                .send::<queries::ViewerPullRequests>(&query)
-                .map_err(|err| {
-                    error!("failed to send viewer pull request query: {:?}", err);
-                    let message = format!("failed to send viewer pull request query: {}", err);
-                    ItemError::QueryError {
-                        service: "github",
-                        message,
-                    }
-                })?;
+                .send::<queries::ViewerStarred>(&query)
+                .await
+                .map_err(|err| Self::query_error("viewer starred", err))?;
 
             Self::check_rate_limits(
                 &rsp.rate_limit_info.rate_limit,
-                queries::ViewerIssues::name(),
+                queries::ViewerStarred::name(),
             );
-            let (prs, page_info) = (
-                rsp.viewer.pull_requests.items,
-                rsp.viewer.pull_requests.page_info,
-            );
-            if let Some(prs) = prs {
-                items.extend(prs.into_iter().flatten().map(|pr| pr.into()));
+
+            let starred = rsp.viewer.starred_repositories;
+            if let Some(nodes) = starred.nodes {
+                repos.extend(nodes.into_iter().flatten().map(|repo| repo.name_with_owner));
             }
 
+            let page_info = starred.page_info;
             if page_info.has_next_page {
                 assert!(
                     page_info.end_cursor.is_some(),
@@ -270,73 +964,328 @@ impl GithubQuery {
             }
         }
 
+        Ok(repos)
+    }
+
+    async fn query_starred(
+        client: &client::Github,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let repos = Self::query_starred_repositories(client).await?;
+        if filters.is_empty() {
+            warn!(
+                "fetching all open items in {} starred repositories; consider adding filters",
+                repos.len(),
+            );
+        }
+
+        // Each batch of repositories is searched concurrently.
+        let searches = repository_queries(&repos, filters)
+            .into_iter()
+            .map(|query| Self::query_search_pages(client, query, None, None));
+        let items = future::try_join_all(searches).await?;
+
+        Ok(items.into_iter().flatten().collect())
+    }
+
+    /// Fetch a rotating selection of items looking for contributors.
+    async fn query_help_wanted(
+        client: &client::Github,
+        help_wanted: &HelpWanted,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let queries = help_wanted_queries(help_wanted, filters);
+        if queries.is_empty() {
+            return Err(ItemError::QueryError {
+                service: "github",
+                message: "no topics or organizations to search for items looking for \
+                          contributors"
+                    .into(),
+            });
+        }
+
+        // Only the most recently updated candidates are considered to avoid paging through
+        // every item looking for contributors.
+        let searches = queries
+            .into_iter()
+            .map(|query| Self::query_search_pages(client, query, None, Some(1)));
+        let candidates = future::try_join_all(searches).await?;
+
+        let mut items = rotating_selection(
+            candidates.into_iter().flatten().collect(),
+            help_wanted.limit,
+            help_wanted.rotation.0,
+            Utc::now(),
+        );
+        for item in &mut items {
+            item.priority = Some(help_wanted.priority);
+        }
+
         Ok(items)
     }
 
-    fn query_projects(
+    /// Fetch the items assigned to members of a team.
+    async fn query_team(
+        client: &client::Github,
+        users: &[String],
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let searches = team_queries(users, filters)
+            .into_iter()
+            .map(|query| Self::query_search_pages(client, query, None, None));
+        let mut items = future::try_join_all(searches)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        // Items assigned to more than one member of the team are found more than once.
+        items.sort_by(|a, b| a.url.cmp(&b.url));
+        items.dedup_by(|a, b| a.url == b.url);
+
+        Ok(items)
+    }
+
+    /// Fetch extra fields for items and record them as properties.
+    async fn query_extra_fields(
+        client: &client::Github,
+        extra_fields: &BTreeMap<String, String>,
+        items: &mut [SourceItem],
+    ) -> Result<(), ItemError> {
+        // Names and paths are validated when the configuration is loaded.
+        let fields = extra_fields
+            .iter()
+            .map(|(name, path)| (name.to_uppercase(), path.split('.').collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        let query = extra_fields_query(&fields);
+
+        let ids = items
+            .iter()
+            .filter_map(|item| item.source_id.as_deref())
+            .collect::<Vec<_>>();
+        let lookups = ids.chunks(NODES_PAGE_SIZE).map(|ids| {
+            let variables = serde_json::json!({ "ids": ids });
+            let query = &query;
+            async move {
+                client
+                    .send_dynamic::<_, serde_json::Value>("ExtraFields", query, &variables)
+                    .await
+                    .map_err(|err| Self::query_error("extra fields", err))
+            }
+        });
+        let rsps = future::try_join_all(lookups).await?;
+
+        let mut properties = rsps
+            .iter()
+            .filter_map(|rsp| rsp.get("nodes").and_then(|nodes| nodes.as_array()))
+            .flatten()
+            .filter_map(|node| {
+                let id = node.get("id")?.as_str()?;
+                let properties = fields
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, (name, path))| {
+                        let mut values = Vec::new();
+                        field_values(node.get(format!("f{}", idx))?, &path[1..], &mut values);
+                        if values.is_empty() {
+                            None
+                        } else {
+                            Some((name.clone(), values.join(",")))
+                        }
+                    })
+                    .collect::<BTreeMap<_, _>>();
+                Some((id.to_string(), properties))
+            })
+            .collect::<HashMap<_, _>>();
+        for item in items {
+            if let Some(extra) = item
+                .source_id
+                .as_ref()
+                .and_then(|source_id| properties.remove(source_id))
+            {
+                item.extra_properties = extra;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetch open items in repositories (given as `owner/repo`).
+    async fn query_projects(
         client: &client::Github,
         projects: &[String],
         filters: &[Filter],
-    ) -> Result<Vec<GithubItem>, ItemError> {
-        unimplemented!()
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        // Each batch of repositories is searched concurrently.
+        let searches = repository_queries(projects, filters)
+            .into_iter()
+            .map(|query| Self::query_search_pages(client, query, None, None));
+        let items = future::try_join_all(searches).await?;
+
+        Ok(items.into_iter().flatten().collect())
     }
 }
 
-impl ItemSource for GithubQuery {
-    fn fetch_items(
+impl GithubQuery {
+    async fn fetch_items_impl(&self, profile: &Profile) -> Result<Vec<SourceItem>, ItemError> {
+        let client = self.client()?;
+
+        let filters = &profile.filters;
+        let mut items = match &profile.target {
+            QueryTarget::SelfUser => Self::query_user(client, filters, profile.team_reviews).await,
+            QueryTarget::Projects(projects) => {
+                Self::query_projects(client, projects, filters).await
+            },
+            QueryTarget::Mentioned => Self::query_mentioned(client, filters).await,
+            QueryTarget::ReviewRequested => {
+                Self::query_review_requested(client, filters, profile.team_reviews).await
+            },
+            QueryTarget::Participating => Self::query_participating(client, filters).await,
+            QueryTarget::Starred => Self::query_starred(client, filters).await,
+            QueryTarget::HelpWanted(help_wanted) => {
+                Self::query_help_wanted(client, help_wanted, filters).await
+            },
+            QueryTarget::Team(users) => Self::query_team(client, users, filters).await,
+        }?;
+
+        if !profile.extra_fields.is_empty() {
+            Self::query_extra_fields(client, &profile.extra_fields, &mut items).await?;
+        }
+        if let Some(max_size) = profile.inline_attachment_size {
+            Self::embed_attachments(client, &mut items, max_size).await;
+        }
+
+        Ok(items)
+    }
+
+    /// Embed the contents of attachments which are no larger than `max_size` bytes.
+    ///
+    /// Attachments which cannot be fetched are only logged; they are still recorded by URL.
+    async fn embed_attachments(client: &client::Github, items: &mut [SourceItem], max_size: usize) {
+        let attachments = items
+            .iter_mut()
+            .flat_map(|item| item.attachments.iter_mut());
+        for attachment in attachments {
+            match client.fetch_file(&attachment.url, max_size).await {
+                Ok(content) => attachment.content = content,
+                Err(client::GithubError::BudgetExhausted {}) => {
+                    warn!("request budget exhausted; not embedding the remaining attachments");
+                    break;
+                },
+                Err(err) => warn!("failed to embed attachment {}: {}", attachment.url, err),
+            }
+        }
+    }
+
+    async fn identity_impl(&self) -> Result<Identity, ItemError> {
+        let client = self.client()?;
+
+        let query = queries::Viewer::build_query(queries::viewer::Variables {});
+        let rsp = client
+            .send::<queries::Viewer>(&query)
+            .await
+            .map_err(|err| Self::query_error("viewer", err))?;
+
+        let rate_limit = rsp
+            .rate_limit_info
+            .rate_limit
+            .map(queries::RateLimitInfo::from);
+        if let Some(info) = rate_limit.as_ref() {
+            info.inspect(queries::Viewer::name());
+        }
+
+        Ok(Identity {
+            login: rsp.viewer.login,
+            rate_limit_remaining: rate_limit.map(|info| info.remaining),
+        })
+    }
+
+    async fn write_back_impl(
         &self,
-        target: &QueryTarget,
-        filters: &[Filter],
-        existing_items: &mut ItemLookup,
-    ) -> Result<Vec<TodoItem>, ItemError> {
-        let client = self
-            .client
-            .get_or_create(|info| client::Github::new(&info.host, &info.token))
-            .as_ref()
-            .map_err(|err| {
-                self.init_error_cell.get_or_init(|| {
-                    error!("failed to connect to github instance: {:?}", err);
+        url: &str,
+        action: WriteBack,
+        comment: &str,
+    ) -> Result<(), ItemError> {
+        let client = self.client()?;
+
+        if action == WriteBack::None {
+            return Ok(());
+        }
+
+        let (node, viewer) = Self::query_item_node(client, url).await?;
+        match action {
+            WriteBack::None => (),
+            WriteBack::Close => {
+                // Pull requests are merged or closed by their authors, not reviewers.
+                let id = if let ItemNode::Issue(id) = node {
+                    id
+                } else {
+                    return Err(ItemError::Unsupported {
+                        operation: "closing pull requests",
+                    });
+                };
+                let query = queries::CloseIssue::build_query(queries::close_issue::Variables {
+                    id,
                 });
-                ItemError::ServiceError {
-                    service: "github",
-                }
-            })?;
+                client
+                    .send_mutation::<queries::CloseIssue>(&query)
+                    .await
+                    .map_err(|err| Self::query_error("close issue", err))?;
+            },
+            WriteBack::Comment => {
+                let query = queries::AddComment::build_query(queries::add_comment::Variables {
+                    id: node.id().into(),
+                    body: comment.into(),
+                });
+                client
+                    .send_mutation::<queries::AddComment>(&query)
+                    .await
+                    .map_err(|err| Self::query_error("add comment", err))?;
+            },
+            WriteBack::Unassign => {
+                let query =
+                    queries::RemoveAssignee::build_query(queries::remove_assignee::Variables {
+                        id: node.id().into(),
+                        assignee: viewer,
+                    });
+                client
+                    .send_mutation::<queries::RemoveAssignee>(&query)
+                    .await
+                    .map_err(|err| Self::query_error("remove assignee", err))?;
+            },
+        }
 
-        let results = match target {
-            QueryTarget::SelfUser => Self::query_user(client, filters),
-            QueryTarget::Projects(projects) => Self::query_projects(client, projects, filters),
-        };
+        Ok(())
+    }
+}
 
-        Ok(results?
-            .into_iter()
-            .filter_map(|result| {
-                if let Some(item) = existing_items.get_mut(&result.url) {
-                    if let Some(due) = result.due {
-                        item.set_due(due);
-                    }
-                    item.set_status(result.status);
-                    item.set_summary(result.summary);
-                    item.set_description(result.description);
+impl ItemSource for GithubQuery {
+    fn fetch_items<'a>(&'a self, profile: &'a Profile) -> ItemFuture<'a, Vec<SourceItem>> {
+        Box::pin(self.fetch_items_impl(profile))
+    }
 
-                    None
-                } else {
-                    let mut item = TodoItem::builder();
+    fn identity(&self) -> ItemFuture<'_, Identity> {
+        Box::pin(self.identity_impl())
+    }
 
-                    item.kind(result.kind)
-                        .status(result.status)
-                        .url(result.url.clone())
-                        .summary(result.summary)
-                        .description(result.description);
+    fn requests_made(&self) -> usize {
+        self.budget.used()
+    }
 
-                    if let Some(due) = result.due {
-                        item.due(due);
-                    }
+    fn is_exhausted(&self) -> bool {
+        self.budget.is_exhausted()
+    }
 
-                    let item = item.build().expect("all item fields should be provided");
+    fn rate_limit(&self) -> Option<RateLimit> {
+        self.client.get()?.as_ref().ok()?.rate_limit()
+    }
 
-                    Some(item)
-                }
-            })
-            .collect())
+    fn write_back<'a>(
+        &'a self,
+        url: &'a str,
+        action: WriteBack,
+        comment: &'a str,
+    ) -> ItemFuture<'a, ()> {
+        Box::pin(self.write_back_impl(url, action, comment))
     }
 }
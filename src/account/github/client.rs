@@ -6,24 +6,45 @@
 
 use std::env;
 use std::fmt::Debug;
-use std::thread;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use base64::Engine;
+use chrono::{DateTime, Utc};
 use graphql_client::{GraphQLQuery, QueryBody, Response};
 use itertools::Itertools;
 use log::{info, warn};
-use reqwest::blocking::Client;
 use reqwest::header::{self, HeaderMap, HeaderValue};
-use reqwest::{self, Url};
-use serde::Deserialize;
+use reqwest::{self, Client, Proxy, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use thiserror::Error;
 
-// The maximum number of times we will retry server errors.
-const BACKOFF_LIMIT: usize = 5;
-// The number of seconds to start retries at.
-const BACKOFF_START: Duration = Duration::from_secs(1);
-// How much to scale retry timeouts for a single query.
-const BACKOFF_SCALE: u32 = 2;
+use crate::account::{self, ConnectContext, RateLimit, RequestBudget, RetryableError};
+use crate::config::BackoffPolicy;
+use crate::redact;
+
+// How long to wait after hitting a secondary rate limit without a `Retry-After` header.
+const SECONDARY_RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
+// The headers reporting the primary rate limit.
+const RATE_LIMIT_REMAINING_HEADER: &str = "x-ratelimit-remaining";
+const RATE_LIMIT_RESET_HEADER: &str = "x-ratelimit-reset";
+// The marker in responses for secondary rate limits.
+const SECONDARY_RATE_LIMIT_MESSAGE: &str = "secondary rate limit";
+// The cache namespace for GitHub responses.
+const CACHE_NAMESPACE: &str = "github";
+// The cache namespace for the contents of files.
+const FILE_CACHE_NAMESPACE: &str = "github-files";
+// The prefix of the error message GitHub uses when the rate limit has been hit.
+const RATE_LIMITED_MESSAGE: &str = "API rate limit exceeded";
+// Error codes GitHub uses when a query does not match its schema.
+const SCHEMA_ERROR_CODES: &[&str] = &[
+    "argumentNotAccepted",
+    "undefinedField",
+    "undefinedType",
+    "undefinedEnumValue",
+];
 
 #[derive(Debug, Error)]
 pub enum GithubError {
@@ -37,28 +58,93 @@ pub enum GithubError {
         endpoint: Url,
         source: reqwest::Error,
     },
+    #[error("failed to create the http client: {}", source)]
+    BuildClient { source: reqwest::Error },
     #[error("github error: {}", response)]
     Github { response: String },
+    #[error("github rejected the token ({}): {}", status, response)]
+    TokenRejected {
+        status: reqwest::StatusCode,
+        response: String,
+    },
     #[error("deserialize error: {}", source)]
     Deserialize {
         #[from]
         source: serde_json::Error,
     },
+    #[error("github secondary rate limit hit; retry after {:?}", retry_after)]
+    SecondaryRateLimit { retry_after: Duration },
     #[error("github service error: {}", status)]
     GithubService { status: reqwest::StatusCode },
+    #[error("failed to fetch {}: {}", url, status)]
+    FetchFile {
+        url: Url,
+        status: reqwest::StatusCode,
+    },
     #[error("json response deserialize: {}", source)]
     JsonResponse { source: reqwest::Error },
     #[error("graphql error: [\"{}\"]", message.iter().format("\", \""))]
     GraphQL { message: Vec<graphql_client::Error> },
+    #[error(
+        "the server does not support the queries (it may be an older GitHub Enterprise Server): \
+         [\"{}\"]",
+        message.iter().format("\", \"")
+    )]
+    UnsupportedSchema { message: Vec<graphql_client::Error> },
     #[error("no response from github")]
     NoResponse {},
     #[error("failure even after exponential backoff")]
     GithubBackoff {},
+    #[error("the request budget has been exhausted")]
+    BudgetExhausted {},
 }
 
-impl GithubError {
+impl RetryableError for GithubError {
     fn should_backoff(&self) -> bool {
-        matches!(self, GithubError::GithubService { .. })
+        matches!(
+            self,
+            GithubError::GithubService { .. } | GithubError::SecondaryRateLimit { .. },
+        )
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        if let GithubError::SecondaryRateLimit {
+            retry_after,
+        } = self
+        {
+            Some(*retry_after)
+        } else {
+            None
+        }
+    }
+
+    fn backoff_exhausted() -> Self {
+        GithubError::GithubBackoff {}
+    }
+}
+
+impl GithubError {
+    /// Whether another token should be used for the request.
+    fn should_rotate(&self) -> bool {
+        match self {
+            GithubError::TokenRejected {
+                ..
+            } => true,
+            GithubError::GraphQL {
+                message,
+            } => {
+                message
+                    .iter()
+                    .any(|err| err.message.starts_with(RATE_LIMITED_MESSAGE))
+            },
+            _ => false,
+        }
+    }
+
+    pub fn build_client(source: reqwest::Error) -> Self {
+        GithubError::BuildClient {
+            source,
+        }
     }
 
     pub fn send_request(endpoint: Url, source: reqwest::Error) -> Self {
@@ -74,12 +160,32 @@ impl GithubError {
         }
     }
 
+    fn token_rejected(status: reqwest::StatusCode, response: String) -> Self {
+        GithubError::TokenRejected {
+            status,
+            response,
+        }
+    }
+
+    fn secondary_rate_limit(retry_after: Duration) -> Self {
+        GithubError::SecondaryRateLimit {
+            retry_after,
+        }
+    }
+
     fn github_service(status: reqwest::StatusCode) -> Self {
         GithubError::GithubService {
             status,
         }
     }
 
+    fn fetch_file(url: Url, status: reqwest::StatusCode) -> Self {
+        GithubError::FetchFile {
+            url,
+            status,
+        }
+    }
+
     pub fn json_response(source: reqwest::Error) -> Self {
         GithubError::JsonResponse {
             source,
@@ -87,8 +193,23 @@ impl GithubError {
     }
 
     fn graphql(message: Vec<graphql_client::Error>) -> Self {
-        GithubError::GraphQL {
-            message,
+        // Queries are checked against the schema of github.com, so errors about the schema come
+        // from servers with an older schema.
+        let is_schema_error = |err: &graphql_client::Error| {
+            err.extensions
+                .as_ref()
+                .and_then(|extensions| extensions.get("code"))
+                .and_then(|code| code.as_str())
+                .is_some_and(|code| SCHEMA_ERROR_CODES.contains(&code))
+        };
+        if message.iter().any(is_schema_error) {
+            GithubError::UnsupportedSchema {
+                message,
+            }
+        } else {
+            GithubError::GraphQL {
+                message,
+            }
         }
     }
 
@@ -96,45 +217,151 @@ impl GithubError {
         GithubError::NoResponse {}
     }
 
-    fn github_backoff() -> Self {
-        GithubError::GithubBackoff {}
+    fn budget_exhausted() -> Self {
+        GithubError::BudgetExhausted {}
     }
 }
 
 pub type GithubResult<T> = Result<T, GithubError>;
 
+/// Headers in a form suitable for capturing.
+fn capture_headers(headers: &HeaderMap) -> serde_json::Value {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if value.is_sensitive() {
+                redact::REDACTED
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            (name.as_str().into(), value.into())
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
 // The user agent for all queries.
 pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), " v", env!("CARGO_PKG_VERSION"));
 
+/// The body of a GraphQL request.
+///
+/// Serialized the same way as `QueryBody`, but the query does not need to be known at compile
+/// time.
+#[derive(Serialize)]
+struct RequestBody<'a, V> {
+    variables: &'a V,
+    query: &'a str,
+    #[serde(rename = "operationName")]
+    operation_name: &'a str,
+}
+
+impl<'a, V> From<&'a QueryBody<V>> for RequestBody<'a, V> {
+    fn from(query: &'a QueryBody<V>) -> Self {
+        Self {
+            variables: &query.variables,
+            query: query.query,
+            operation_name: query.operation_name,
+        }
+    }
+}
+
 /// A client for communicating with a Github instance.
-#[derive(Clone)]
 pub struct Github {
     /// The client used to communicate with Github.
     client: Client,
     /// The endpoint for GraphQL queries.
     gql_endpoint: Url,
 
-    /// The token for the client.
-    token: String,
+    /// The tokens for the client.
+    tokens: Vec<String>,
+    /// The index of the token currently in use.
+    current_token: AtomicUsize,
+    /// The policy for retrying failed requests.
+    backoff: BackoffPolicy,
+    /// Facilities shared with other connections.
+    context: ConnectContext,
+    /// The budget for requests made by the client.
+    budget: Arc<RequestBudget>,
+    /// The rate limit as of the last response.
+    rate_limit: Mutex<Option<RateLimit>>,
 }
 
 impl Github {
-    pub fn new<T>(host: &str, token: T) -> GithubResult<Self>
-    where
-        T: Into<String>,
-    {
-        let gql_endpoint = Url::parse(&format!("https://{}/graphql", host))?;
+    pub fn new(
+        endpoint: &str,
+        tokens: Vec<String>,
+        backoff: BackoffPolicy,
+        budget: Arc<RequestBudget>,
+        context: ConnectContext,
+        timeout: Option<Duration>,
+        proxy: Option<&str>,
+    ) -> GithubResult<Self> {
+        let gql_endpoint = Url::parse(endpoint)?;
+        let mut builder = Client::builder();
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(Proxy::all(proxy).map_err(GithubError::build_client)?);
+        }
+        let client = builder.build().map_err(GithubError::build_client)?;
 
         Ok(Github {
-            client: Client::new(),
+            client,
             gql_endpoint,
-            token: token.into(),
+            tokens,
+            current_token: AtomicUsize::new(0),
+            backoff,
+            budget,
+            context,
+            rate_limit: Mutex::new(None),
         })
     }
 
+    /// The rate limit as of the last response.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().expect("rate limit lock poisoned")
+    }
+
+    /// Note the rate limit reported in response headers.
+    fn update_rate_limit(&self, headers: &HeaderMap) {
+        let header_value = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<i64>().ok())
+        };
+
+        if let Some(remaining) = header_value(RATE_LIMIT_REMAINING_HEADER) {
+            let reset_at = header_value(RATE_LIMIT_RESET_HEADER)
+                .and_then(|reset| DateTime::<Utc>::from_timestamp(reset, 0));
+            *self.rate_limit.lock().expect("rate limit lock poisoned") = Some(RateLimit {
+                remaining,
+                reset_at,
+            });
+        }
+    }
+
+    /// The token currently in use.
+    fn token(&self) -> &str {
+        let index = self.current_token.load(Ordering::SeqCst);
+        &self.tokens[index % self.tokens.len()]
+    }
+
+    /// Switch to the next token.
+    fn rotate_token(&self) {
+        let index = self.current_token.fetch_add(1, Ordering::SeqCst) + 1;
+        info!(
+            target: "github",
+            "rotating to token {} of {}",
+            index % self.tokens.len() + 1,
+            self.tokens.len(),
+        );
+    }
+
     /// The authorization header for GraphQL.
     fn auth_header(&self) -> GithubResult<HeaderMap> {
-        let mut header_value: HeaderValue = format!("bearer {}", self.token).parse().unwrap();
+        let mut header_value: HeaderValue = format!("bearer {}", self.token()).parse().unwrap();
         header_value.set_sensitive(true);
         Ok([(header::AUTHORIZATION, header_value)]
             .iter()
@@ -143,12 +370,33 @@ impl Github {
     }
 
     /// Send a GraphQL query.
-    fn send_impl<Q>(&self, query: &QueryBody<Q::Variables>) -> GithubResult<Q::ResponseData>
+    ///
+    /// Responses are only cached if `cacheable` is set.
+    async fn send_impl<V, T>(&self, query: &RequestBody<'_, V>, cacheable: bool) -> GithubResult<T>
     where
-        Q: GraphQLQuery,
-        Q::Variables: Debug,
-        for<'d> Q::ResponseData: Deserialize<'d>,
+        V: Serialize + Debug,
+        T: DeserializeOwned,
     {
+        let request_body = serde_json::to_string(query)?;
+        let cache_key = (
+            self.gql_endpoint.as_str(),
+            self.token(),
+            request_body.as_str(),
+        );
+        let cache = self.context.cache.as_ref().filter(|_| cacheable);
+        if let Some(cache) = cache {
+            if let Some(body) = cache.get(CACHE_NAMESPACE, &cache_key) {
+                match Self::parse_response(&body) {
+                    Ok(data) => return Ok(data),
+                    Err(err) => warn!(target: "github", "ignoring invalid cache entry: {}", err),
+                }
+            }
+        }
+
+        if !self.budget.spend() {
+            return Err(GithubError::budget_exhausted());
+        }
+
         info!(
             target: "github",
             "sending GraphQL query '{}' {:?}",
@@ -162,23 +410,137 @@ impl Github {
             .header(header::USER_AGENT, USER_AGENT)
             .json(query)
             .send()
+            .await
             .map_err(|err| GithubError::send_request(self.gql_endpoint.clone(), err))?;
-        if rsp.status().is_server_error() {
+        let status = rsp.status();
+        let retry_after = rsp
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_secs);
+        self.update_rate_limit(rsp.headers());
+        let rsp_headers = self
+            .context
+            .capture
+            .as_ref()
+            .map(|_| capture_headers(rsp.headers()));
+        let body = rsp.text().await;
+        if let (Some(capture), Some(rsp_headers)) = (self.context.capture.as_ref(), rsp_headers) {
+            let exchange = serde_json::json!({
+                "endpoint": self.gql_endpoint.as_str(),
+                "request": {
+                    "headers": capture_headers(&self.auth_header()?),
+                    "body": serde_json::to_value(query)?,
+                },
+                "response": {
+                    "status": status.as_u16(),
+                    "headers": rsp_headers,
+                    "body": body.as_ref().map(String::as_str).unwrap_or("<failed to read body>"),
+                },
+            });
+            capture.record("github", &exchange);
+        }
+
+        if status.is_server_error() {
             warn!(
                 target: "github",
                 "service error {} for query; retrying with backoff",
-                rsp.status().as_u16(),
+                status.as_u16(),
             );
-            return Err(GithubError::github_service(rsp.status()));
+            return Err(GithubError::github_service(status));
         }
-        if !rsp.status().is_success() {
-            let err = rsp
-                .text()
+        if !status.is_success() {
+            let err = body
                 .unwrap_or_else(|text_err| format!("failed to extract error body: {:?}", text_err));
+            let rate_limited = matches!(
+                status,
+                StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS,
+            );
+            if rate_limited && (retry_after.is_some() || err.contains(SECONDARY_RATE_LIMIT_MESSAGE))
+            {
+                let retry_after = retry_after.unwrap_or(SECONDARY_RATE_LIMIT_DELAY);
+                warn!(
+                    target: "github",
+                    "secondary rate limit hit; retrying after {} seconds",
+                    retry_after.as_secs(),
+                );
+                return Err(GithubError::secondary_rate_limit(retry_after));
+            }
+            if rate_limited || status == StatusCode::UNAUTHORIZED {
+                return Err(GithubError::token_rejected(status, err));
+            }
             return Err(GithubError::github(err));
         }
 
-        let rsp: Response<Q::ResponseData> = rsp.json().map_err(GithubError::json_response)?;
+        let body = body.map_err(GithubError::json_response)?;
+        let data = Self::parse_response(&body)?;
+        if let Some(cache) = cache {
+            cache.put(CACHE_NAMESPACE, &cache_key, &body);
+        }
+        Ok(data)
+    }
+
+    /// Fetch the base64-encoded contents of a file if it is no larger than `max_size` bytes.
+    ///
+    /// Credentials are not sent since files may be hosted elsewhere.
+    pub async fn fetch_file(&self, url: &str, max_size: usize) -> GithubResult<Option<String>> {
+        let url = Url::parse(url)?;
+        let cache_key = (url.as_str(), max_size);
+        if let Some(cache) = self.context.cache.as_ref() {
+            if let Some(content) = cache.get(FILE_CACHE_NAMESPACE, &cache_key) {
+                return Ok(Some(content));
+            }
+        }
+
+        if !self.budget.spend() {
+            return Err(GithubError::budget_exhausted());
+        }
+
+        info!(target: "github", "fetching file {}", url);
+        let mut rsp = self
+            .client
+            .get(url.clone())
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await
+            .map_err(|err| GithubError::send_request(url.clone(), err))?;
+        let status = rsp.status();
+        if !status.is_success() {
+            return Err(GithubError::fetch_file(url, status));
+        }
+        if rsp
+            .content_length()
+            .is_some_and(|len| len > max_size as u64)
+        {
+            return Ok(None);
+        }
+
+        let mut content = Vec::new();
+        while let Some(chunk) = rsp
+            .chunk()
+            .await
+            .map_err(|err| GithubError::send_request(url.clone(), err))?
+        {
+            content.extend_from_slice(&chunk);
+            if content.len() > max_size {
+                return Ok(None);
+            }
+        }
+
+        let content = base64::engine::general_purpose::STANDARD.encode(content);
+        if let Some(cache) = self.context.cache.as_ref() {
+            cache.put(FILE_CACHE_NAMESPACE, &cache_key, &content);
+        }
+        Ok(Some(content))
+    }
+
+    /// Extract the data from a GraphQL response.
+    fn parse_response<T>(body: &str) -> GithubResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let rsp: Response<T> = serde_json::from_str(body)?;
         if let Some(errs) = rsp.errors {
             return Err(GithubError::graphql(errs));
         }
@@ -186,34 +548,66 @@ impl Github {
     }
 
     /// Send a GraphQL query.
-    pub fn send<Q>(&self, query: &QueryBody<Q::Variables>) -> GithubResult<Q::ResponseData>
+    pub async fn send<Q>(&self, query: &QueryBody<Q::Variables>) -> GithubResult<Q::ResponseData>
     where
         Q: GraphQLQuery,
         Q::Variables: Debug,
-        for<'d> Q::ResponseData: Deserialize<'d>,
+        Q::ResponseData: DeserializeOwned,
     {
-        retry_with_backoff(|| self.send_impl::<Q>(query))
+        self.send_with(&query.into(), true).await
     }
-}
 
-fn retry_with_backoff<F, K>(mut go: F) -> GithubResult<K>
-where
-    F: FnMut() -> GithubResult<K>,
-{
-    let mut timeout = BACKOFF_START;
-    for _ in 0..BACKOFF_LIMIT {
-        match go() {
-            Ok(r) => return Ok(r),
-            Err(err) => {
-                if err.should_backoff() {
-                    thread::sleep(timeout);
-                    timeout *= BACKOFF_SCALE;
-                } else {
-                    return Err(err);
-                }
-            },
-        }
+    /// Send a GraphQL query built at runtime.
+    pub async fn send_dynamic<V, T>(
+        &self,
+        operation_name: &str,
+        query: &str,
+        variables: &V,
+    ) -> GithubResult<T>
+    where
+        V: Serialize + Debug,
+        T: DeserializeOwned,
+    {
+        let body = RequestBody {
+            variables,
+            query,
+            operation_name,
+        };
+        self.send_with(&body, true).await
     }
 
-    Err(GithubError::github_backoff())
+    /// Send a GraphQL mutation.
+    ///
+    /// Mutations are never answered from the cache.
+    pub async fn send_mutation<Q>(
+        &self,
+        query: &QueryBody<Q::Variables>,
+    ) -> GithubResult<Q::ResponseData>
+    where
+        Q: GraphQLQuery,
+        Q::Variables: Debug,
+        Q::ResponseData: DeserializeOwned,
+    {
+        self.send_with(&query.into(), false).await
+    }
+
+    async fn send_with<V, T>(&self, query: &RequestBody<'_, V>, cacheable: bool) -> GithubResult<T>
+    where
+        V: Serialize + Debug,
+        T: DeserializeOwned,
+    {
+        // Give each token a chance before giving up.
+        let mut attempts = self.tokens.len();
+        loop {
+            let send = || self.send_impl(query, cacheable);
+            match account::retry_with_backoff(&self.backoff, send).await {
+                Err(err) if attempts > 1 && err.should_rotate() => {
+                    warn!(target: "github", "token unusable: {}", err);
+                    attempts -= 1;
+                    self.rotate_token();
+                },
+                res => return res,
+            }
+        }
+    }
 }
@@ -1,22 +1,71 @@
 #!/bin/sh
 
+# Fetch the GraphQL schema from GitHub and rebuild the query bindings against it.
+#
+# Usage: update_schema.sh [--check]
+#
+# The endpoint may be set with `GITHUB_GRAPHQL_URL` (e.g.,
+# `https://github.example.com/api/graphql` for GitHub Enterprise Server). The
+# token is read from `GITHUB_TOKEN` or the `.gh-token` file.
+#
+# With `--check`, the queries are checked against the fetched schema and the
+# existing schema is restored afterwards. This is useful to see whether an
+# Enterprise Server instance supports the queries.
+
 set -e
 
-readonly token="$( cat ".gh-token" )"
+readonly graphql_url="${GITHUB_GRAPHQL_URL:-https://api.github.com/graphql}"
+readonly schema_dir="$( dirname "$0" )"
+readonly schema="$schema_dir/schema.graphql"
+
+check=false
+case "$1" in
+    --check)
+        check=true
+        ;;
+    "")
+        ;;
+    *)
+        echo >&2 "usage: $0 [--check]"
+        exit 1
+        ;;
+esac
+readonly check
+
+if [ -n "$GITHUB_TOKEN" ]; then
+    token="$GITHUB_TOKEN"
+else
+    token="$( cat ".gh-token" )"
+fi
+readonly token
+
+readonly fetched="$( mktemp )"
+trap 'rm -f "$fetched" "$fetched.orig"' EXIT
 
 curl \
+  --fail \
   --header "Accept: application/vnd.github.v4.idl" \
   --header "Authorization: bearer $token" \
-  "https://api.github.com/graphql" | \
+  "$graphql_url" | \
   jq .data --raw-output \
-  > schema.graphql
+  > "$fetched"
 
 # Double newline because the main schema is without a newline at the end of the
 # file.
-cat >>schema.graphql <<EOF
+cat >>"$fetched" <<EOF
 
 schema {
   query: Query
   mutation: Mutation
 }
 EOF
+
+if $check; then
+    cp "$schema" "$fetched.orig"
+    trap 'cp "$fetched.orig" "$schema"; rm -f "$fetched" "$fetched.orig"' EXIT
+fi
+cp "$fetched" "$schema"
+
+# The bindings are generated from the schema when building, so building
+# regenerates them and checks that the queries are valid against the schema.
+cargo check --manifest-path "$schema_dir/../../../../Cargo.toml" --features github
@@ -38,8 +38,14 @@ macro_rules! gql_query {
     };
 }
 
-gql_query!(ViewerIssues, "User");
-gql_query!(ViewerPullRequests, "Issue");
+gql_query!(Viewer, "Viewer");
+gql_query!(ViewerItems, "Items");
+gql_query!(ViewerSearch, "Search");
+gql_query!(ViewerStarred, "Starred");
+gql_query!(ItemNode, "ItemNode");
+gql_query_base!(CloseIssue);
+gql_query_base!(AddComment);
+gql_query_base!(RemoveAssignee);
 
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct RateLimitInfo {
@@ -110,5 +116,8 @@ macro_rules! impl_into_rate_limit_info {
     };
 }
 
-impl_into_rate_limit_info!(viewer_issues::RateLimitInfoRateLimit);
-impl_into_rate_limit_info!(viewer_pull_requests::RateLimitInfoRateLimit);
+impl_into_rate_limit_info!(viewer::RateLimitInfoRateLimit);
+impl_into_rate_limit_info!(viewer_items::RateLimitInfoRateLimit);
+impl_into_rate_limit_info!(viewer_search::RateLimitInfoRateLimit);
+impl_into_rate_limit_info!(viewer_starred::RateLimitInfoRateLimit);
+impl_into_rate_limit_info!(item_node::RateLimitInfoRateLimit);
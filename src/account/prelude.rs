@@ -4,11 +4,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-pub use std::error::Error;
-
+pub use crate::account::DueDates;
+pub use crate::account::Identity;
 pub use crate::account::ItemError;
-pub use crate::account::ItemLookup;
+pub use crate::account::ItemFuture;
 pub use crate::account::ItemSource;
+pub use crate::account::RateLimit;
+pub use crate::account::SourceItem;
 pub use crate::config::Filter;
+pub use crate::config::Profile;
 pub use crate::config::QueryTarget;
-pub use crate::todo::TodoItem;
+pub use crate::config::WriteBack;
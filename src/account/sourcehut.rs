@@ -0,0 +1,530 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future;
+use graphql_client::GraphQLQuery;
+use lazy_init::LazyTransform;
+use log::{error, warn};
+use once_cell::sync::OnceCell;
+
+use crate::account::prelude::*;
+use crate::account::{ConnectContext, RequestBudget};
+use crate::config::{Account, BackoffPolicy};
+use crate::todo::{Dependencies, TodoKind, TodoStatus};
+
+mod client;
+mod queries;
+
+// The host of the hosted todo.sr.ht instance.
+const SOURCEHUT_TODO_HOST: &str = "todo.sr.ht";
+
+struct ConnInfo {
+    endpoint: String,
+    tokens: Vec<String>,
+    backoff: BackoffPolicy,
+    context: ConnectContext,
+    budget: Arc<RequestBudget>,
+    timeout: Option<Duration>,
+    proxy: Option<String>,
+}
+
+pub struct SourcehutQuery {
+    host: String,
+    client: LazyTransform<ConnInfo, client::SourcehutResult<client::Sourcehut>>,
+    init_error_cell: OnceCell<()>,
+    budget: Arc<RequestBudget>,
+}
+
+/// A tracker to fetch tickets from.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct TrackerName {
+    /// The canonical name of the owner (e.g., `~user`).
+    owner: String,
+    name: String,
+}
+
+impl TrackerName {
+    /// Parse a tracker name of the form `~owner/name`.
+    fn parse(tracker: &str) -> Option<Self> {
+        let (owner, name) = tracker.split_once('/')?;
+        if owner.is_empty() || name.is_empty() || name.contains('/') {
+            return None;
+        }
+
+        // Owners are referred to with a leading `~`.
+        let owner = if owner.starts_with('~') {
+            owner.into()
+        } else {
+            format!("~{}", owner)
+        };
+
+        Some(TrackerName {
+            owner,
+            name: name.into(),
+        })
+    }
+}
+
+/// Whether a ticket's labels satisfy the filters.
+fn matches_filters(labels: &[&str], filters: &[Filter]) -> bool {
+    filters.iter().all(|filter| {
+        match filter {
+            Filter::Label(label) => labels.iter().any(|name| name == label),
+            Filter::NotLabel(label) => !labels.iter().any(|name| name == label),
+            // Rejected before querying.
+            Filter::Milestone(_) => false,
+        }
+    })
+}
+
+impl SourcehutQuery {
+    pub fn new(account: Account, tokens: Vec<String>, context: ConnectContext) -> Self {
+        let budget = Arc::new(RequestBudget::new(account.max_requests_per_run));
+        let host = account
+            .hostname
+            .unwrap_or_else(|| SOURCEHUT_TODO_HOST.into());
+        let path = account.api_path.unwrap_or_else(|| "query".into());
+        let scheme = if account.insecure_http {
+            warn!("using plain HTTP to communicate with {}", host);
+            "http"
+        } else {
+            "https"
+        };
+        let endpoint = format!("{}://{}/{}", scheme, host, path.trim_start_matches('/'));
+
+        SourcehutQuery {
+            host: format!("{}://{}", scheme, host),
+            client: LazyTransform::new(ConnInfo {
+                endpoint,
+                tokens,
+                backoff: account.backoff,
+                context,
+                budget: budget.clone(),
+                timeout: account.timeout.and_then(|timeout| timeout.0.to_std().ok()),
+                proxy: account.proxy,
+            }),
+            init_error_cell: OnceCell::new(),
+            budget,
+        }
+    }
+
+    /// The client for the instance.
+    fn client(&self) -> Result<&client::Sourcehut, ItemError> {
+        self.client
+            .get_or_create(|info| {
+                client::Sourcehut::new(
+                    &info.endpoint,
+                    info.tokens,
+                    info.backoff,
+                    info.budget,
+                    info.context,
+                    info.timeout,
+                    info.proxy.as_deref(),
+                )
+            })
+            .as_ref()
+            .map_err(|err| {
+                self.init_error_cell.get_or_init(|| {
+                    error!("failed to connect to sourcehut instance: {:?}", err);
+                });
+                ItemError::ServiceError {
+                    service: "sourcehut",
+                }
+            })
+    }
+
+    /// Convert an error from sending a query into an item error.
+    fn query_error(name: &str, err: client::SourcehutError) -> ItemError {
+        if let client::SourcehutError::BudgetExhausted {} = err {
+            return ItemError::BudgetExhausted {
+                service: "sourcehut",
+            };
+        }
+
+        error!("failed to send {} query: {:?}", name, err);
+        let message = format!("failed to send {} query: {}", name, err);
+        ItemError::QueryError {
+            service: "sourcehut",
+            message,
+        }
+    }
+
+    /// The canonical name of the user.
+    async fn query_viewer(client: &client::Sourcehut) -> Result<String, ItemError> {
+        let query = queries::Viewer::build_query(queries::viewer::Variables {});
+        let rsp = client
+            .send::<queries::Viewer>(&query)
+            .await
+            .map_err(|err| Self::query_error("viewer", err))?;
+
+        Ok(rsp.me.canonical_name)
+    }
+
+    /// The trackers owned by the user.
+    async fn query_owned_trackers(
+        client: &client::Sourcehut,
+        trackers: &mut BTreeSet<TrackerName>,
+    ) -> Result<(), ItemError> {
+        let mut input = queries::viewer_trackers::Variables {
+            cursor: None,
+        };
+
+        loop {
+            let query = queries::ViewerTrackers::build_query(input.clone());
+            let rsp = client
+                .send::<queries::ViewerTrackers>(&query)
+                .await
+                .map_err(|err| Self::query_error("viewer trackers", err))?;
+
+            let results = rsp.me.trackers;
+            trackers.extend(results.results.into_iter().map(|tracker| {
+                TrackerName {
+                    owner: tracker.owner.canonical_name,
+                    name: tracker.name,
+                }
+            }));
+
+            if results.cursor.is_some() {
+                input.cursor = results.cursor;
+            } else {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The trackers the user is subscribed to, either directly or through one of its tickets.
+    ///
+    /// Submitting or commenting on a ticket subscribes the user to it, so this finds trackers
+    /// owned by others which contain tickets the user is involved with.
+    async fn query_subscribed_trackers(
+        client: &client::Sourcehut,
+        trackers: &mut BTreeSet<TrackerName>,
+    ) -> Result<(), ItemError> {
+        use queries::viewer_subscriptions::ViewerSubscriptionsSubscriptionsResults as Subscription;
+
+        let mut input = queries::viewer_subscriptions::Variables {
+            cursor: None,
+        };
+
+        loop {
+            let query = queries::ViewerSubscriptions::build_query(input.clone());
+            let rsp = client
+                .send::<queries::ViewerSubscriptions>(&query)
+                .await
+                .map_err(|err| Self::query_error("viewer subscriptions", err))?;
+
+            let results = rsp.subscriptions;
+            trackers.extend(results.results.into_iter().map(|subscription| {
+                match subscription {
+                    Subscription::TrackerSubscription(sub) => {
+                        TrackerName {
+                            owner: sub.tracker.owner.canonical_name,
+                            name: sub.tracker.name,
+                        }
+                    },
+                    Subscription::TicketSubscription(sub) => {
+                        TrackerName {
+                            owner: sub.ticket.tracker.owner.canonical_name,
+                            name: sub.ticket.tracker.name,
+                        }
+                    },
+                }
+            }));
+
+            if results.cursor.is_some() {
+                input.cursor = results.cursor;
+            } else {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the tickets in a tracker which are assigned to or submitted by the user.
+    async fn query_tracker(
+        &self,
+        client: &client::Sourcehut,
+        tracker: &TrackerName,
+        viewer: &str,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let mut input = queries::tracker_tickets::Variables {
+            owner: tracker.owner.clone(),
+            tracker: tracker.name.clone(),
+            cursor: None,
+        };
+
+        let mut items = Vec::new();
+
+        loop {
+            let query = queries::TrackerTickets::build_query(input.clone());
+            let rsp = client
+                .send::<queries::TrackerTickets>(&query)
+                .await
+                .map_err(|err| Self::query_error("tracker tickets", err))?;
+
+            let tracker_info = if let Some(tracker_info) = rsp.tracker_by_owner {
+                tracker_info
+            } else {
+                return Err(ItemError::QueryError {
+                    service: "sourcehut",
+                    message: format!("no such tracker: {}/{}", tracker.owner, tracker.name),
+                });
+            };
+            let confidential = matches!(
+                tracker_info.visibility,
+                queries::tracker_tickets::Visibility::PRIVATE,
+            );
+
+            let tickets = tracker_info.tickets;
+            items.extend(tickets.results.into_iter().filter_map(|ticket| {
+                self.ticket_item(tracker, ticket, viewer, confidential, filters)
+            }));
+
+            if tickets.cursor.is_some() {
+                input.cursor = tickets.cursor;
+            } else {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Convert a ticket into an item if it is relevant to the user.
+    fn ticket_item(
+        &self,
+        tracker: &TrackerName,
+        ticket: queries::tracker_tickets::TicketInfo,
+        viewer: &str,
+        confidential: bool,
+        filters: &[Filter],
+    ) -> Option<SourceItem> {
+        use queries::tracker_tickets::{TicketResolution, TicketStatus};
+
+        let is_assigned = ticket
+            .assignees
+            .iter()
+            .any(|assignee| assignee.canonical_name == viewer);
+        let is_author = ticket.submitter.canonical_name == viewer;
+        if !is_assigned && !is_author {
+            return None;
+        }
+
+        let labels = ticket
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect::<Vec<_>>();
+        if !matches_filters(&labels, filters) {
+            return None;
+        }
+
+        let has_assignees = !ticket.assignees.is_empty();
+        let kind = if is_assigned {
+            TodoKind::AssignedIssue
+        } else {
+            TodoKind::Issue
+        };
+        let is_open = !matches!(ticket.status, TicketStatus::RESOLVED);
+        let delegated = is_open && is_author && has_assignees && !is_assigned;
+        let status = match ticket.status {
+            TicketStatus::RESOLVED => {
+                match ticket.resolution {
+                    Some(
+                        TicketResolution::WONT_FIX
+                        | TicketResolution::BY_DESIGN
+                        | TicketResolution::INVALID
+                        | TicketResolution::DUPLICATE
+                        | TicketResolution::NOT_OUR_BUG,
+                    ) => TodoStatus::Cancelled,
+                    _ => TodoStatus::Completed,
+                }
+            },
+            TicketStatus::IN_PROGRESS => TodoStatus::InProcess,
+            TicketStatus::REPORTED | TicketStatus::CONFIRMED | TicketStatus::PENDING => {
+                if has_assignees {
+                    TodoStatus::InProcess
+                } else {
+                    TodoStatus::NeedsAction
+                }
+            },
+            state => {
+                warn!("unknown sourcehut ticket status: {:?}", state);
+                TodoStatus::NeedsAction
+            },
+        };
+
+        Some(SourceItem {
+            due: None,
+            due_dates: DueDates::default(),
+            estimate: None,
+            summary: ticket.subject,
+            description: ticket.body.unwrap_or_default(),
+            kind,
+            status,
+            url: format!(
+                "{}/{}/{}/{}",
+                self.host, tracker.owner, tracker.name, ticket.id,
+            ),
+            attachments: Vec::new(),
+            dependencies: Dependencies::default(),
+            delegated,
+            labels: labels.into_iter().map(Into::into).collect(),
+            confidential,
+            private: false,
+            source_id: None,
+            milestone: None,
+            priority: None,
+            assignees: Vec::new(),
+            extra_properties: BTreeMap::new(),
+            updated_at: Some(ticket.updated),
+        })
+    }
+
+    async fn query_user(
+        &self,
+        client: &client::Sourcehut,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        // There is no way to search for tickets across trackers (or to filter the tickets of a
+        // tracker), so look through the trackers the user owns or is subscribed to.
+        let mut trackers = BTreeSet::new();
+        Self::query_owned_trackers(client, &mut trackers).await?;
+        Self::query_subscribed_trackers(client, &mut trackers).await?;
+        self.query_trackers_items(client, trackers.iter(), filters)
+            .await
+    }
+
+    async fn query_projects(
+        &self,
+        client: &client::Sourcehut,
+        projects: &[String],
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError> {
+        let trackers = projects
+            .iter()
+            .map(|project| {
+                TrackerName::parse(project).ok_or_else(|| {
+                    ItemError::QueryError {
+                        service: "sourcehut",
+                        message: format!(
+                            "invalid tracker name (expected `~owner/name`): {}",
+                            project
+                        ),
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.query_trackers_items(client, trackers.iter(), filters)
+            .await
+    }
+
+    async fn query_trackers_items<'a, I>(
+        &self,
+        client: &client::Sourcehut,
+        trackers: I,
+        filters: &[Filter],
+    ) -> Result<Vec<SourceItem>, ItemError>
+    where
+        I: IntoIterator<Item = &'a TrackerName>,
+    {
+        // Look up the user once rather than with every page of tickets.
+        let viewer = Self::query_viewer(client).await?;
+
+        // Trackers are paginated independently, so fetch them concurrently.
+        let queries = trackers
+            .into_iter()
+            .map(|tracker| self.query_tracker(client, tracker, &viewer, filters));
+        let items = future::try_join_all(queries).await?;
+        Ok(items.into_iter().flatten().collect())
+    }
+
+    async fn fetch_items_impl(&self, profile: &Profile) -> Result<Vec<SourceItem>, ItemError> {
+        // Tickets do not have milestones.
+        if profile
+            .filters
+            .iter()
+            .any(|filter| matches!(filter, Filter::Milestone(_)))
+        {
+            return Err(ItemError::Unsupported {
+                operation: "filtering by milestone on sourcehut",
+            });
+        }
+
+        let client = self.client()?;
+
+        let filters = &profile.filters;
+        match &profile.target {
+            QueryTarget::SelfUser => self.query_user(client, filters).await,
+            QueryTarget::Projects(projects) => self.query_projects(client, projects, filters).await,
+            QueryTarget::Mentioned => {
+                Err(ItemError::Unsupported {
+                    operation: "querying mentions on sourcehut",
+                })
+            },
+            QueryTarget::ReviewRequested => {
+                Err(ItemError::Unsupported {
+                    operation: "querying review requests on sourcehut",
+                })
+            },
+            QueryTarget::Participating => {
+                Err(ItemError::Unsupported {
+                    operation: "querying participation on sourcehut",
+                })
+            },
+            QueryTarget::Starred => {
+                Err(ItemError::Unsupported {
+                    operation: "querying starred repositories on sourcehut",
+                })
+            },
+            QueryTarget::HelpWanted(_) => {
+                Err(ItemError::Unsupported {
+                    operation: "querying items looking for contributors on sourcehut",
+                })
+            },
+            QueryTarget::Team(_) => {
+                Err(ItemError::Unsupported {
+                    operation: "querying team workloads on sourcehut",
+                })
+            },
+        }
+    }
+
+    async fn identity_impl(&self) -> Result<Identity, ItemError> {
+        let client = self.client()?;
+
+        Ok(Identity {
+            login: Self::query_viewer(client).await?,
+            rate_limit_remaining: None,
+        })
+    }
+}
+
+impl ItemSource for SourcehutQuery {
+    fn fetch_items<'a>(&'a self, profile: &'a Profile) -> ItemFuture<'a, Vec<SourceItem>> {
+        Box::pin(self.fetch_items_impl(profile))
+    }
+
+    fn identity(&self) -> ItemFuture<'_, Identity> {
+        Box::pin(self.identity_impl())
+    }
+
+    fn requests_made(&self) -> usize {
+        self.budget.used()
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.budget.is_exhausted()
+    }
+}
@@ -0,0 +1,334 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use graphql_client::{GraphQLQuery, QueryBody, Response};
+use itertools::Itertools;
+use log::{info, warn};
+use reqwest::header::{self, HeaderValue};
+use reqwest::{self, Client, Proxy, StatusCode, Url};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::account::{self, ConnectContext, RequestBudget, RetryableError};
+use crate::config::BackoffPolicy;
+
+// The cache namespace for SourceHut responses.
+const CACHE_NAMESPACE: &str = "sourcehut";
+
+#[derive(Debug, Error)]
+pub enum SourcehutError {
+    #[error("url parse error: {}", source)]
+    UrlParse {
+        #[from]
+        source: url::ParseError,
+    },
+    #[error("failed to send request to {}: {}", endpoint, source)]
+    SendRequest {
+        endpoint: Url,
+        source: reqwest::Error,
+    },
+    #[error("failed to create the http client: {}", source)]
+    BuildClient { source: reqwest::Error },
+    #[error("sourcehut error: {}", response)]
+    Sourcehut { response: String },
+    #[error("sourcehut rejected the token ({}): {}", status, response)]
+    TokenRejected {
+        status: reqwest::StatusCode,
+        response: String,
+    },
+    #[error("deserialize error: {}", source)]
+    Deserialize {
+        #[from]
+        source: serde_json::Error,
+    },
+    #[error("sourcehut service error: {}", status)]
+    SourcehutService { status: reqwest::StatusCode },
+    #[error("json response deserialize: {}", source)]
+    JsonResponse { source: reqwest::Error },
+    #[error("graphql error: [\"{}\"]", message.iter().format("\", \""))]
+    GraphQL { message: Vec<graphql_client::Error> },
+    #[error("no response from sourcehut")]
+    NoResponse {},
+    #[error("failure even after exponential backoff")]
+    SourcehutBackoff {},
+    #[error("the request budget has been exhausted")]
+    BudgetExhausted {},
+}
+
+impl RetryableError for SourcehutError {
+    fn should_backoff(&self) -> bool {
+        matches!(self, SourcehutError::SourcehutService { .. })
+    }
+
+    fn backoff_exhausted() -> Self {
+        SourcehutError::SourcehutBackoff {}
+    }
+}
+
+impl SourcehutError {
+    pub fn build_client(source: reqwest::Error) -> Self {
+        SourcehutError::BuildClient {
+            source,
+        }
+    }
+
+    pub fn send_request(endpoint: Url, source: reqwest::Error) -> Self {
+        SourcehutError::SendRequest {
+            endpoint,
+            source,
+        }
+    }
+
+    fn sourcehut(response: String) -> Self {
+        SourcehutError::Sourcehut {
+            response,
+        }
+    }
+
+    fn token_rejected(status: reqwest::StatusCode, response: String) -> Self {
+        SourcehutError::TokenRejected {
+            status,
+            response,
+        }
+    }
+
+    fn sourcehut_service(status: reqwest::StatusCode) -> Self {
+        SourcehutError::SourcehutService {
+            status,
+        }
+    }
+
+    fn json_response(source: reqwest::Error) -> Self {
+        SourcehutError::JsonResponse {
+            source,
+        }
+    }
+
+    fn graphql(message: Vec<graphql_client::Error>) -> Self {
+        SourcehutError::GraphQL {
+            message,
+        }
+    }
+
+    fn no_response() -> Self {
+        SourcehutError::NoResponse {}
+    }
+
+    fn budget_exhausted() -> Self {
+        SourcehutError::BudgetExhausted {}
+    }
+}
+
+pub type SourcehutResult<T> = Result<T, SourcehutError>;
+
+// The user agent for all queries.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), " v", env!("CARGO_PKG_VERSION"));
+
+/// A client for communicating with a SourceHut instance.
+pub struct Sourcehut {
+    /// The client used to communicate with SourceHut.
+    client: Client,
+    /// The endpoint for GraphQL queries.
+    gql_endpoint: Url,
+
+    /// The tokens for the client.
+    tokens: Vec<String>,
+    /// The index of the token currently in use.
+    current_token: AtomicUsize,
+    /// The policy for retrying failed requests.
+    backoff: BackoffPolicy,
+    /// Facilities shared with other connections.
+    context: ConnectContext,
+    /// The budget for requests made by the client.
+    budget: Arc<RequestBudget>,
+}
+
+impl Sourcehut {
+    pub fn new(
+        endpoint: &str,
+        tokens: Vec<String>,
+        backoff: BackoffPolicy,
+        budget: Arc<RequestBudget>,
+        context: ConnectContext,
+        timeout: Option<Duration>,
+        proxy: Option<&str>,
+    ) -> SourcehutResult<Self> {
+        let gql_endpoint = Url::parse(endpoint)?;
+        let mut builder = Client::builder();
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(Proxy::all(proxy).map_err(SourcehutError::build_client)?);
+        }
+        let client = builder.build().map_err(SourcehutError::build_client)?;
+
+        Ok(Sourcehut {
+            client,
+            gql_endpoint,
+            tokens,
+            current_token: AtomicUsize::new(0),
+            backoff,
+            budget,
+            context,
+        })
+    }
+
+    /// The token currently in use.
+    fn token(&self) -> &str {
+        let index = self.current_token.load(Ordering::SeqCst);
+        &self.tokens[index % self.tokens.len()]
+    }
+
+    /// Switch to the next token.
+    fn rotate_token(&self) {
+        let index = self.current_token.fetch_add(1, Ordering::SeqCst) + 1;
+        info!(
+            target: "sourcehut",
+            "rotating to token {} of {}",
+            index % self.tokens.len() + 1,
+            self.tokens.len(),
+        );
+    }
+
+    /// The authorization header for GraphQL.
+    fn auth_header(&self) -> HeaderValue {
+        let mut header_value: HeaderValue = format!("Bearer {}", self.token()).parse().unwrap();
+        header_value.set_sensitive(true);
+        header_value
+    }
+
+    /// Send a GraphQL query.
+    async fn send_impl<Q>(
+        &self,
+        query: &QueryBody<Q::Variables>,
+    ) -> SourcehutResult<Q::ResponseData>
+    where
+        Q: GraphQLQuery,
+        Q::Variables: Debug,
+        for<'d> Q::ResponseData: Deserialize<'d>,
+    {
+        let request_body = serde_json::to_string(query)?;
+        let cache_key = (
+            self.gql_endpoint.as_str(),
+            self.token(),
+            request_body.as_str(),
+        );
+        if let Some(cache) = self.context.cache.as_ref() {
+            if let Some(body) = cache.get(CACHE_NAMESPACE, &cache_key) {
+                match Self::parse_response::<Q>(&body) {
+                    Ok(data) => return Ok(data),
+                    Err(err) => {
+                        warn!(target: "sourcehut", "ignoring invalid cache entry: {}", err);
+                    },
+                }
+            }
+        }
+
+        if !self.budget.spend() {
+            return Err(SourcehutError::budget_exhausted());
+        }
+
+        info!(
+            target: "sourcehut",
+            "sending GraphQL query '{}' {:?}",
+            query.operation_name,
+            query.variables,
+        );
+        let rsp = self
+            .client
+            .post(self.gql_endpoint.clone())
+            .header(header::AUTHORIZATION, self.auth_header())
+            .header(header::USER_AGENT, USER_AGENT)
+            .json(query)
+            .send()
+            .await
+            .map_err(|err| SourcehutError::send_request(self.gql_endpoint.clone(), err))?;
+        let status = rsp.status();
+        let body = rsp.text().await;
+        if let Some(capture) = self.context.capture.as_ref() {
+            let exchange = serde_json::json!({
+                "endpoint": self.gql_endpoint.as_str(),
+                "request": {
+                    "body": serde_json::to_value(query)?,
+                },
+                "response": {
+                    "status": status.as_u16(),
+                    "body": body.as_ref().map(String::as_str).unwrap_or("<failed to read body>"),
+                },
+            });
+            capture.record("sourcehut", &exchange);
+        }
+
+        if status.is_server_error() {
+            warn!(
+                target: "sourcehut",
+                "service error {} for query; retrying with backoff",
+                status.as_u16(),
+            );
+            return Err(SourcehutError::sourcehut_service(status));
+        }
+        if !status.is_success() {
+            let err = body
+                .unwrap_or_else(|text_err| format!("failed to extract error body: {:?}", text_err));
+            if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+                return Err(SourcehutError::token_rejected(status, err));
+            }
+            return Err(SourcehutError::sourcehut(err));
+        }
+
+        let body = body.map_err(SourcehutError::json_response)?;
+        let data = Self::parse_response::<Q>(&body)?;
+        if let Some(cache) = self.context.cache.as_ref() {
+            cache.put(CACHE_NAMESPACE, &cache_key, &body);
+        }
+        Ok(data)
+    }
+
+    /// Extract the data from a GraphQL response.
+    fn parse_response<Q>(body: &str) -> SourcehutResult<Q::ResponseData>
+    where
+        Q: GraphQLQuery,
+        for<'d> Q::ResponseData: Deserialize<'d>,
+    {
+        let rsp: Response<Q::ResponseData> = serde_json::from_str(body)?;
+        if let Some(errs) = rsp.errors {
+            return Err(SourcehutError::graphql(errs));
+        }
+        rsp.data.ok_or_else(SourcehutError::no_response)
+    }
+
+    /// Send a GraphQL query.
+    pub async fn send<Q>(&self, query: &QueryBody<Q::Variables>) -> SourcehutResult<Q::ResponseData>
+    where
+        Q: GraphQLQuery,
+        Q::Variables: Debug,
+        for<'d> Q::ResponseData: Deserialize<'d>,
+    {
+        // Give each token a chance before giving up.
+        let mut attempts = self.tokens.len();
+        loop {
+            let send = || self.send_impl::<Q>(query);
+            match account::retry_with_backoff(&self.backoff, send).await {
+                Err(
+                    err @ SourcehutError::TokenRejected {
+                        ..
+                    },
+                ) if attempts > 1 => {
+                    warn!(target: "sourcehut", "token unusable: {}", err);
+                    attempts -= 1;
+                    self.rotate_token();
+                },
+                res => return res,
+            }
+        }
+    }
+}
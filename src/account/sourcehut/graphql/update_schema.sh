@@ -0,0 +1,8 @@
+#!/bin/sh
+
+set -e
+
+# The schema is published in the todo.sr.ht repository.
+curl \
+  "https://git.sr.ht/~sircmpwn/todo.sr.ht/blob/master/api/graph/schema.graphqls" \
+  > schema.graphql
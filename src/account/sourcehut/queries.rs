@@ -0,0 +1,30 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use chrono::Utc;
+use graphql_client::GraphQLQuery;
+
+type Time = chrono::DateTime<Utc>;
+type Cursor = String;
+
+macro_rules! gql_query {
+    ($name:ident) => {
+        #[derive(GraphQLQuery)]
+        #[graphql(
+            schema_path = "src/account/sourcehut/graphql/schema.graphql",
+            query_path = "src/account/sourcehut/graphql/query.graphql",
+            deprecated = "warn",
+            variables_derives = "Debug, Clone",
+            response_derives = "Debug, Clone"
+        )]
+        pub struct $name;
+    };
+}
+
+gql_query!(Viewer);
+gql_query!(ViewerTrackers);
+gql_query!(ViewerSubscriptions);
+gql_query!(TrackerTickets);
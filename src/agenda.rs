@@ -0,0 +1,121 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io::{self, Write};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::todo::TodoItem;
+
+/// An item with a due date.
+#[derive(Debug, Serialize)]
+struct AgendaEntry {
+    target: String,
+    due: String,
+    #[serde(skip)]
+    deadline: DateTime<Utc>,
+    overdue: bool,
+    status: String,
+    url: String,
+    summary: String,
+}
+
+/// Which items to include in an agenda.
+pub struct AgendaFilter {
+    /// Only include items due within this amount of time (overdue items are always included).
+    pub within: Option<Duration>,
+    /// Only include overdue items.
+    pub overdue_only: bool,
+}
+
+/// Outstanding items across targets, ordered by their due dates.
+pub struct Agenda {
+    filter: AgendaFilter,
+    now: DateTime<Utc>,
+    entries: Vec<AgendaEntry>,
+}
+
+impl Agenda {
+    pub fn new(filter: AgendaFilter, now: DateTime<Utc>) -> Self {
+        Self {
+            filter,
+            now,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add the items of a target which match the filter.
+    pub fn add<'a, I>(&mut self, target: &str, items: I)
+    where
+        I: IntoIterator<Item = &'a TodoItem>,
+    {
+        for item in items {
+            if item.status().is_done() {
+                continue;
+            }
+            let due = if let Some(due) = item.due() {
+                due
+            } else {
+                continue;
+            };
+
+            let deadline = due.deadline();
+            let overdue = deadline < self.now;
+            if self.filter.overdue_only && !overdue {
+                continue;
+            }
+            if self
+                .filter
+                .within
+                .is_some_and(|within| deadline > self.now + within)
+            {
+                continue;
+            }
+
+            self.entries.push(AgendaEntry {
+                target: target.into(),
+                due: due.to_string(),
+                deadline,
+                overdue,
+                status: item.status().as_ref().into(),
+                url: item.url().into(),
+                summary: item.summary().into(),
+            });
+        }
+    }
+
+    /// Write the agenda as tab-separated lines.
+    pub fn write<W>(mut self, out: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        self.entries.sort_by_key(|entry| entry.deadline);
+        for entry in &self.entries {
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}",
+                entry.due,
+                if entry.overdue { "overdue" } else { "due" },
+                entry.target,
+                entry.url,
+                entry.summary,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the agenda as a JSON array.
+    pub fn write_json<W>(mut self, out: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        self.entries.sort_by_key(|entry| entry.deadline);
+        serde_json::to_writer_pretty(&mut *out, &self.entries)?;
+        writeln!(out)
+    }
+}
@@ -0,0 +1,199 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use log::{debug, warn};
+use thiserror::Error;
+
+use crate::vdir;
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("failed to write backup {}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+    #[error("failed to read backup {}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("refusing to overwrite {} while restoring", path.display())]
+    WouldOverwrite { path: PathBuf },
+}
+
+impl BackupError {
+    fn write(path: PathBuf, source: io::Error) -> Self {
+        Self::Write {
+            path,
+            source,
+        }
+    }
+
+    fn read(path: PathBuf, source: io::Error) -> Self {
+        Self::Read {
+            path,
+            source,
+        }
+    }
+
+    fn would_overwrite(path: PathBuf) -> Self {
+        Self::WouldOverwrite {
+            path,
+        }
+    }
+}
+
+// The directory within backups containing the target's files.
+const TARGET_PREFIX: &str = "target";
+// The directory within backups containing devtodo's state.
+const STATE_PREFIX: &str = "state";
+// The compression level for backups.
+const COMPRESSION_LEVEL: i32 = 19;
+
+/// Add the files under a directory to an archive.
+fn append_dir<W>(builder: &mut tar::Builder<W>, dir: &Path, prefix: &Path) -> io::Result<()>
+where
+    W: io::Write,
+{
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        // Locks belong to the process which held them.
+        if name == vdir::LOCK_FILE {
+            continue;
+        }
+
+        let path = entry.path();
+        let archive_path = prefix.join(&name);
+        if entry.file_type()?.is_dir() {
+            append_dir(builder, &path, &archive_path)?;
+        } else {
+            debug!("adding {} to the backup", path.display());
+            builder.append_path_with_name(&path, &archive_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Bundle a target's directory and devtodo's state into a compressed archive.
+pub fn export(directory: &Path, state_dir: &Path, output: &Path) -> Result<(), BackupError> {
+    let write = || -> io::Result<()> {
+        let file = File::create(output)?;
+        let encoder = zstd::Encoder::new(file, COMPRESSION_LEVEL)?;
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+        append_dir(&mut builder, directory, Path::new(TARGET_PREFIX))?;
+        append_dir(&mut builder, state_dir, Path::new(STATE_PREFIX))?;
+        builder.into_inner()?.finish()?;
+        Ok(())
+    };
+
+    write().map_err(|err| BackupError::write(output.into(), err))
+}
+
+/// Where an entry in a backup should be restored to.
+///
+/// Returns `None` for entries which do not belong in a backup.
+fn restore_path(entry_path: &Path, directory: &Path, state_dir: &Path) -> Option<PathBuf> {
+    let mut components = entry_path.components();
+    let base = match components.next()? {
+        Component::Normal(prefix) if prefix == TARGET_PREFIX => directory,
+        Component::Normal(prefix) if prefix == STATE_PREFIX => state_dir,
+        _ => return None,
+    };
+    // Only allow plain paths so that entries cannot escape the destination.
+    let rest = components
+        .map(|component| {
+            match component {
+                Component::Normal(part) => Some(part),
+                _ => None,
+            }
+        })
+        .collect::<Option<PathBuf>>()?;
+    if rest.as_os_str().is_empty() {
+        return None;
+    }
+
+    Some(base.join(rest))
+}
+
+type Archive = tar::Archive<zstd::Decoder<'static, io::BufReader<File>>>;
+
+fn open(input: &Path) -> io::Result<Archive> {
+    let file = File::open(input)?;
+    Ok(tar::Archive::new(zstd::Decoder::new(file)?))
+}
+
+/// The files in a backup and where they should be restored to.
+fn restore_targets(
+    archive: &mut Archive,
+    directory: &Path,
+    state_dir: &Path,
+) -> io::Result<Vec<Option<PathBuf>>> {
+    archive
+        .entries()?
+        .map(|entry| {
+            let entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                return Ok(None);
+            }
+
+            let entry_path = entry.path()?;
+            let path = restore_path(&entry_path, directory, state_dir);
+            if path.is_none() {
+                warn!("ignoring unexpected backup entry {}", entry_path.display());
+            }
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Restore a target's directory and devtodo's state from a backup.
+///
+/// Existing files are only replaced if `overwrite` is set. Nothing is restored if any file would
+/// be replaced otherwise.
+pub fn restore(
+    input: &Path,
+    directory: &Path,
+    state_dir: &Path,
+    overwrite: bool,
+) -> Result<(), BackupError> {
+    let read_err = |err| BackupError::read(input.into(), err);
+
+    let paths = restore_targets(&mut open(input).map_err(read_err)?, directory, state_dir)
+        .map_err(read_err)?;
+    if !overwrite {
+        if let Some(path) = paths.iter().flatten().find(|path| path.exists()) {
+            return Err(BackupError::would_overwrite(path.clone()));
+        }
+    }
+
+    let mut archive = open(input).map_err(read_err)?;
+    for (entry, path) in archive.entries().map_err(read_err)?.zip(paths) {
+        let mut entry = entry.map_err(read_err)?;
+        let path = if let Some(path) = path {
+            path
+        } else {
+            continue;
+        };
+
+        debug!("restoring {}", path.display());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| BackupError::write(parent.into(), err))?;
+        }
+        entry
+            .unpack(&path)
+            .map_err(|err| BackupError::write(path.clone(), err))?;
+    }
+
+    Ok(())
+}
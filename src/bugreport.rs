@@ -0,0 +1,166 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use serde_yaml::Value;
+use thiserror::Error;
+
+use crate::redact;
+use crate::timing;
+
+#[derive(Debug, Error)]
+#[error("failed to write bug report {}", path.display())]
+pub struct BugReportError {
+    path: PathBuf,
+    source: io::Error,
+}
+
+impl BugReportError {
+    fn new(path: PathBuf, source: io::Error) -> Self {
+        Self {
+            path,
+            source,
+        }
+    }
+}
+
+/// The file in the state directory holding the log of the last sync.
+pub const RUN_LOG_FILE: &str = "last-run.log";
+// The compression level for bug reports.
+const COMPRESSION_LEVEL: i32 = 19;
+// Configuration keys which hold secrets.
+const SECRET_KEYS: &[&str] = &["secret", "tokens"];
+
+/// The features devtodo was built with.
+fn features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "bitbucket") {
+        features.push("bitbucket");
+    }
+    if cfg!(feature = "caldav") {
+        features.push("caldav");
+    }
+    if cfg!(feature = "github") {
+        features.push("github");
+    }
+    if cfg!(feature = "gotify") {
+        features.push("gotify");
+    }
+    if cfg!(feature = "keyring") {
+        features.push("keyring");
+    }
+    if cfg!(feature = "matrix") {
+        features.push("matrix");
+    }
+    if cfg!(feature = "sourcehut") {
+        features.push("sourcehut");
+    }
+    features
+}
+
+/// A description of the build and the system it is running on.
+fn version() -> String {
+    format!(
+        "devtodo {}\nfeatures: {}\nplatform: {}-{}\n",
+        clap::crate_version!(),
+        features().join(", "),
+        env::consts::ARCH,
+        env::consts::OS,
+    )
+}
+
+/// Replace values of secret keys within a configuration document.
+fn sanitize(value: &mut Value) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter_mut() {
+                let is_secret = key.as_str().is_some_and(|key| SECRET_KEYS.contains(&key));
+                if is_secret {
+                    *value = Value::String(redact::REDACTED.into());
+                } else {
+                    sanitize(value);
+                }
+            }
+        },
+        Value::Sequence(values) => values.iter_mut().for_each(sanitize),
+        Value::Tagged(tagged) => sanitize(&mut tagged.value),
+        _ => (),
+    }
+}
+
+/// The configuration with any secrets removed.
+///
+/// Configurations which cannot be parsed are left out since secrets cannot be found reliably.
+fn sanitized_config(contents: &str) -> String {
+    let sanitized = serde_yaml::from_str(contents).and_then(|mut doc: Value| {
+        sanitize(&mut doc);
+        serde_yaml::to_string(&doc)
+    });
+    match sanitized {
+        Ok(config) => redact::redact(&config).into_owned(),
+        Err(err) => format!("# failed to parse the configuration: {}\n", err),
+    }
+}
+
+/// Add a file's contents to an archive.
+fn append_data<W>(builder: &mut tar::Builder<W>, name: &str, data: &[u8]) -> io::Result<()>
+where
+    W: io::Write,
+{
+    debug!("adding {} to the bug report", name);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)
+}
+
+/// Add a file from the state directory to an archive if it exists.
+fn append_state_file<W>(
+    builder: &mut tar::Builder<W>,
+    state_dir: &Path,
+    name: &str,
+) -> io::Result<()>
+where
+    W: io::Write,
+{
+    match fs::read_to_string(state_dir.join(name)) {
+        Ok(contents) => append_data(builder, name, redact::redact(&contents).as_bytes()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Gather information for an issue report into a compressed archive.
+///
+/// The archive contains the version of devtodo, the configuration with secrets removed, and
+/// the log and timing information of the last run.
+pub fn create(config: Option<&str>, state_dir: &Path, output: &Path) -> Result<(), BugReportError> {
+    let write = || -> io::Result<()> {
+        let file = File::create(output)?;
+        let encoder = zstd::Encoder::new(file, COMPRESSION_LEVEL)?;
+        let mut builder = tar::Builder::new(encoder);
+        append_data(&mut builder, "version.txt", version().as_bytes())?;
+        if let Some(config) = config {
+            append_data(
+                &mut builder,
+                "devtodo.yaml",
+                sanitized_config(config).as_bytes(),
+            )?;
+        }
+        append_state_file(&mut builder, state_dir, RUN_LOG_FILE)?;
+        append_state_file(&mut builder, state_dir, timing::TIMING_FILE)?;
+        builder.into_inner()?.finish()?;
+        Ok(())
+    };
+
+    write().map_err(|err| BugReportError::new(output.into(), err))
+}
@@ -0,0 +1,127 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, warn};
+
+/// A cache of service responses kept between runs.
+///
+/// Entries are stored as files under a namespace directory and are only used while they are
+/// younger than the maximum age of the cache.
+pub struct Cache {
+    directory: PathBuf,
+    max_age: Duration,
+}
+
+impl Cache {
+    /// Create a cache within a directory.
+    pub fn new(directory: PathBuf, max_age: Duration) -> Self {
+        Self {
+            directory,
+            max_age,
+        }
+    }
+
+    /// The path to the entry for a key.
+    fn entry_path<K>(&self, namespace: &str, key: &K) -> PathBuf
+    where
+        K: Hash,
+    {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.directory
+            .join(namespace)
+            .join(format!("{:016x}", hasher.finish()))
+    }
+
+    /// Look up an entry in the cache.
+    pub fn get<K>(&self, namespace: &str, key: &K) -> Option<String>
+    where
+        K: Hash,
+    {
+        let path = self.entry_path(namespace, key);
+        let age = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age > self.max_age {
+            return None;
+        }
+
+        debug!("using cached entry {}", path.display());
+        fs::read_to_string(&path).ok()
+    }
+
+    /// Store an entry in the cache.
+    ///
+    /// Failures are only logged since the cache is an optimization.
+    pub fn put<K>(&self, namespace: &str, key: &K, contents: &str)
+    where
+        K: Hash,
+    {
+        let path = self.entry_path(namespace, key);
+        let res = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, contents));
+        if let Err(err) = res {
+            warn!("failed to write cache entry {}: {:?}", path.display(), err);
+        }
+    }
+}
+
+/// Remove all entries from a cache directory.
+pub fn clear(directory: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(directory) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// Remove the oldest entries from a cache directory until it fits within a size limit.
+pub fn prune(directory: &Path, max_size: u64) -> io::Result<()> {
+    let namespaces = match fs::read_dir(directory) {
+        Ok(namespaces) => namespaces,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    let mut entries = Vec::new();
+    for namespace in namespaces {
+        let namespace = namespace?;
+        if !namespace.file_type()?.is_dir() {
+            continue;
+        }
+
+        for entry in fs::read_dir(namespace.path())? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                entries.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+    }
+
+    let mut size = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+    entries.sort();
+    for (_, len, path) in entries {
+        if size <= max_size {
+            break;
+        }
+
+        debug!("pruning cache entry {}", path.display());
+        fs::remove_file(&path)?;
+        size -= len;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,488 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use reqwest::header::{self, HeaderValue};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use thiserror::Error;
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::Caldav;
+use crate::secret::{self, SecretError};
+use crate::state::CaldavResource;
+
+// The request for the entity tags of the resources in a collection.
+const PROPFIND_BODY: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                             <d:propfind xmlns:d=\"DAV:\">\
+                             <d:prop><d:getetag/></d:prop>\
+                             </d:propfind>";
+// The extension of resources holding items.
+const ICS_EXTENSION: &str = ".ics";
+
+#[derive(Debug, Error)]
+pub enum CaldavError {
+    #[error("invalid collection URL {}: {}", url, source)]
+    InvalidUrl {
+        url: String,
+        source: url::ParseError,
+    },
+    #[error("failed to create the http client: {}", source)]
+    BuildClient { source: reqwest::Error },
+    #[error("failed to get the secret")]
+    Secret {
+        #[from]
+        source: SecretError,
+    },
+    #[error("no secret given for the collection")]
+    MissingSecret {},
+    #[error("failed to build a request: {}", source)]
+    BuildRequest { source: reqwest::Error },
+    #[error("failed to send {} request to {}: {}", method, url, source)]
+    SendRequest {
+        method: Method,
+        url: String,
+        source: reqwest::Error,
+    },
+    #[error("{} request to {} failed ({}): {}", method, url, status, body)]
+    Response {
+        method: Method,
+        url: String,
+        status: StatusCode,
+        body: String,
+    },
+    #[error("failed to read the response from {}: {}", url, source)]
+    ReadResponse { url: String, source: reqwest::Error },
+    #[error("failed to read {}: {}", path.display(), source)]
+    ReadFile { path: PathBuf, source: io::Error },
+    #[error("failed to write {}: {}", path.display(), source)]
+    WriteFile { path: PathBuf, source: io::Error },
+    #[error("failed to remove {}: {}", path.display(), source)]
+    RemoveFile { path: PathBuf, source: io::Error },
+}
+
+impl CaldavError {
+    fn invalid_url(url: String, source: url::ParseError) -> Self {
+        CaldavError::InvalidUrl {
+            url,
+            source,
+        }
+    }
+
+    fn build_client(source: reqwest::Error) -> Self {
+        CaldavError::BuildClient {
+            source,
+        }
+    }
+
+    fn build_request(source: reqwest::Error) -> Self {
+        CaldavError::BuildRequest {
+            source,
+        }
+    }
+
+    fn send_request(method: Method, url: String, source: reqwest::Error) -> Self {
+        CaldavError::SendRequest {
+            method,
+            url,
+            source,
+        }
+    }
+
+    fn response(method: Method, url: String, status: StatusCode, body: String) -> Self {
+        CaldavError::Response {
+            method,
+            url,
+            status,
+            body,
+        }
+    }
+
+    fn read_response(url: String, source: reqwest::Error) -> Self {
+        CaldavError::ReadResponse {
+            url,
+            source,
+        }
+    }
+
+    fn read_file(path: PathBuf, source: io::Error) -> Self {
+        CaldavError::ReadFile {
+            path,
+            source,
+        }
+    }
+
+    fn write_file(path: PathBuf, source: io::Error) -> Self {
+        CaldavError::WriteFile {
+            path,
+            source,
+        }
+    }
+
+    fn remove_file(path: PathBuf, source: io::Error) -> Self {
+        CaldavError::RemoveFile {
+            path,
+            source,
+        }
+    }
+}
+
+type CaldavResult<T> = Result<T, CaldavError>;
+
+/// A hash of the contents of a resource.
+fn hash(contents: &str) -> String {
+    format!(
+        "{}",
+        Uuid::new_v5(&Uuid::NAMESPACE_OID, contents.as_bytes()).simple(),
+    )
+}
+
+/// The contents of elements with the given name, ignoring namespace prefixes.
+///
+/// This is only meant for the simple documents in WebDAV responses; nested elements of the same
+/// name are not supported.
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        let after_open = &rest[start + 1..];
+        let end = if let Some(end) = after_open.find('>') {
+            end
+        } else {
+            break;
+        };
+        let tag = &after_open[..end];
+        rest = &after_open[end + 1..];
+
+        // Skip closing tags, declarations, and comments.
+        if tag.starts_with(['/', '?', '!']) {
+            continue;
+        }
+        let tag_name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        if tag_name.rsplit(':').next() != Some(name) {
+            continue;
+        }
+        if tag.ends_with('/') {
+            found.push("");
+            continue;
+        }
+
+        let close = format!("</{}>", tag_name);
+        if let Some(close_idx) = rest.find(&close) {
+            found.push(&rest[..close_idx]);
+            rest = &rest[close_idx + close.len()..];
+        }
+    }
+    found
+}
+
+/// The text of an element with entities replaced.
+fn text(content: &str) -> String {
+    content
+        .trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The name of the file for a resource.
+///
+/// Only `.ics` resources directly within the collection are synced.
+fn file_name(href: &Url) -> Option<String> {
+    href.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| name.ends_with(ICS_EXTENSION) && !name.starts_with('.'))
+        .map(Into::into)
+}
+
+/// A CalDAV collection mirrored by a directory.
+pub struct Collection {
+    client: Client,
+    url: Url,
+    username: String,
+    password: String,
+}
+
+impl Collection {
+    pub fn new(caldav: &Caldav) -> CaldavResult<Self> {
+        // Make sure that joining names onto the URL keeps its path.
+        let url = Url::parse(&format!("{}/", caldav.url.trim_end_matches('/')))
+            .map_err(|err| CaldavError::invalid_url(caldav.url.clone(), err))?;
+        let host = url.host_str().unwrap_or_default();
+        let password = secret::resolve(&caldav.secret, host)?
+            .into_iter()
+            .next()
+            .ok_or(CaldavError::MissingSecret {})?;
+
+        let mut builder = Client::builder();
+        if let Some(timeout) = caldav.timeout.and_then(|timeout| timeout.0.to_std().ok()) {
+            builder = builder.timeout(timeout);
+        }
+        let client = builder.build().map_err(CaldavError::build_client)?;
+
+        Ok(Self {
+            client,
+            url,
+            username: caldav.username.clone(),
+            password,
+        })
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        self.client
+            .request(method, url)
+            .basic_auth(&self.username, Some(&self.password))
+    }
+
+    /// Send a request and read the response.
+    ///
+    /// Returns the status, entity tag, and body of the response. Statuses other than those
+    /// given are errors.
+    async fn send(
+        &self,
+        request: RequestBuilder,
+        allowed: &[StatusCode],
+    ) -> CaldavResult<(StatusCode, Option<String>, String)> {
+        let request = request.build().map_err(CaldavError::build_request)?;
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        let rsp = self
+            .client
+            .execute(request)
+            .await
+            .map_err(|err| CaldavError::send_request(method.clone(), url.clone(), err))?;
+        let status = rsp.status();
+        let etag = rsp
+            .headers()
+            .get(header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(Into::into);
+        let body = rsp
+            .text()
+            .await
+            .map_err(|err| CaldavError::read_response(url.clone(), err))?;
+
+        if status.is_success() || allowed.contains(&status) {
+            Ok((status, etag, body))
+        } else {
+            Err(CaldavError::response(method, url, status, body))
+        }
+    }
+
+    /// The entity tags of the resources in the collection (by file name).
+    async fn list(&self) -> CaldavResult<BTreeMap<String, (Url, String)>> {
+        let method = Method::from_bytes(b"PROPFIND").expect("PROPFIND is a valid method");
+        let request = self
+            .request(method, self.url.clone())
+            .header("Depth", "1")
+            .header(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/xml; charset=utf-8"),
+            )
+            .body(PROPFIND_BODY);
+        let (_, _, body) = self.send(request, &[]).await?;
+
+        Ok(elements(&body, "response")
+            .into_iter()
+            .filter_map(|response| {
+                let href = elements(response, "href").into_iter().next()?;
+                let href = self.url.join(&text(href)).ok()?;
+                let name = file_name(&href)?;
+                let etag = elements(response, "getetag").into_iter().next()?;
+                Some((name, (href, text(etag))))
+            })
+            .collect())
+    }
+
+    /// Bring changes made on the server into the directory.
+    ///
+    /// `known` holds the resources as of the last sync and is updated.
+    pub async fn pull(
+        &self,
+        dir: &Path,
+        known: &mut BTreeMap<String, CaldavResource>,
+    ) -> CaldavResult<()> {
+        let remote = self.list().await?;
+
+        for (name, (href, etag)) in &remote {
+            let previous = known.get(name);
+            if previous.is_some_and(|previous| &previous.etag == etag) {
+                continue;
+            }
+
+            info!("fetching {} from {}", name, self.url);
+            let request = self.request(Method::GET, href.clone());
+            let (_, _, contents) = self.send(request, &[]).await?;
+
+            let path = dir.join(name);
+            if let Some(previous) = previous {
+                let local = fs::read_to_string(&path).ok();
+                if local.is_some_and(|local| hash(&local) != previous.hash) {
+                    warn!(
+                        "{} changed both locally and on the server; using the server's copy",
+                        path.display(),
+                    );
+                }
+            }
+            fs::create_dir_all(dir).map_err(|err| CaldavError::write_file(dir.into(), err))?;
+            fs::write(&path, contents.as_bytes())
+                .map_err(|err| CaldavError::write_file(path, err))?;
+            known.insert(
+                name.clone(),
+                CaldavResource {
+                    etag: etag.clone(),
+                    hash: hash(&contents),
+                },
+            );
+        }
+
+        // Remove files for resources which have been removed from the server.
+        let removed = known
+            .keys()
+            .filter(|name| !remote.contains_key(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in removed {
+            let previous = known
+                .remove(&name)
+                .expect("removed resources should be known");
+            let path = dir.join(&name);
+            let local = match fs::read_to_string(&path) {
+                Ok(local) => local,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(CaldavError::read_file(path, err)),
+            };
+            // Keep files with local changes; they are sent to the server again.
+            if hash(&local) != previous.hash {
+                continue;
+            }
+            info!("removing {} as it was removed from {}", name, self.url);
+            fs::remove_file(&path).map_err(|err| CaldavError::remove_file(path, err))?;
+        }
+
+        Ok(())
+    }
+
+    /// Send changes made in the directory to the server.
+    ///
+    /// `known` holds the resources as of the last sync and is updated.
+    pub async fn push(
+        &self,
+        dir: &Path,
+        known: &mut BTreeMap<String, CaldavResource>,
+    ) -> CaldavResult<()> {
+        let mut local = BTreeMap::new();
+        let entries = fs::read_dir(dir).map_err(|err| CaldavError::read_file(dir.into(), err))?;
+        for entry in entries {
+            let entry = entry.map_err(|err| CaldavError::read_file(dir.into(), err))?;
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.ends_with(ICS_EXTENSION) || name.starts_with('.') {
+                continue;
+            }
+            let contents =
+                fs::read_to_string(&path).map_err(|err| CaldavError::read_file(path, err))?;
+            local.insert(name, contents);
+        }
+
+        for (name, contents) in &local {
+            let contents_hash = hash(contents);
+            let previous = known.get(name);
+            if previous.is_some_and(|previous| previous.hash == contents_hash) {
+                continue;
+            }
+
+            let url = self
+                .url
+                .join(name)
+                .map_err(|err| CaldavError::invalid_url(name.clone(), err))?;
+            let request = self
+                .request(Method::PUT, url)
+                .header(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/calendar; charset=utf-8"),
+                )
+                .body(contents.clone());
+            // Avoid overwriting changes made on the server since the last sync.
+            let request = match previous {
+                Some(previous) if !previous.etag.is_empty() => {
+                    request.header(header::IF_MATCH, &previous.etag)
+                },
+                Some(_) => request,
+                None => request.header(header::IF_NONE_MATCH, "*"),
+            };
+            let (status, etag, _) = self
+                .send(request, &[StatusCode::PRECONDITION_FAILED])
+                .await?;
+            if status == StatusCode::PRECONDITION_FAILED {
+                warn!(
+                    "{} changed on the server; it will be fetched on the next sync",
+                    name,
+                );
+                continue;
+            }
+
+            info!("sending {} to {}", name, self.url);
+            known.insert(
+                name.clone(),
+                CaldavResource {
+                    // Without an entity tag, the resource is fetched again on the next sync.
+                    etag: etag.unwrap_or_default(),
+                    hash: contents_hash,
+                },
+            );
+        }
+
+        // Remove resources whose files have been removed.
+        let removed = known
+            .keys()
+            .filter(|name| !local.contains_key(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in removed {
+            let url = self
+                .url
+                .join(&name)
+                .map_err(|err| CaldavError::invalid_url(name.clone(), err))?;
+            let etag = &known[&name].etag;
+            let mut request = self.request(Method::DELETE, url);
+            if !etag.is_empty() {
+                request = request.header(header::IF_MATCH, etag);
+            }
+            let (status, _, _) = self
+                .send(
+                    request,
+                    &[StatusCode::NOT_FOUND, StatusCode::PRECONDITION_FAILED],
+                )
+                .await?;
+            if status == StatusCode::PRECONDITION_FAILED {
+                warn!(
+                    "{} changed on the server; it will be fetched on the next sync",
+                    name,
+                );
+                continue;
+            }
+
+            info!("removing {} from {}", name, self.url);
+            known.remove(&name);
+        }
+
+        Ok(())
+    }
+}
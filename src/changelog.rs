@@ -0,0 +1,245 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::todo::{Due, TodoItem, TodoStatus};
+
+#[derive(Debug, Error)]
+pub enum ChangeLogError {
+    #[error("failed to read change log {}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to parse line {} of change log {}", line, path.display())]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("failed to write change log {}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+}
+
+impl ChangeLogError {
+    fn read(path: PathBuf, source: io::Error) -> Self {
+        Self::Read {
+            path,
+            source,
+        }
+    }
+
+    fn parse(path: PathBuf, line: usize, source: serde_json::Error) -> Self {
+        Self::Parse {
+            path,
+            line,
+            source,
+        }
+    }
+
+    fn write(path: PathBuf, source: io::Error) -> Self {
+        Self::Write {
+            path,
+            source,
+        }
+    }
+}
+
+const CHANGE_LOG_FILE: &str = "changes.jsonl";
+
+/// A change made to an item.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    Created,
+    StatusChanged {
+        from: String,
+        to: String,
+    },
+    DueChanged {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    /// The item moved on the service (e.g., it was transferred to another repository).
+    Moved {
+        from: String,
+    },
+    Completed,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Created => write!(f, "created"),
+            Change::StatusChanged {
+                from,
+                to,
+            } => write!(f, "status {} -> {}", from, to),
+            Change::DueChanged {
+                from,
+                to,
+            } => {
+                write!(
+                    f,
+                    "due {} -> {}",
+                    from.as_deref().unwrap_or("(none)"),
+                    to.as_deref().unwrap_or("(none)"),
+                )
+            },
+            Change::Moved {
+                from,
+            } => write!(f, "moved from {}", from),
+            Change::Completed => write!(f, "completed"),
+        }
+    }
+}
+
+/// An entry in the change log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Utc>,
+    pub target: String,
+    pub url: String,
+    pub summary: String,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+impl Entry {
+    fn new(target: &str, item: &TodoItem, change: Change, time: DateTime<Utc>) -> Self {
+        Self {
+            time,
+            target: target.into(),
+            url: item.url().into(),
+            summary: item.summary().into(),
+            change,
+        }
+    }
+
+    /// The entries for a newly created item.
+    pub fn created(target: &str, item: &TodoItem, time: DateTime<Utc>) -> Vec<Self> {
+        let mut entries = vec![Self::new(target, item, Change::Created, time)];
+        if item.status() == TodoStatus::Completed {
+            entries.push(Self::new(target, item, Change::Completed, time));
+        }
+        entries
+    }
+
+    /// The entries for changes between two snapshots of an item.
+    pub fn changed(
+        target: &str,
+        old: &Snapshot,
+        item: &TodoItem,
+        time: DateTime<Utc>,
+    ) -> Vec<Self> {
+        let mut entries = Vec::new();
+
+        if old.url != item.url() {
+            let change = Change::Moved {
+                from: old.url.clone(),
+            };
+            entries.push(Self::new(target, item, change, time));
+        }
+        if old.status != item.status() {
+            let change = if item.status() == TodoStatus::Completed {
+                Change::Completed
+            } else {
+                Change::StatusChanged {
+                    from: old.status.as_ref().into(),
+                    to: item.status().as_ref().into(),
+                }
+            };
+            entries.push(Self::new(target, item, change, time));
+        }
+        if old.due != item.due() {
+            let change = Change::DueChanged {
+                from: old.due.map(|due| due.to_string()),
+                to: item.due().map(|due| due.to_string()),
+            };
+            entries.push(Self::new(target, item, change, time));
+        }
+
+        entries
+    }
+}
+
+/// The state of an item which is tracked in the change log.
+pub struct Snapshot {
+    url: String,
+    status: TodoStatus,
+    due: Option<Due>,
+}
+
+impl Snapshot {
+    pub fn new(item: &TodoItem) -> Self {
+        Self {
+            url: item.url().into(),
+            status: item.status(),
+            due: item.due(),
+        }
+    }
+
+    /// Whether an item has been completed since the snapshot was taken.
+    pub fn is_completed_by(&self, item: &TodoItem) -> bool {
+        self.status != TodoStatus::Completed && item.status() == TodoStatus::Completed
+    }
+}
+
+/// Append entries to the change log in a directory.
+pub fn append(dir: &Path, entries: &[Entry]) -> Result<(), ChangeLogError> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir).map_err(|err| ChangeLogError::write(dir.into(), err))?;
+    let path = dir.join(CHANGE_LOG_FILE);
+    let mut contents = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut contents, entry)
+            .expect("change log entries should always be serializable");
+        contents.push(b'\n');
+    }
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&contents))
+        .map_err(|err| ChangeLogError::write(path, err))
+}
+
+/// Read the entries of the change log in a directory.
+///
+/// Only entries at or after `since` are returned if given.
+pub fn read(dir: &Path, since: Option<DateTime<Utc>>) -> Result<Vec<Entry>, ChangeLogError> {
+    let path = dir.join(CHANGE_LOG_FILE);
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(ChangeLogError::read(path, err)),
+    };
+
+    let mut entries = Vec::new();
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| ChangeLogError::read(path.clone(), err))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry: Entry = serde_json::from_str(&line)
+            .map_err(|err| ChangeLogError::parse(path.clone(), idx + 1, err))?;
+        if since.is_none_or(|since| entry.time >= since) {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,135 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+use log::{debug, warn};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::config::Hook;
+use crate::todo::TodoItem;
+
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error("hook has no command")]
+    NoCommand {},
+    #[error("failed to run hook {}", command)]
+    Run { command: String, source: io::Error },
+    #[error("hook {} failed: {}", command, status)]
+    Failed { command: String, status: ExitStatus },
+}
+
+impl HookError {
+    fn no_command() -> Self {
+        HookError::NoCommand {}
+    }
+
+    fn run(command: String, source: io::Error) -> Self {
+        HookError::Run {
+            command,
+            source,
+        }
+    }
+
+    fn failed(command: String, status: ExitStatus) -> Self {
+        HookError::Failed {
+            command,
+            status,
+        }
+    }
+}
+
+/// An item in a change set.
+#[derive(Debug, Serialize)]
+pub struct ChangedItem {
+    /// The target holding the item.
+    pub target: String,
+    /// The UID of the item.
+    ///
+    /// This does not change for the lifetime of the item's file.
+    pub uid: String,
+    pub url: String,
+    pub summary: String,
+    pub kind: String,
+    pub status: String,
+}
+
+impl ChangedItem {
+    pub fn new(target: &str, item: &TodoItem) -> Self {
+        Self {
+            target: target.into(),
+            uid: item.uid().into(),
+            url: item.url().into(),
+            summary: item.summary().into(),
+            kind: item.kind().as_ref().into(),
+            status: item.status().as_ref().into(),
+        }
+    }
+}
+
+/// The items changed by a run.
+#[derive(Debug, Default, Serialize)]
+pub struct ChangeSet {
+    /// Items which were created.
+    pub new: Vec<ChangedItem>,
+    /// Items which were updated without being completed.
+    pub updated: Vec<ChangedItem>,
+    /// Items which were completed on their service.
+    pub completed: Vec<ChangedItem>,
+    /// Open items which were not fetched from their service.
+    pub stale: Vec<ChangedItem>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.new.is_empty()
+            && self.updated.is_empty()
+            && self.completed.is_empty()
+            && self.stale.is_empty()
+    }
+
+    /// Write the change set as JSON.
+    pub fn write<W>(&self, out: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        serde_json::to_writer(&mut *out, self)?;
+        writeln!(out)
+    }
+}
+
+/// Run a hook with the change set on its standard input.
+pub fn run_hook(hook: &Hook, changes: &ChangeSet) -> Result<(), HookError> {
+    let (program, args) = hook
+        .command
+        .split_first()
+        .ok_or_else(HookError::no_command)?;
+    let command = hook.command.join(" ");
+
+    debug!("running hook {}", command);
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| HookError::run(command.clone(), err))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(err) = changes.write(&mut stdin) {
+            // The hook may not care about the changes.
+            warn!("failed to send changes to hook {}: {}", command, err);
+        }
+    }
+    let status = child
+        .wait()
+        .map_err(|err| HookError::run(command.clone(), err))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(HookError::failed(command, status))
+    }
+}
@@ -0,0 +1,64 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::account::ItemLookup;
+use crate::config::Chore;
+use crate::todo::{Recurrence, TodoItem, TodoKind, TodoStatus};
+
+/// The URL used to identify a chore within a target.
+///
+/// Chores do not have a URL on any service, so one is made up to track the item.
+// The prefix of URLs given to chores.
+const URL_PREFIX: &str = "urn:devtodo:chore:";
+
+fn url(name: &str) -> String {
+    format!("{}{}", URL_PREFIX, name)
+}
+
+/// Whether an item URL refers to a chore.
+pub fn is_chore(url: &str) -> bool {
+    url.starts_with(URL_PREFIX)
+}
+
+/// Materialize chores into items.
+///
+/// Existing chores have their summary, description, and recurrence updated. Their status is left
+/// alone as it is managed by the calendar client. Returns any newly created items.
+pub fn materialize<'a, I>(chores: I, existing_items: &mut ItemLookup) -> Vec<TodoItem>
+where
+    I: IntoIterator<Item = (&'a String, &'a Chore)>,
+{
+    chores
+        .into_iter()
+        .filter_map(|(name, chore)| {
+            let url = url(name);
+            let recurrence = Recurrence {
+                rule: chore.rrule.clone(),
+                start: chore.start,
+            };
+
+            if let Some(item) = existing_items.get_mut(&url) {
+                item.set_summary(chore.summary.clone());
+                item.set_description(chore.description.clone());
+                item.set_recurrence(recurrence);
+
+                None
+            } else {
+                let item = TodoItem::builder()
+                    .kind(TodoKind::Todo)
+                    .status(TodoStatus::NeedsAction)
+                    .url(url)
+                    .summary(chore.summary.clone())
+                    .description(chore.description.clone())
+                    .recurrence(recurrence)
+                    .build()
+                    .expect("all item fields should be provided");
+
+                Some(item)
+            }
+        })
+        .collect()
+}
@@ -5,52 +5,1166 @@
 // except according to those terms.
 
 use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use serde::Deserialize;
+use chrono::{Duration, NaiveDate, NaiveTime};
+use directories::BaseDirs;
+use itertools::Itertools;
+use log::LevelFilter;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use serde_yaml::Value;
+use thiserror::Error;
+
+use crate::redact;
+use crate::todo::{self, TodoKind, TodoStatus};
+
+mod migrate;
+
+pub use self::migrate::{migrate, MigrateError, CONFIG_VERSION};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub accounts: BTreeMap<String, Account>,
+    /// Names which refer to a list of accounts.
+    ///
+    /// Profiles using an alias fetch items from each of its accounts.
+    #[serde(default)]
+    pub account_aliases: BTreeMap<String, Vec<String>>,
     #[serde(default)]
     pub targets: BTreeMap<String, SyncTarget>,
     #[serde(default)]
     pub default_targets: Vec<String>,
+    /// Rules sending fetched items to other targets.
+    ///
+    /// The first matching route is used.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub serve: ServeConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Commands to run after syncing with the changes made on their standard input.
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+    /// Services to notify about the changes made by syncing.
+    #[serde(default)]
+    pub notifications: Vec<Notification>,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error(
+        "the CalDAV collection of the {} target cannot be used with {}",
+        target,
+        setting
+    )]
+    CaldavConflict {
+        target: String,
+        setting: &'static str,
+    },
+}
+
+impl ConfigError {
+    fn caldav_conflict(target: String, setting: &'static str) -> Self {
+        ConfigError::CaldavConflict {
+            target,
+            setting,
+        }
+    }
+}
+
+impl Config {
+    /// Check settings which conflict with each other.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (name, target) in &self.targets {
+            // Only the target directory itself is synced with the server; items elsewhere
+            // would be deleted from the server.
+            if target.caldav.is_some() {
+                if !target.collections.is_empty() {
+                    return Err(ConfigError::caldav_conflict(name.clone(), "collections"));
+                }
+                let archives = target
+                    .retention
+                    .as_ref()
+                    .is_some_and(|retention| retention.action == RetentionAction::Archive);
+                if archives {
+                    return Err(ConfigError::caldav_conflict(
+                        name.clone(),
+                        "archiving retention",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A service to notify about the changes made by syncing.
+// Settings are only read by the services devtodo is built with.
+#[cfg_attr(not(any(feature = "gotify", feature = "matrix")), allow(dead_code))]
+#[derive(Debug, Deserialize)]
+pub struct Notification {
+    /// The kind of service (e.g., `matrix` or `gotify`).
+    pub service: String,
+    /// The base URL of the service (e.g., the Matrix homeserver).
+    pub url: String,
+    /// The room to post to (Matrix).
+    #[cfg_attr(not(feature = "matrix"), allow(dead_code))]
+    #[serde(default)]
+    pub room: Option<String>,
+    /// The access token for the service.
+    ///
+    /// For Gotify, this is an application token.
+    pub secret: Secrets,
+    /// The priority of messages (Gotify).
+    #[cfg_attr(not(feature = "gotify"), allow(dead_code))]
+    #[serde(default)]
+    pub priority: Option<i64>,
+    /// The kinds of changes to notify about.
+    ///
+    /// If empty, new, updated, and completed items are included.
+    #[serde(default)]
+    pub changes: Vec<ChangeCategory>,
+    /// How long to wait for a response from the service.
+    #[serde(default)]
+    pub timeout: Option<HumanDuration>,
+}
+
+/// A kind of change made by syncing.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeCategory {
+    #[serde(rename = "new")]
+    New,
+    #[serde(rename = "updated")]
+    Updated,
+    #[serde(rename = "completed")]
+    Completed,
+    #[serde(rename = "stale")]
+    Stale,
+}
+
+impl ChangeCategory {
+    /// The changes notified about unless others are requested.
+    pub const DEFAULT: &'static [Self] = &[Self::New, Self::Updated, Self::Completed];
+}
+
+/// A command run after syncing.
+///
+/// The changes made by the run are given as JSON on its standard input. Hooks are only run
+/// if there were changes.
+#[derive(Debug, Deserialize)]
+pub struct Hook {
+    /// The program and its arguments.
+    pub command: Vec<String>,
+}
+
+/// Default logging settings.
+///
+/// Command line flags take precedence over these settings.
+#[derive(Debug, Default, Deserialize)]
+pub struct LoggingConfig {
+    /// The logging backend to use.
+    #[serde(default)]
+    pub logger: Option<String>,
+    /// The default level to log at.
+    #[serde(default)]
+    pub level: Option<LogLevel>,
+    /// Levels to use for specific modules (e.g., `devtodo::account`).
+    #[serde(default)]
+    pub filters: BTreeMap<String, LogLevel>,
+}
+
+/// A rule sending items to a target.
+#[derive(Debug, Deserialize)]
+pub struct Route {
+    /// A pattern for the repository (e.g., `owner/*`) or URL of items.
+    ///
+    /// `*` matches any sequence of characters. Patterns containing `://` are matched against
+    /// URLs.
+    pub pattern: String,
+    /// The target to send matching items to.
+    pub target: String,
 }
 
+/// Configuration for serving calendar feeds over HTTP.
 #[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ServeConfig {
+    /// The address to listen on.
+    pub listen: String,
+    /// Tokens which grant access to the feeds.
+    ///
+    /// Tokens may be given as a bearer token or in a `token` query parameter. Feeds are
+    /// available to anyone who can connect if no tokens are given.
+    pub tokens: Vec<String>,
+    /// How long ago targets may have last been synced before `/healthz` reports a failure.
+    ///
+    /// Only the age of the last sync is reported if not given.
+    pub max_sync_age: Option<HumanDuration>,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:8080".into(),
+            tokens: Vec::new(),
+            max_sync_age: None,
+        }
+    }
+}
+
+/// Configuration for the cache of service responses.
+#[derive(Debug, Default, Deserialize)]
+pub struct CacheConfig {
+    /// How long responses may be reused for.
+    ///
+    /// Responses are not cached if not given.
+    #[serde(default)]
+    pub max_age: Option<HumanDuration>,
+    /// The maximum size of the cache directory in bytes.
+    #[serde(default)]
+    pub max_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct Account {
     pub service: String,
     #[serde(default)]
     pub hostname: Option<String>,
-    pub secret: String,
+    /// The path to the API endpoint on the host.
+    ///
+    /// If not given, a path is chosen based on the service and the hostname.
+    #[serde(default)]
+    pub api_path: Option<String>,
+    /// Whether to communicate with the service over plain HTTP.
+    ///
+    /// Only intended for self-hosted instances on trusted networks.
+    #[serde(default)]
+    pub insecure_http: bool,
+    /// The secret to use for the account.
+    ///
+    /// A list of secrets may be given; they are rotated between when one is rate limited or
+    /// rejected.
+    pub secret: Secrets,
+    /// The maximum number of requests to make to the service in a single run.
+    #[serde(default)]
+    pub max_requests_per_run: Option<usize>,
+    /// How to retry requests which fail due to service errors.
+    #[serde(default)]
+    pub backoff: BackoffPolicy,
+    /// How long to wait for a response from the service.
+    #[serde(default)]
+    pub timeout: Option<HumanDuration>,
+    /// The proxy to use for requests to the service (e.g., `http://proxy:3128`).
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Other identities on the service grouped under the account.
+    ///
+    /// Each has its own secrets (e.g., a token for another user's account) and is used by
+    /// profiles which name it with `as_user`. Everything else is shared with the account.
+    #[serde(default)]
+    pub users: BTreeMap<String, Secrets>,
+}
+
+// Environment variables describing a configuration without a file.
+const ENV_SERVICE: &str = "DEVTODO_SERVICE";
+const ENV_HOSTNAME: &str = "DEVTODO_HOSTNAME";
+const ENV_TOKEN: &str = "DEVTODO_TOKEN";
+const ENV_DIRECTORY: &str = "DEVTODO_DIRECTORY";
+const ENV_QUERY: &str = "DEVTODO_QUERY";
+// The name used for the account, target, and profile of configurations from the environment.
+const ENV_NAME: &str = "env";
+
+/// A mapping with a single entry using the name for configurations from the environment.
+fn env_entry(value: serde_yaml::Mapping) -> Value {
+    let mut mapping = serde_yaml::Mapping::new();
+    mapping.insert(ENV_NAME.into(), value.into());
+    mapping.into()
+}
+
+/// A configuration document built from environment variables.
+///
+/// `DEVTODO_TOKEN` and `DEVTODO_DIRECTORY` are required. `DEVTODO_SERVICE` defaults to
+/// `github` and `DEVTODO_QUERY` to `self`; `DEVTODO_HOSTNAME` may be used for self-hosted
+/// instances. The document has a single account, target, and profile.
+pub fn from_env() -> Option<String> {
+    let var = |name| {
+        env::var(name)
+            .ok()
+            .filter(|value: &String| !value.is_empty())
+    };
+    let token = var(ENV_TOKEN)?;
+    let directory = var(ENV_DIRECTORY)?;
+
+    let mut account = serde_yaml::Mapping::new();
+    account.insert(
+        "service".into(),
+        var(ENV_SERVICE).unwrap_or_else(|| "github".into()).into(),
+    );
+    if let Some(hostname) = var(ENV_HOSTNAME) {
+        account.insert("hostname".into(), hostname.into());
+    }
+    account.insert("secret".into(), token.into());
+
+    let mut profile = serde_yaml::Mapping::new();
+    profile.insert("account".into(), ENV_NAME.into());
+    profile.insert(
+        "target".into(),
+        var(ENV_QUERY).unwrap_or_else(|| "self".into()).into(),
+    );
+
+    let mut target = serde_yaml::Mapping::new();
+    target.insert("directory".into(), directory.into());
+    // Containers usually start with an empty volume.
+    target.insert("create_directory".into(), true.into());
+    target.insert("profiles".into(), env_entry(profile));
+
+    let mut doc = serde_yaml::Mapping::new();
+    // The document is generated using the current layout.
+    doc.insert(migrate::VERSION_KEY.into(), CONFIG_VERSION.into());
+    doc.insert(ACCOUNTS_KEY.into(), env_entry(account));
+    doc.insert("targets".into(), env_entry(target));
+    doc.insert("default_targets".into(), vec![ENV_NAME].into());
+
+    Some(serde_yaml::to_string(&doc).expect("configuration documents should be serializable"))
+}
+
+const ACCOUNT_DEFAULTS_KEY: &str = "account_defaults";
+const ACCOUNTS_KEY: &str = "accounts";
+
+/// Fill in account settings from the `account_defaults` block of a configuration document.
+///
+/// Settings given for an account replace the default entirely (i.e., mappings are not merged).
+pub fn apply_account_defaults(doc: &mut Value) {
+    let mapping = if let Some(mapping) = doc.as_mapping_mut() {
+        mapping
+    } else {
+        return;
+    };
+    let defaults = match mapping.remove(ACCOUNT_DEFAULTS_KEY) {
+        Some(Value::Mapping(defaults)) => defaults,
+        _ => return,
+    };
+    let accounts = mapping
+        .get_mut(ACCOUNTS_KEY)
+        .and_then(Value::as_mapping_mut)
+        .into_iter()
+        .flat_map(|accounts| accounts.iter_mut())
+        .filter_map(|(_, account)| account.as_mapping_mut());
+    for account in accounts {
+        for (key, value) in &defaults {
+            if !account.contains_key(key) {
+                account.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// A policy for retrying failed requests with exponential backoff.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackoffPolicy {
+    /// The maximum number of attempts for a request (at least 1).
+    #[serde(deserialize_with = "deserialize_attempts")]
+    pub limit: usize,
+    /// The delay before the first retry.
+    pub start: HumanDuration,
+    /// How much to scale the delay after each retry (at least 1).
+    #[serde(deserialize_with = "deserialize_scale")]
+    pub scale: u32,
+    /// The maximum fraction of the delay to randomly add to each delay.
+    pub jitter: f64,
+    /// The maximum amount of time to spend retrying a request.
+    pub max_elapsed: Option<HumanDuration>,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            limit: 5,
+            start: HumanDuration(Duration::seconds(1)),
+            scale: 2,
+            jitter: 0.,
+            max_elapsed: None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SyncTarget {
+    /// The directory to write items into.
+    ///
+    /// Not required for report targets. A leading `~` and environment variables (e.g.,
+    /// `$XDG_DATA_HOME`) are expanded.
+    #[serde(default, deserialize_with = "deserialize_path")]
     pub directory: PathBuf,
+    /// Report on items rather than writing them to the directory.
+    ///
+    /// JSON reports are followed by a `changes` record with the changes made to other targets
+    /// during the run.
+    #[serde(default)]
+    pub report: Option<ReportFormat>,
+    /// A CalDAV collection to keep in sync with the directory (requires the `caldav` feature).
+    ///
+    /// Changes made on the server are brought into the directory before syncing and changes to
+    /// the directory are sent to the server afterwards. Targets using collections or archiving
+    /// expired items may not use one since their subdirectories are not synced.
+    #[serde(default)]
+    pub caldav: Option<Caldav>,
     pub profiles: BTreeMap<String, Profile>,
+    /// The maximum length (in bytes) of item descriptions.
+    #[serde(default)]
+    pub max_description_length: Option<usize>,
+    /// A footer to append to item descriptions.
+    ///
+    /// The `{url}` and `{time}` placeholders are replaced by the item's URL and the time the item
+    /// was last updated on the service.
+    #[serde(default)]
+    pub description_footer: Option<String>,
+    /// Rules for escalating items as their due dates approach.
+    #[serde(default)]
+    pub escalation: Vec<EscalationRule>,
+    /// Recurring local todo items.
+    #[serde(default)]
+    pub chores: BTreeMap<String, Chore>,
+    /// How long to keep completed and cancelled items.
+    ///
+    /// Expired items are handled while syncing unless `--no-prune` is given.
+    #[serde(default)]
+    pub retention: Option<Retention>,
+    /// How UIDs are chosen for new items.
+    #[serde(default)]
+    pub uid_scheme: UidScheme,
+    /// Collection metadata for the target directory.
+    #[serde(default)]
+    pub metadata: VdirMetadata,
+    /// Subdirectories to sort items into by their kind.
+    #[serde(default)]
+    pub collections: BTreeMap<String, Collection>,
+    /// Write files by renaming a new file over the old one.
+    ///
+    /// This keeps tools reading the directory concurrently from seeing partially written files.
+    #[serde(default)]
+    pub atomic_writes: bool,
+    /// Create the directory if it does not exist.
+    #[serde(default)]
+    pub create_directory: bool,
+    /// `PRODID` prefixes of other tools' files to manage as if devtodo wrote them.
+    ///
+    /// Useful for files written by forks or predecessors of devtodo. Items in these files
+    /// which do not record their kind are given the `unknown` kind.
+    #[serde(default)]
+    pub claim_prodids: Vec<String>,
+    /// Properties of items which devtodo must never change.
+    ///
+    /// Useful for properties other clients edit (e.g., `PRIORITY` or `CATEGORIES`).
+    #[serde(default)]
+    pub protected_properties: Vec<String>,
+    /// Hold a `.lock` file in the directory while syncing.
+    ///
+    /// The target is skipped if the lock is already held by another tool.
+    #[serde(default)]
+    pub lock: bool,
+    /// Drop due dates from items which are blocked by other items.
+    ///
+    /// Avoids alarms for items which cannot be worked on yet.
+    #[serde(default)]
+    pub suppress_blocked_due: bool,
+    /// Write due dates without a time as this local time of day (e.g., `17:00`).
+    ///
+    /// Some clients treat due dates as midnight and alarm at the start of the day. Without a
+    /// value, they are written as dates.
+    #[serde(default)]
+    pub due_time: Option<TimeOfDay>,
+    /// The status to use for open items created by the user but assigned to others.
+    ///
+    /// Such items are also given the `delegated` category.
+    #[serde(default)]
+    pub delegated_status: Option<TodoStatus>,
+    /// Turn labels on items into categories.
+    ///
+    /// Labels are not added as categories if not given.
+    #[serde(default)]
+    pub label_categories: Option<LabelCategories>,
+    /// Add the milestone of items as a category.
+    ///
+    /// The milestone is always available in the `X-DEVTODO-MILESTONE` property.
+    #[serde(default)]
+    pub milestone_categories: bool,
+    /// Expand task lists (e.g., `- [ ] task`) in item descriptions into subtasks.
+    ///
+    /// Subtasks are related to their item and are completed when their entry is checked.
+    #[serde(default)]
+    pub subtasks: bool,
+}
+
+impl SyncTarget {
+    /// The directory items of a given kind belong in.
+    pub fn directory_for(&self, kind: TodoKind) -> PathBuf {
+        self.collections
+            .iter()
+            .find(|(_, collection)| collection.kinds.contains(&kind))
+            .map_or_else(
+                || self.directory.clone(),
+                |(name, _)| self.directory.join(name),
+            )
+    }
+}
+
+/// A CalDAV collection on a server.
+#[cfg_attr(not(feature = "caldav"), allow(dead_code))]
+#[derive(Debug, Deserialize)]
+pub struct Caldav {
+    /// The URL of the collection.
+    pub url: String,
+    /// The user to authenticate as.
+    pub username: String,
+    /// The password for the user.
+    pub secret: Secrets,
+    /// How long to wait for a response from the server.
+    #[serde(default)]
+    pub timeout: Option<HumanDuration>,
+}
+
+/// A subdirectory of a target containing items of specific kinds.
+#[derive(Debug, Deserialize)]
+pub struct Collection {
+    /// The kinds of items which belong in the collection.
+    pub kinds: Vec<TodoKind>,
+    /// Collection metadata for the subdirectory.
+    #[serde(default)]
+    pub metadata: VdirMetadata,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[serde(rename = "text")]
+    Text,
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "markdown")]
+    Markdown,
+}
+
+/// How labels on items are turned into categories.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LabelCategories {
+    /// A prefix for each category (e.g., `label:`).
+    pub prefix: String,
+    /// Remove emoji and other non-ASCII symbols from labels.
+    pub strip_emoji: bool,
+    /// The replacement for runs of whitespace and separators (`,`, `;`, and `\`) in labels.
+    pub replacement: String,
+}
+
+impl Default for LabelCategories {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            strip_emoji: false,
+            replacement: "-".into(),
+        }
+    }
+}
+
+impl LabelCategories {
+    /// The category for a label.
+    ///
+    /// Returns `None` if nothing is left of the label after sanitizing it.
+    pub fn category(&self, label: &str) -> Option<String> {
+        let label = if self.strip_emoji {
+            label
+                .chars()
+                .filter(|c| c.is_ascii() || c.is_alphanumeric())
+                .collect()
+        } else {
+            label.to_string()
+        };
+        let category = label
+            .split(|c: char| c.is_whitespace() || ",;\\".contains(c))
+            .filter(|part| !part.is_empty())
+            .join(&self.replacement);
+
+        if category.is_empty() {
+            None
+        } else {
+            Some(format!("{}{}", self.prefix, category))
+        }
+    }
+}
+
+/// Metadata for a `vdir` collection.
+#[derive(Debug, Default, Deserialize)]
+pub struct VdirMetadata {
+    /// The name of the collection.
+    #[serde(default)]
+    pub displayname: Option<String>,
+    /// The color of the collection (e.g., `#ff0000`).
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Retention {
+    /// How long to keep items after they have been completed or cancelled.
+    pub keep: HumanDuration,
+    /// What to do with items once they expire.
+    #[serde(default)]
+    pub action: RetentionAction,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAction {
+    /// Move expired items into an `archive` subdirectory.
+    #[default]
+    #[serde(rename = "archive")]
+    Archive,
+    /// Delete expired items.
+    #[serde(rename = "delete")]
+    Delete,
+}
+
+/// How UIDs are chosen for new items.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum UidScheme {
+    /// A random UUID (v4).
+    #[default]
+    #[serde(rename = "random")]
+    Random,
+    /// A UUID derived from the item's URL (v5).
+    ///
+    /// Recreating a target from scratch results in the same files.
+    #[serde(rename = "url")]
+    Url,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Chore {
+    pub summary: String,
+    #[serde(default)]
+    pub description: String,
+    /// The recurrence rule for the chore (e.g., `FREQ=WEEKLY;BYDAY=MO`).
+    pub rrule: String,
+    /// The date of the first occurrence of the chore.
+    pub start: NaiveDate,
+}
+
+/// A secret or where to get it from.
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Secret {
+    /// The secret itself.
+    Plain(String),
+    /// The secret itself, given explicitly.
+    Inline { plain: String },
+    /// An environment variable holding the secret.
+    Env { env: String },
+    /// A command which prints the secret (e.g., `["pass", "show", "github"]`).
+    Command { command: Vec<String> },
+    /// A file holding the secret.
+    ///
+    /// A leading `~` and environment variables are expanded.
+    File {
+        #[serde(deserialize_with = "deserialize_path")]
+        file: PathBuf,
+    },
+    /// A git credential helper (e.g., `git-credential-libsecret`).
+    ///
+    /// The password it has for the service's host is used. Helpers starting with `!` are run
+    /// using the shell.
+    Helper { helper: String },
+    /// An entry in the platform keyring (requires the `keyring` feature).
+    ///
+    /// The entry for the service's host under the given keyring service name is used. Use
+    /// `devtodo auth set` to store it.
+    Keyring { keyring: String },
+}
+
+/// One or more secrets for an account.
+#[derive(Clone)]
+pub struct Secrets(pub Vec<Secret>);
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Secrets")
+            .field(&vec![redact::REDACTED; self.0.len()])
+            .finish()
+    }
+}
+
+impl<'de> Deserialize<'de> for Secrets {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum OneOrMany {
+            One(Secret),
+            Many(Vec<Secret>),
+        }
+
+        let secrets = match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(secret) => vec![secret],
+            OneOrMany::Many(secrets) => secrets,
+        };
+        if secrets.is_empty() {
+            return Err(D::Error::custom("at least one secret is required"));
+        }
+
+        Ok(Secrets(secrets))
+    }
+}
+
+/// Default values for XDG base directory variables relative to the home directory.
+const XDG_DEFAULTS: &[(&str, &str)] = &[
+    ("XDG_CONFIG_HOME", ".config"),
+    ("XDG_DATA_HOME", ".local/share"),
+    ("XDG_STATE_HOME", ".local/state"),
+    ("XDG_CACHE_HOME", ".cache"),
+];
+
+fn home_dir() -> Option<PathBuf> {
+    BaseDirs::new().map(|dirs| dirs.home_dir().into())
+}
+
+/// The value of a variable used in a path.
+///
+/// XDG base directory variables fall back to their default values if unset.
+fn path_variable(name: &str) -> Option<String> {
+    if let Some(value) = env::var_os(name) {
+        return Some(value.to_string_lossy().into());
+    }
+    if name == "HOME" {
+        return home_dir().map(|home| home.to_string_lossy().into());
+    }
+
+    let (_, default) = XDG_DEFAULTS.iter().find(|(var, _)| *var == name)?;
+    home_dir().map(|home| home.join(default).to_string_lossy().into())
+}
+
+/// Expand a leading `~` and `$VAR` or `${VAR}` references in a path.
+fn expand_path(path: &str) -> Result<PathBuf, String> {
+    let mut expanded = String::new();
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") {
+        let home = home_dir().ok_or("failed to determine the home directory")?;
+        expanded.push_str(&home.to_string_lossy());
+        rest = &rest[1..];
+    }
+
+    while let Some(idx) = rest.find('$') {
+        expanded.push_str(&rest[..idx]);
+        let after = &rest[idx + 1..];
+        let (name, remaining) = if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| format!("unterminated variable in path: {}", path))?;
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        if name.is_empty() {
+            return Err(format!("empty variable in path: {}", path));
+        }
+
+        let value =
+            path_variable(name).ok_or_else(|| format!("unset variable in path: {}", name))?;
+        expanded.push_str(&value);
+        rest = remaining;
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded.into())
+}
+
+fn deserialize_path<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    expand_path(&value).map_err(D::Error::custom)
+}
+
+fn deserialize_attempts<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = usize::deserialize(deserializer)?;
+    if value == 0 {
+        return Err(D::Error::custom("at least one attempt is required"));
+    }
+    Ok(value)
+}
+
+fn deserialize_scale<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = u32::deserialize(deserializer)?;
+    if value == 0 {
+        return Err(D::Error::custom(
+            "the delay cannot be scaled down to nothing",
+        ));
+    }
+    Ok(value)
+}
+
+/// Whether a string is a valid GraphQL name.
+fn is_graphql_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn deserialize_extra_fields<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let fields = BTreeMap::<String, String>::deserialize(deserializer)?;
+    for (name, path) in &fields {
+        let is_valid_name =
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !is_valid_name {
+            return Err(D::Error::custom(format!(
+                "invalid extra property name: {}",
+                name,
+            )));
+        }
+        if !path.split('.').all(is_graphql_name) {
+            return Err(D::Error::custom(format!(
+                "invalid extra field path: {}",
+                path,
+            )));
+        }
+    }
+    Ok(fields)
+}
+
+/// A duration written as a human-readable string (e.g., `2d` or `1h30m`).
+#[derive(Debug, Clone, Copy)]
+pub struct HumanDuration(pub Duration);
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        todo::parse_human_duration(&value)
+            .map(HumanDuration)
+            .ok_or_else(|| D::Error::custom(format!("invalid duration: {}", value)))
+    }
+}
+
+/// A local time of day written as a string (e.g., `17:00`).
+#[derive(Debug, Clone, Copy)]
+pub struct TimeOfDay(pub NaiveTime);
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&value, "%H:%M")
+            .map(TimeOfDay)
+            .map_err(|_| D::Error::custom(format!("invalid time of day: {}", value)))
+    }
+}
+
+/// A log level written as a string (e.g., `warn` or `debug`).
+#[derive(Debug, Clone, Copy)]
+pub struct LogLevel(pub LevelFilter);
+
+impl<'de> Deserialize<'de> for LogLevel {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        LevelFilter::from_str(&value)
+            .map(LogLevel)
+            .map_err(|_| D::Error::custom(format!("invalid log level: {}", value)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EscalationRule {
+    /// The rule applies once the due date is within this amount of time.
+    ///
+    /// Without a value, the rule applies only to overdue items.
+    #[serde(default)]
+    pub within: Option<HumanDuration>,
+    /// The priority to give matching items (1 is the highest, 9 the lowest).
+    #[serde(default)]
+    pub priority: Option<u8>,
+    /// A category to add to matching items.
+    #[serde(default)]
+    pub category: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Profile {
     pub account: String,
+    /// The identity of the account to fetch as (from its `users`).
+    ///
+    /// The account's own secrets are used if not given.
+    #[serde(default)]
+    pub as_user: Option<String>,
     pub target: QueryTarget,
     #[serde(default)]
     pub filters: Vec<Filter>,
+    /// Include review requests made to teams the user is a member of.
+    #[serde(default)]
+    pub team_reviews: bool,
+    /// Prefix summaries with a reference to the item (e.g., `owner/repo#123`).
+    #[serde(default)]
+    pub summary_prefix: bool,
+    /// What to do with confidential items.
+    #[serde(default)]
+    pub confidential: ConfidentialPolicy,
+    /// Only import items updated within this long before the first sync of the profile.
+    ///
+    /// Older items are imported once they are updated.
+    #[serde(default)]
+    pub initial_window: Option<HumanDuration>,
+    /// Priorities to give items with these labels (1 is the highest, 9 the lowest).
+    ///
+    /// Labels are matched exactly (e.g., `P0` or `priority::1`). The highest priority among an
+    /// item's labels takes precedence over the priority from the service.
+    #[serde(default)]
+    pub label_priorities: BTreeMap<String, u8>,
+    /// Extra fields of items to record as `X-` properties (only supported for GitHub).
+    ///
+    /// Keys are property names without the `X-` prefix and values are paths to fields of
+    /// issues and pull requests in GitHub's GraphQL schema (e.g., `reactions.totalCount`).
+    /// Paths cannot pass arguments, so they cannot select into connections which require them
+    /// (e.g., `labels.nodes.name` needs `first:`); use their counts instead (e.g.,
+    /// `labels.totalCount`).
+    #[serde(default, deserialize_with = "deserialize_extra_fields")]
+    pub extra_fields: BTreeMap<String, String>,
+    /// Embed the contents of attachments up to this size (in bytes) into items (only supported
+    /// for GitHub).
+    ///
+    /// Each attachment costs a request on every sync (unless responses are cached). Only files
+    /// which can be fetched without credentials (e.g., from public repositories) are embedded.
+    #[serde(default)]
+    pub inline_attachment_size: Option<usize>,
+    /// Where to take due dates from, in order of preference.
+    ///
+    /// Defaults to the item itself, then its milestone, iteration, and epic.
+    #[serde(default)]
+    pub due_fallback: DueFallback,
+    /// Close items which the profile stops returning (e.g., they were unassigned).
+    ///
+    /// Items rotated out of a `help_wanted` selection are always closed; this only changes how.
+    #[serde(default)]
+    pub close_missing: Option<CloseMissing>,
+    /// What to do on the service when an item is completed locally.
+    #[serde(default)]
+    pub write_back: WriteBack,
+    /// The comment to leave for the `comment` write back.
+    ///
+    /// `{url}` and `{reference}` are replaced with the URL of the item and a short reference
+    /// to it (e.g., `owner/repo#123`).
+    #[serde(default)]
+    pub completion_comment: Option<String>,
+}
+
+impl Profile {
+    /// How to close items which the profile stops returning, if at all.
+    pub fn close_missing(&self) -> Option<CloseMissing> {
+        self.close_missing.or_else(|| {
+            // Items rotated out of the selection would otherwise stay open forever.
+            matches!(self.target, QueryTarget::HelpWanted(_)).then(|| {
+                CloseMissing {
+                    grace: HumanDuration(Duration::zero()),
+                    ..CloseMissing::default()
+                }
+            })
+        })
+    }
+}
+
+/// Where the due date of an item may come from.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DueSource {
+    /// The item's own due date.
+    #[serde(rename = "item")]
+    Item,
+    /// The due date of the milestone the item belongs to.
+    #[serde(rename = "milestone")]
+    Milestone,
+    /// The end of the iteration (sprint) the item is scheduled in.
+    #[serde(rename = "iteration")]
+    Iteration,
+    /// The due date of the epic the item belongs to.
+    #[serde(rename = "epic")]
+    Epic,
+}
+
+/// The order in which sources of due dates are consulted.
+#[derive(Debug, Deserialize)]
+#[serde(transparent)]
+pub struct DueFallback(pub Vec<DueSource>);
+
+impl Default for DueFallback {
+    fn default() -> Self {
+        Self(vec![
+            DueSource::Item,
+            DueSource::Milestone,
+            DueSource::Iteration,
+            DueSource::Epic,
+        ])
+    }
+}
+
+/// How to close items which a profile stops returning.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct CloseMissing {
+    /// The status to give the items (usually `COMPLETED` or `CANCELLED`).
+    pub status: TodoStatus,
+    /// How long an item must be missing before it is closed.
+    ///
+    /// Covers items which briefly drop out of results (e.g., while being moved).
+    pub grace: HumanDuration,
+}
+
+impl Default for CloseMissing {
+    fn default() -> Self {
+        Self {
+            status: TodoStatus::Cancelled,
+            grace: HumanDuration(Duration::days(1)),
+        }
+    }
+}
+
+/// What to do on the service when an item is completed locally.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WriteBack {
+    /// Leave the item alone.
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    /// Close the item.
+    #[serde(rename = "close")]
+    Close,
+    /// Comment on the item that it has been completed.
+    #[serde(rename = "comment")]
+    Comment,
+    /// Remove the user from the item's assignees.
+    #[serde(rename = "unassign")]
+    Unassign,
+}
+
+/// What to do with confidential items (e.g., those in private repositories).
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ConfidentialPolicy {
+    /// Include them like any other item.
+    #[default]
+    #[serde(rename = "include")]
+    Include,
+    /// Leave them out.
+    #[serde(rename = "skip")]
+    Skip,
+    /// Include them with `CLASS:PRIVATE`.
+    #[serde(rename = "private")]
+    Private,
+    /// Include them with a generic summary and no details.
+    #[serde(rename = "redact")]
+    Redact,
 }
 
 #[derive(Debug, Deserialize)]
 pub enum QueryTarget {
     #[serde(rename = "self")]
     SelfUser,
+    /// Open items in the given projects (e.g., `owner/repo` on GitHub).
     #[serde(rename = "projects")]
     Projects(Vec<String>),
+    /// Items which mention the user without being assigned to them.
+    #[serde(rename = "mentioned")]
+    Mentioned,
+    /// Pull requests for which the user's review has been requested.
+    #[serde(rename = "review_requested")]
+    ReviewRequested,
+    /// Items the user is participating in without being the author or an assignee.
+    #[serde(rename = "participating")]
+    Participating,
+    /// Open items in repositories the user has starred.
+    ///
+    /// Filters should be used to select the interesting items (e.g., `help wanted` labels).
+    #[serde(rename = "starred")]
+    Starred,
+    /// A rotating selection of open items looking for contributors.
+    #[serde(rename = "help_wanted")]
+    HelpWanted(HelpWanted),
+    /// Open items assigned to any of the given users.
+    ///
+    /// Items are given an `@login` category for each of the users assigned to them so that the
+    /// target shows the workload of the team.
+    #[serde(rename = "team")]
+    Team(Vec<String>),
+}
+
+/// Where to look for items looking for contributors.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HelpWanted {
+    /// Search repositories with these topics (e.g., `rust`).
+    pub topics: Vec<String>,
+    /// Search repositories owned by these organizations or users.
+    pub orgs: Vec<String>,
+    /// Labels marking items looking for contributors.
+    ///
+    /// Items with any of the labels are candidates.
+    pub labels: Vec<String>,
+    /// The maximum number of items to sync at a time.
+    pub limit: usize,
+    /// How long to keep a selection before rotating to other candidates.
+    pub rotation: HumanDuration,
+    /// The priority to give the items (1 is the highest, 9 the lowest).
+    pub priority: u8,
+}
+
+impl Default for HelpWanted {
+    fn default() -> Self {
+        Self {
+            topics: Vec::new(),
+            orgs: Vec::new(),
+            labels: vec!["good first issue".into(), "help wanted".into()],
+            limit: 5,
+            rotation: HumanDuration(Duration::weeks(1)),
+            priority: 9,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub enum Filter {
+    /// Only include items with the label.
     #[serde(rename = "label")]
     Label(String),
+    /// Exclude items with the label.
+    #[serde(rename = "not_label")]
+    NotLabel(String),
+    /// Only include items in the milestone.
+    #[serde(rename = "milestone")]
+    Milestone(String),
 }
@@ -0,0 +1,77 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde_yaml::{Mapping, Value};
+use thiserror::Error;
+
+/// The current version of the configuration layout.
+pub const CONFIG_VERSION: u64 = 1;
+
+/// The key holding the version of the configuration layout.
+pub const VERSION_KEY: &str = "version";
+
+#[derive(Debug, Error)]
+pub enum MigrateError {
+    #[error("the configuration is not a mapping")]
+    NotAMapping,
+    #[error("the configuration version is not a positive integer")]
+    InvalidVersion,
+    #[error(
+        "the configuration version {} is newer than the supported version {}",
+        version,
+        CONFIG_VERSION
+    )]
+    UnsupportedVersion { version: u64 },
+}
+
+impl MigrateError {
+    fn unsupported_version(version: u64) -> Self {
+        Self::UnsupportedVersion {
+            version,
+        }
+    }
+}
+
+/// A migration from one version of the layout to the next.
+type Migration = fn(&mut Mapping) -> Result<(), MigrateError>;
+
+/// The version of configurations which do not specify one.
+///
+/// Unversioned configurations use the first layout. Should they ever need migrating, they are
+/// found by their missing version.
+const UNVERSIONED: u64 = 1;
+
+/// Migrations indexed by the version they migrate from (starting with the first version).
+const MIGRATIONS: &[Migration] = &[];
+
+/// Migrate a configuration document to the current layout.
+///
+/// Returns whether the document was changed.
+pub fn migrate(doc: &mut Value) -> Result<bool, MigrateError> {
+    let mapping = doc.as_mapping_mut().ok_or(MigrateError::NotAMapping)?;
+    let version = match mapping.get(VERSION_KEY) {
+        Some(version) => {
+            version
+                .as_u64()
+                .filter(|&version| version > 0)
+                .ok_or(MigrateError::InvalidVersion)?
+        },
+        None => UNVERSIONED,
+    };
+    if version > CONFIG_VERSION {
+        return Err(MigrateError::unsupported_version(version));
+    }
+    if version == CONFIG_VERSION {
+        return Ok(false);
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(mapping)?;
+    }
+    mapping.insert(VERSION_KEY.into(), CONFIG_VERSION.into());
+
+    Ok(true)
+}
@@ -0,0 +1,203 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt::Write;
+
+// The number of unchanged lines to show around changes.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+
+/// Compute the line operations turning `old` into `new` using their longest common subsequence.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    // `lcs[i][j]` is the length of the longest common subsequence of `old[i..]` and `new[j..]`.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            ops.push((Op::Same, old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push((Op::Removed, old[i]));
+            i += 1;
+        } else {
+            ops.push((Op::Added, new[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[i..].iter().map(|line| (Op::Removed, *line)));
+    ops.extend(new[j..].iter().map(|line| (Op::Added, *line)));
+
+    ops
+}
+
+/// Format the range of a hunk.
+fn hunk_range(start: usize, len: usize) -> String {
+    // Empty ranges refer to the line before the hunk.
+    let start = if len == 0 { start } else { start + 1 };
+    if len == 1 {
+        format!("{}", start)
+    } else {
+        format!("{},{}", start, len)
+    }
+}
+
+/// A unified diff between two texts.
+///
+/// Returns `None` if the texts have the same lines.
+pub fn unified(old_name: &str, new_name: &str, old: &str, new: &str) -> Option<String> {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    // Group changes which are close to each other into hunks.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (idx, _) in ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != Op::Same)
+    {
+        let start = idx.saturating_sub(CONTEXT);
+        let end = (idx + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    if hunks.is_empty() {
+        return None;
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "--- {}", old_name);
+    let _ = writeln!(out, "+++ {}", new_name);
+
+    // Line numbers within the old and new texts at the start of each operation.
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for (op, _) in &ops {
+        positions.push((old_pos, new_pos));
+        match op {
+            Op::Same => {
+                old_pos += 1;
+                new_pos += 1;
+            },
+            Op::Removed => old_pos += 1,
+            Op::Added => new_pos += 1,
+        }
+    }
+    positions.push((old_pos, new_pos));
+
+    for (start, end) in hunks {
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start),
+        );
+        for (op, line) in &ops[start..end] {
+            let prefix = match op {
+                Op::Same => ' ',
+                Op::Removed => '-',
+                Op::Added => '+',
+            };
+            let _ = writeln!(out, "{}{}", prefix, line);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unified;
+
+    /// The lines of a text, one per number.
+    fn numbered(numbers: &[usize]) -> String {
+        numbers.iter().map(|n| format!("{}\n", n)).collect()
+    }
+
+    #[test]
+    fn same_lines() {
+        assert_eq!(unified("a", "b", "", ""), None);
+        assert_eq!(unified("a", "b", "x\ny\n", "x\ny"), None);
+    }
+
+    #[test]
+    fn empty_old() {
+        let diff = unified("a", "b", "", "x\ny\n").unwrap();
+
+        assert_eq!(diff, "--- a\n+++ b\n@@ -0,0 +1,2 @@\n+x\n+y\n");
+    }
+
+    #[test]
+    fn empty_new() {
+        let diff = unified("a", "b", "x\n", "").unwrap();
+
+        assert_eq!(diff, "--- a\n+++ b\n@@ -1 +0,0 @@\n-x\n");
+    }
+
+    #[test]
+    fn insertion() {
+        let diff = unified("a", "b", "x\nz\n", "x\ny\nz\n").unwrap();
+
+        assert_eq!(diff, "--- a\n+++ b\n@@ -1,2 +1,3 @@\n x\n+y\n z\n");
+    }
+
+    #[test]
+    fn deletion() {
+        let diff = unified("a", "b", "x\ny\nz\n", "x\nz\n").unwrap();
+
+        assert_eq!(diff, "--- a\n+++ b\n@@ -1,3 +1,2 @@\n x\n-y\n z\n");
+    }
+
+    #[test]
+    fn context_hunks() {
+        let old = numbered(&(1..=20).collect::<Vec<_>>());
+        let new = numbered(&(1..=20).filter(|&n| n != 2 && n != 18).collect::<Vec<_>>());
+        let diff = unified("a", "b", &old, &new).unwrap();
+
+        // Distant changes are separate hunks with three lines of context.
+        assert_eq!(
+            diff,
+            "--- a\n+++ b\n\
+             @@ -1,5 +1,4 @@\n 1\n-2\n 3\n 4\n 5\n\
+             @@ -15,6 +14,5 @@\n 15\n 16\n 17\n-18\n 19\n 20\n",
+        );
+    }
+
+    #[test]
+    fn nearby_changes_share_a_hunk() {
+        let old = numbered(&(1..=10).collect::<Vec<_>>());
+        let new = numbered(&[1, 2, 3, 40, 5, 6, 7, 8, 90, 10]);
+        let diff = unified("a", "b", &old, &new).unwrap();
+
+        assert_eq!(
+            diff,
+            "--- a\n+++ b\n\
+             @@ -1,10 +1,10 @@\n 1\n 2\n 3\n-4\n+40\n 5\n 6\n 7\n 8\n-9\n+90\n 10\n",
+        );
+    }
+}
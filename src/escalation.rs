@@ -0,0 +1,63 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::EscalationRule;
+use crate::todo::{Escalation, TodoItem, TodoStatus};
+
+/// Compute the escalation for an item based on its due date.
+fn compute(rules: &[EscalationRule], item: &TodoItem, now: DateTime<Utc>) -> Escalation {
+    let mut escalation = Escalation::default();
+
+    // Only open items are escalated.
+    match item.status() {
+        TodoStatus::NeedsAction | TodoStatus::InProcess => (),
+        TodoStatus::Completed | TodoStatus::Cancelled => return escalation,
+    }
+
+    let deadline = if let Some(due) = item.due() {
+        due.deadline()
+    } else {
+        return escalation;
+    };
+    let remaining = deadline - now;
+
+    for rule in rules {
+        let within = rule
+            .within
+            .map(|within| within.0)
+            .unwrap_or_else(Duration::zero);
+        if remaining > within {
+            continue;
+        }
+
+        if let Some(priority) = rule.priority {
+            // Lower values are more important.
+            escalation.priority = Some(
+                escalation
+                    .priority
+                    .map_or(priority, |current| current.min(priority)),
+            );
+        }
+        if let Some(category) = rule.category.as_ref() {
+            if !escalation.categories.contains(category) {
+                escalation.categories.push(category.clone());
+            }
+        }
+    }
+
+    escalation
+}
+
+/// Escalate an item according to a set of rules.
+///
+/// This is applied to every item on each sync so that escalations take effect even if the item
+/// has not changed on the service.
+pub fn escalate(rules: &[EscalationRule], item: &mut TodoItem, now: DateTime<Utc>) {
+    let escalation = compute(rules, item, now);
+    item.set_escalation(escalation);
+}
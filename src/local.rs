@@ -0,0 +1,124 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc, Weekday};
+use uuid::Uuid;
+
+use crate::todo::{self, Due, TodoItem, TodoKind, TodoStatus};
+
+// The prefix of URLs given to items added locally.
+const URL_PREFIX: &str = "urn:devtodo:todo:";
+
+/// Whether an item URL refers to an item added locally.
+///
+/// These items do not exist on any service, so they are never stale.
+pub fn is_local(url: &str) -> bool {
+    url.starts_with(URL_PREFIX)
+}
+
+/// Parse a due date given on the command line.
+///
+/// Accepts dates (`2024-05-17`), `today`, `tomorrow`, weekdays (`friday` or `fri`; the next
+/// such day after today), or a duration from now (e.g., `2h` or `3d`). Days are relative to the
+/// local date.
+pub fn parse_due(s: &str, now: DateTime<Utc>) -> Option<Due> {
+    let today = now.with_timezone(&Local).date_naive();
+    let lower = s.to_lowercase();
+    let date = match lower.as_str() {
+        "today" => Some(today),
+        "tomorrow" => today.succ_opt(),
+        _ => {
+            if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+                Some(date)
+            } else if let Ok(weekday) = lower.parse::<Weekday>() {
+                Some(next_weekday(today, weekday))
+            } else {
+                return todo::parse_human_duration(s).map(|duration| Due::DateTime(now + duration));
+            }
+        },
+    };
+
+    date.map(Due::Date)
+}
+
+/// The next day after `today` which falls on a weekday.
+fn next_weekday(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days_ahead =
+        (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday() - 1) % 7 + 1;
+    today + Duration::days(days_ahead.into())
+}
+
+/// A new URL for an item which is not tied to any service.
+pub fn new_url() -> String {
+    format!("{}{}", URL_PREFIX, Uuid::new_v4().simple())
+}
+
+/// Create an item which is not tied to any service.
+pub fn new_item(summary: String, description: String, due: Option<Due>) -> TodoItem {
+    let mut item = TodoItem::builder();
+    item.kind(TodoKind::Todo)
+        .status(TodoStatus::NeedsAction)
+        .url(new_url())
+        .summary(summary)
+        .description(description);
+    if let Some(due) = due {
+        item.due(due);
+    }
+
+    item.build().expect("all item fields should be provided")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local, NaiveDate, Utc, Weekday};
+
+    use crate::todo::Due;
+
+    use super::{next_weekday, parse_due};
+
+    #[test]
+    fn next_weekday_is_after_today() {
+        // 2026-03-04 is a Wednesday.
+        let wednesday = NaiveDate::from_ymd_opt(2026, 3, 4).unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2026, 3, d).unwrap();
+
+        assert_eq!(next_weekday(wednesday, Weekday::Thu), day(5));
+        assert_eq!(next_weekday(wednesday, Weekday::Fri), day(6));
+        assert_eq!(next_weekday(wednesday, Weekday::Sun), day(8));
+        assert_eq!(next_weekday(wednesday, Weekday::Mon), day(9));
+        assert_eq!(next_weekday(wednesday, Weekday::Tue), day(10));
+        // The same weekday is a week away.
+        assert_eq!(next_weekday(wednesday, Weekday::Wed), day(11));
+        // Weeks and months are crossed.
+        assert_eq!(
+            next_weekday(NaiveDate::from_ymd_opt(2026, 2, 28).unwrap(), Weekday::Mon),
+            day(2),
+        );
+    }
+
+    #[test]
+    fn due_days_are_local() {
+        let now = Utc::now();
+        let today = now.with_timezone(&Local).date_naive();
+
+        assert_eq!(parse_due("today", now), Some(Due::Date(today)));
+        assert_eq!(parse_due("Tomorrow", now), today.succ_opt().map(Due::Date));
+        assert_eq!(
+            parse_due("fri", now),
+            Some(Due::Date(next_weekday(today, Weekday::Fri))),
+        );
+        assert_eq!(
+            parse_due("2026-03-04", now),
+            NaiveDate::from_ymd_opt(2026, 3, 4).map(Due::Date),
+        );
+        assert_eq!(
+            parse_due("2h", now),
+            Some(Due::DateTime(now + Duration::hours(2))),
+        );
+        assert_eq!(parse_due("someday", now), None);
+        assert_eq!(parse_due("", now), None);
+    }
+}
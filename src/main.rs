@@ -4,29 +4,81 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::collections::BTreeMap;
-use std::fs;
-use std::io;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::iter;
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
+use chrono::{DateTime, Utc};
 use clap::builder::PossibleValuesParser;
-use clap::{self, Arg, ArgAction, Command};
+use clap::{self, Arg, ArgAction, ArgMatches, Command};
 use directories::ProjectDirs;
+use futures_util::future;
 use human_panic::setup_panic;
+use itertools::Itertools;
 use log::*;
 use thiserror::Error;
+use tokio::runtime::{self, Runtime};
+use vobject::Property;
 
 mod account;
+mod agenda;
+mod backup;
+mod bugreport;
+mod cache;
+#[cfg(feature = "caldav")]
+mod caldav;
+mod changelog;
+mod changeset;
+mod chore;
 mod config;
+mod diff;
+mod escalation;
+mod local;
+mod missing;
+mod notify;
+mod open;
+mod redact;
+mod report;
+mod retention;
+mod review;
+mod route;
+mod search;
+mod secret;
+mod serve;
+mod state;
+mod subtask;
+mod systemd;
+mod timing;
 mod todo;
+mod vdir;
+mod writeback;
 
-use self::config::Config;
-use self::todo::TodoFile;
+use self::account::{ConnectContext, HttpCapture, ItemLookup, ItemSource, SourceItem};
+use self::cache::Cache;
+use self::changeset::{ChangeSet, ChangedItem};
+use self::config::{
+    Account, ConfidentialPolicy, Config, LoggingConfig, Profile, QueryTarget, ReportFormat, Secret,
+    ServeConfig, SyncTarget, UidScheme,
+};
+use self::missing::{MissingItems, ProfileReturns};
+use self::state::{AccountStatus, CaldavResource, State};
+use self::timing::{ProfileTiming, TargetTiming};
+use self::todo::{TodoFile, TodoStatus, WriteMode, DATE_TIME_FMT};
+use self::writeback::WriteBacks;
 
 #[derive(Debug, Error)]
 enum LogError {
     #[error("unknown logger: {}", _0)]
     UnknownLogger(String),
+    #[error("invalid log filter: {}", _0)]
+    InvalidFilter(String),
 }
 
 enum Logger {
@@ -39,16 +91,35 @@ enum SetupError {
     NoProjectDir,
     #[error("failed to read configuration file {}", path.display())]
     ReadConfig { path: PathBuf, source: io::Error },
-    #[error("failed to parse configuration file {}", path.display())]
+    #[error("failed to parse the {}", origin)]
     ParseConfig {
-        path: PathBuf,
+        origin: ConfigOrigin,
         source: serde_yaml::Error,
     },
-    #[error("failed to handle merge keys in configuration file {}", path.display())]
+    #[error("failed to handle merge keys in the {}", origin)]
     MergeKeys {
-        path: PathBuf,
+        origin: ConfigOrigin,
         source: yaml_merge_keys::MergeKeyError,
     },
+    #[error("failed to migrate the {}", origin)]
+    MigrateConfig {
+        origin: ConfigOrigin,
+        source: config::MigrateError,
+    },
+    #[error("invalid {}", origin)]
+    InvalidConfig {
+        origin: ConfigOrigin,
+        source: config::ConfigError,
+    },
+    #[error("there is no configuration file at {} to migrate", path.display())]
+    NoConfigFile { path: PathBuf },
+    #[error("failed to write configuration file {}", path.display())]
+    WriteConfig { path: PathBuf, source: io::Error },
+    #[error("failed to serialize the migrated configuration for {}", path.display())]
+    SerializeConfig {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
     #[error("log error")]
     LogError {
         #[from]
@@ -74,6 +145,15 @@ enum SetupError {
     },
     #[error("no such account {}", name)]
     NoSuchAccount { name: String },
+    #[error("the {} account has no {} user", account, user)]
+    NoSuchUser { account: String, user: String },
+    #[error("the {} account does not use the keyring", name)]
+    NoKeyring { name: String },
+    #[error("secret error")]
+    Secret {
+        #[from]
+        source: secret::SecretError,
+    },
     #[error(
         "failed to fetch items from the {} account for the {} profile",
         account,
@@ -84,6 +164,96 @@ enum SetupError {
         profile: String,
         source: account::ItemError,
     },
+    #[error("failed to write collection metadata for {}", name)]
+    Metadata {
+        name: String,
+        source: vdir::MetadataError,
+    },
+    #[error("failed to write the report for {}", name)]
+    Report { name: String, source: io::Error },
+    #[error("failed to write the changes to the report")]
+    ReportChanges { source: io::Error },
+    #[error("failed to create the directory {} for {}", path.display(), name)]
+    CreateDirectory {
+        path: PathBuf,
+        name: String,
+        source: io::Error,
+    },
+    #[error("no directory given for {}", name)]
+    NoDirectory { name: String },
+    #[error("failed to create the HTTP capture directory {}", path.display())]
+    HttpCapture { path: PathBuf, source: io::Error },
+    #[error("failed to clear the cache directory {}", path.display())]
+    ClearCache { path: PathBuf, source: io::Error },
+    #[error("failed to lock the directory for {}", name)]
+    Lock {
+        name: String,
+        source: vdir::LockError,
+    },
+    #[error("change log error")]
+    ChangeLog {
+        #[from]
+        source: changelog::ChangeLogError,
+    },
+    #[error("backup error")]
+    Backup {
+        #[from]
+        source: backup::BackupError,
+    },
+    #[error("systemd error")]
+    Systemd {
+        #[from]
+        source: systemd::SystemdError,
+    },
+    #[error("bug report error")]
+    BugReport {
+        #[from]
+        source: bugreport::BugReportError,
+    },
+    #[error("no such target {}", name)]
+    NoSuchTarget { name: String },
+    #[error("no items match {}", pattern)]
+    NoMatch { pattern: String },
+    #[error("{} items match {}", count, pattern)]
+    AmbiguousMatch { pattern: String, count: usize },
+    #[error("failed to open {}", url)]
+    OpenUrl { url: String, source: io::Error },
+    #[error("failed to write changes to {}", path.display())]
+    WriteChanges { path: PathBuf, source: io::Error },
+    #[error("failed to serve calendar feeds")]
+    Serve { source: io::Error },
+    #[error("failed to start the async runtime")]
+    Runtime { source: io::Error },
+    #[cfg(feature = "caldav")]
+    #[error("failed to sync the CalDAV collection for {}", name)]
+    Caldav {
+        name: String,
+        source: caldav::CaldavError,
+    },
+    #[cfg(not(feature = "caldav"))]
+    #[error("CalDAV support is not available for {}", name)]
+    CaldavUnsupported { name: String },
+    #[error("invalid status: {}", value)]
+    InvalidStatus { value: String },
+    #[error("invalid kind: {}", value)]
+    InvalidKind { value: String },
+    #[error("invalid duration: {}", value)]
+    InvalidDuration { value: String },
+    #[error("invalid due date: {}", value)]
+    InvalidDue { value: String },
+    #[error("state error")]
+    State {
+        #[from]
+        source: state::StateError,
+    },
+    #[error("failed to connect to accounts: {}", names.iter().format(", "))]
+    AccountTest { names: Vec<String> },
+    #[error(
+        "failed to sync {} targets: {}",
+        errors.len(),
+        errors.iter().map(|(name, _)| name).format(", ")
+    )]
+    TargetErrors { errors: Vec<(String, SetupError)> },
     #[error("failed to write {} items", errors.len())]
     WriteErrors {
         errors: Vec<(String, todo::TodoError)>,
@@ -98,15 +268,49 @@ impl SetupError {
         }
     }
 
-    fn parse_config(path: PathBuf, source: serde_yaml::Error) -> Self {
+    fn parse_config(origin: ConfigOrigin, source: serde_yaml::Error) -> Self {
         Self::ParseConfig {
-            path,
+            origin,
             source,
         }
     }
 
-    fn merge_keys(path: PathBuf, source: yaml_merge_keys::MergeKeyError) -> Self {
+    fn merge_keys(origin: ConfigOrigin, source: yaml_merge_keys::MergeKeyError) -> Self {
         Self::MergeKeys {
+            origin,
+            source,
+        }
+    }
+
+    fn migrate_config(origin: ConfigOrigin, source: config::MigrateError) -> Self {
+        Self::MigrateConfig {
+            origin,
+            source,
+        }
+    }
+
+    fn invalid_config(origin: ConfigOrigin, source: config::ConfigError) -> Self {
+        Self::InvalidConfig {
+            origin,
+            source,
+        }
+    }
+
+    fn no_config_file(path: PathBuf) -> Self {
+        Self::NoConfigFile {
+            path,
+        }
+    }
+
+    fn write_config(path: PathBuf, source: io::Error) -> Self {
+        Self::WriteConfig {
+            path,
+            source,
+        }
+    }
+
+    fn serialize_config(path: PathBuf, source: serde_yaml::Error) -> Self {
+        Self::SerializeConfig {
             path,
             source,
         }
@@ -147,6 +351,19 @@ impl SetupError {
         }
     }
 
+    fn no_such_user(account: String, user: String) -> Self {
+        Self::NoSuchUser {
+            account,
+            user,
+        }
+    }
+
+    fn no_keyring(name: String) -> Self {
+        Self::NoKeyring {
+            name,
+        }
+    }
+
     fn fetch_items(account: String, profile: String, source: account::ItemError) -> Self {
         Self::FetchItems {
             account,
@@ -155,250 +372,2959 @@ impl SetupError {
         }
     }
 
-    fn write_errors(errors: Vec<(String, todo::TodoError)>) -> Self {
-        Self::WriteErrors {
-            errors,
+    fn metadata(name: String, source: vdir::MetadataError) -> Self {
+        Self::Metadata {
+            name,
+            source,
         }
     }
-}
-
-fn read_directory(dirpath: &Path, name: &str) -> Result<Vec<TodoFile>, SetupError> {
-    let mut todo_files = Vec::new();
-    let dir_iter = fs::read_dir(dirpath)
-        .map_err(|err| SetupError::read_dir(dirpath.into(), name.into(), err))?;
-    for entry in dir_iter {
-        let entry = entry.map_err(|err| SetupError::read_entry(name.into(), err))?;
-        let path = entry.path();
 
-        // Only look at `.ics` files.
-        if path.extension().map(|ext| ext != "ics").unwrap_or(true) {
-            continue;
+    fn report(name: String, source: io::Error) -> Self {
+        Self::Report {
+            name,
+            source,
         }
+    }
 
-        // Check the filetype.
-        match entry.metadata() {
-            Ok(md) => {
-                let filetype = md.file_type();
-                if filetype.is_dir() {
-                    // Ignore directories.
-                    continue;
-                }
-                // Get the actual file we're dealing with here.
-                let real_filetype = if filetype.is_symlink() {
-                    match path.metadata() {
-                        Ok(real_md) => real_md.file_type(),
-                        Err(err) => {
-                            warn!(
-                                "failed to read target metadata for {}: {}; ignoring",
-                                path.display(),
-                                err,
-                            );
-                            continue;
-                        },
-                    }
-                } else {
-                    filetype
-                };
-                // Ignore non-files.
-                if !real_filetype.is_file() {
-                    continue;
-                }
-            },
-            Err(err) => {
-                warn!(
-                    "failed to read metadata for {}: {}; ignoring",
-                    path.display(),
-                    err,
-                );
-                continue;
-            },
+    fn report_changes(source: io::Error) -> Self {
+        Self::ReportChanges {
+            source,
         }
+    }
 
-        if let Some(todo_file) =
-            TodoFile::from_path(&path).map_err(|err| SetupError::todo_file(path, err))?
-        {
-            todo_files.push(todo_file);
+    fn create_directory(path: PathBuf, name: String, source: io::Error) -> Self {
+        Self::CreateDirectory {
+            path,
+            name,
+            source,
         }
     }
 
-    Ok(todo_files)
-}
-
-fn try_main() -> Result<(), SetupError> {
-    let matches = Command::new("devtodo")
-        .version(clap::crate_version!())
-        .author("Ben Boeckel <mathstuf@gmail.com>")
-        .about("Query code hosting platforms for todo items to add to a calendar")
-        .arg(
-            Arg::new("CONFIG")
-                .short('c')
-                .long("config")
-                .help("Path to the configuration file")
-                .value_name("FILE")
-                .action(ArgAction::Set),
-        )
-        .arg(
-            Arg::new("ALL_TARGETS")
-                .short('a')
-                .long("all-targets")
-                .help("Sync all targets")
-                .conflicts_with("TARGET"),
-        )
-        .arg(
-            Arg::new("TARGET")
-                .short('t')
-                .long("target")
-                .help("Name of a target to sync")
-                .action(ArgAction::Append)
-                .number_of_values(1),
-        )
-        .arg(
-            Arg::new("DEBUG")
-                .short('d')
-                .long("debug")
-                .help("Increase verbosity")
-                .action(ArgAction::Count),
-        )
-        .arg(
-            Arg::new("LOGGER")
-                .short('l')
-                .long("logger")
-                .default_value("env")
-                .value_parser(PossibleValuesParser::new(["env"]))
-                .help("Logging backend")
-                .value_name("LOGGER")
-                .action(ArgAction::Set),
-        )
-        .get_matches();
+    fn no_directory(name: String) -> Self {
+        Self::NoDirectory {
+            name,
+        }
+    }
 
-    let log_level = match matches.get_one::<u8>("DEBUG").copied().unwrap_or(0) {
-        0 => LevelFilter::Error,
-        1 => LevelFilter::Warn,
-        2 => LevelFilter::Info,
-        3 => LevelFilter::Debug,
-        _ => LevelFilter::Trace,
-    };
+    fn http_capture(path: PathBuf, source: io::Error) -> Self {
+        Self::HttpCapture {
+            path,
+            source,
+        }
+    }
 
-    let _logger = match matches
-        .get_one::<String>("LOGGER")
-        .expect("logger should have a value")
-        .as_ref()
-    {
-        "env" => {
-            env_logger::Builder::new().filter(None, log_level).init();
-            Logger::Env
-        },
+    fn lock(name: String, source: vdir::LockError) -> Self {
+        Self::Lock {
+            name,
+            source,
+        }
+    }
 
-        logger => {
-            return Err(LogError::UnknownLogger(logger.into()).into());
-        },
-    };
+    fn invalid_duration(value: String) -> Self {
+        Self::InvalidDuration {
+            value,
+        }
+    }
 
-    log::set_max_level(log_level);
+    fn invalid_due(value: String) -> Self {
+        Self::InvalidDue {
+            value,
+        }
+    }
 
-    let basedirs = ProjectDirs::from("net.benboeckel.devtodo", "", "devtodo")
-        .ok_or(SetupError::NoProjectDir)?;
-    let config: Config = {
-        let config_path = if let Some(config) = matches.get_one::<String>("CONFIG") {
-            Path::new(config).into()
-        } else {
-            basedirs.config_dir().join("devtodo.yaml")
-        };
-        let contents = fs::read_to_string(&config_path)
-            .map_err(|err| SetupError::read_config(config_path.clone(), err))?;
-        let doc = serde_yaml::from_str(&contents)
-            .map_err(|err| SetupError::parse_config(config_path.clone(), err))?;
-        let doc = yaml_merge_keys::merge_keys_serde(doc)
-            .map_err(|err| SetupError::merge_keys(config_path.clone(), err))?;
-        serde_yaml::from_value(doc).map_err(|err| SetupError::parse_config(config_path, err))?
-    };
+    fn no_such_target(name: String) -> Self {
+        Self::NoSuchTarget {
+            name,
+        }
+    }
 
-    let accounts = config
-        .accounts
-        .into_iter()
-        .map(|(name, account)| {
-            let item_source =
-                account::connect(account).map_err(|err| SetupError::account(name.clone(), err))?;
-            Ok((name, item_source))
-        })
-        .collect::<Result<BTreeMap<_, _>, SetupError>>()?;
+    fn no_match(pattern: String) -> Self {
+        Self::NoMatch {
+            pattern,
+        }
+    }
 
-    let targets = if matches.get_flag("ALL_TARGETS") {
-        config.targets.keys().cloned().collect()
-    } else {
-        matches
-            .get_many::<String>("TARGET")
-            .map(|values| values.map(Into::into).collect())
-            .unwrap_or(config.default_targets)
-    };
+    fn ambiguous_match(pattern: String, count: usize) -> Self {
+        Self::AmbiguousMatch {
+            pattern,
+            count,
+        }
+    }
 
-    let targets_to_use = config
-        .targets
-        .into_iter()
-        .filter(|(name, _)| targets.iter().any(|target| target == name))
-        .collect::<BTreeMap<_, _>>();
+    fn open_url(url: String, source: io::Error) -> Self {
+        Self::OpenUrl {
+            url,
+            source,
+        }
+    }
 
-    let mut errors = Vec::new();
-    for (name, target) in targets_to_use {
-        let mut todo_files = read_directory(&target.directory, &name)?;
-        let mut url_map = todo_files
-            .iter_mut()
-            .map(|todo_file| (todo_file.item.url().into(), &mut todo_file.item))
-            .collect::<BTreeMap<String, _>>();
-
-        let mut all_new_items = Vec::new();
-        for (name, profile) in target.profiles {
-            let item_source = accounts
-                .get(&profile.account)
-                .ok_or_else(|| SetupError::no_such_account(profile.account.clone()))?;
-            let new_items = item_source
-                .fetch_items(&profile.target, &profile.filters, &mut url_map)
-                .map_err(|err| SetupError::fetch_items(profile.account, name, err))?;
-            all_new_items.extend(new_items);
-        }
-
-        let mut write_item = |url: String, item| {
-            if let Err(err) = item {
-                error!(
-                    "failed to write todo for {} in the {} target: {:?}",
-                    url, name, err,
-                );
-                errors.push((
-                    format!(
-                        "failed to write todo for {} in the {} target: {}",
-                        url, name, err,
-                    ),
-                    err,
-                ));
-            }
-        };
+    fn write_changes(path: PathBuf, source: io::Error) -> Self {
+        Self::WriteChanges {
+            path,
+            source,
+        }
+    }
 
-        for todo_item in all_new_items {
-            let url = todo_item.url().into();
-            write_item(
-                url,
-                TodoFile::from_item(&target.directory, todo_item).map(|_| ()),
-            );
+    fn serve(source: io::Error) -> Self {
+        Self::Serve {
+            source,
         }
+    }
 
-        for mut todo_file in todo_files {
-            let url = todo_file.item.url().into();
-            write_item(url, todo_file.write());
+    fn runtime(source: io::Error) -> Self {
+        Self::Runtime {
+            source,
         }
     }
 
-    if errors.is_empty() {
+    #[cfg(feature = "caldav")]
+    fn caldav(name: String, source: caldav::CaldavError) -> Self {
+        Self::Caldav {
+            name,
+            source,
+        }
+    }
+
+    fn invalid_status(value: String) -> Self {
+        Self::InvalidStatus {
+            value,
+        }
+    }
+
+    fn invalid_kind(value: String) -> Self {
+        Self::InvalidKind {
+            value,
+        }
+    }
+
+    fn clear_cache(path: PathBuf, source: io::Error) -> Self {
+        Self::ClearCache {
+            path,
+            source,
+        }
+    }
+
+    fn account_test(names: Vec<String>) -> Self {
+        Self::AccountTest {
+            names,
+        }
+    }
+
+    fn target_errors(errors: Vec<(String, SetupError)>) -> Self {
+        Self::TargetErrors {
+            errors,
+        }
+    }
+
+    fn write_errors(errors: Vec<(String, todo::TodoError)>) -> Self {
+        Self::WriteErrors {
+            errors,
+        }
+    }
+}
+
+/// Where the configuration was read from.
+#[derive(Debug, Clone)]
+enum ConfigOrigin {
+    /// A configuration file.
+    File(PathBuf),
+    /// Environment variables.
+    Environment,
+}
+
+impl fmt::Display for ConfigOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigOrigin::File(path) => write!(f, "configuration file {}", path.display()),
+            ConfigOrigin::Environment => write!(f, "configuration from the environment"),
+        }
+    }
+}
+
+/// Load the configuration.
+///
+/// Also returns whether the configuration uses an older layout.
+fn load_config(origin: &ConfigOrigin, contents: &str) -> Result<(Config, bool), SetupError> {
+    let doc = serde_yaml::from_str(contents)
+        .map_err(|err| SetupError::parse_config(origin.clone(), err))?;
+    let mut doc = yaml_merge_keys::merge_keys_serde(doc)
+        .map_err(|err| SetupError::merge_keys(origin.clone(), err))?;
+    let outdated =
+        config::migrate(&mut doc).map_err(|err| SetupError::migrate_config(origin.clone(), err))?;
+    config::apply_account_defaults(&mut doc);
+    let config: Config =
+        serde_yaml::from_value(doc).map_err(|err| SetupError::parse_config(origin.clone(), err))?;
+    config
+        .validate()
+        .map_err(|err| SetupError::invalid_config(origin.clone(), err))?;
+
+    Ok((config, outdated))
+}
+
+/// Open the file recording the log of the current sync.
+fn open_run_log(state_dir: &Path) -> io::Result<File> {
+    fs::create_dir_all(state_dir)?;
+    File::create(state_dir.join(bugreport::RUN_LOG_FILE))
+}
+
+/// Set up the logger.
+///
+/// Command line flags take precedence over the configuration. Messages are also recorded
+/// into the run log if one is given.
+fn init_logger(
+    matches: &ArgMatches,
+    config: Option<&LoggingConfig>,
+    run_log: Option<File>,
+) -> Result<Logger, LogError> {
+    let log_level = match matches.get_one::<u8>("DEBUG").copied().unwrap_or(0) {
+        0 => {
+            config
+                .and_then(|config| config.level)
+                .map(|level| level.0)
+                .unwrap_or(LevelFilter::Error)
+        },
+        1 => LevelFilter::Warn,
+        2 => LevelFilter::Info,
+        3 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let mut filters = config
+        .map(|config| {
+            config
+                .filters
+                .iter()
+                .map(|(module, level)| (module.clone(), level.0))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if let Some(directives) = matches.get_many::<String>("LOG_FILTER") {
+        for directive in directives.flat_map(|directives| directives.split(',')) {
+            filters.push(parse_log_filter(directive)?);
+        }
+    }
+    let max_level = filters
+        .iter()
+        .map(|&(_, level)| level)
+        .fold(log_level, std::cmp::max);
+
+    let logger = matches
+        .get_one::<String>("LOGGER")
+        .map(String::as_str)
+        .or_else(|| config.and_then(|config| config.logger.as_deref()))
+        .unwrap_or("env");
+    let logger = match logger {
+        "env" => {
+            let mut builder = env_logger::Builder::new();
+            let run_log = run_log.map(Mutex::new);
+            builder.filter(None, log_level).format(move |buf, record| {
+                let timestamp = buf.timestamp();
+                let message = record.args().to_string();
+                let message = redact::redact(&message);
+                if let Some(mut run_log) = run_log.as_ref().and_then(|log| log.lock().ok()) {
+                    // The run log is best-effort.
+                    let _ = writeln!(
+                        run_log,
+                        "[{} {:<5} {}] {}",
+                        timestamp,
+                        record.level(),
+                        record.target(),
+                        message,
+                    );
+                }
+                let level = buf.default_styled_level(record.level());
+                writeln!(
+                    buf,
+                    "[{} {:<5} {}] {}",
+                    timestamp,
+                    level,
+                    record.target(),
+                    message,
+                )
+            });
+            for (name, level) in filters {
+                for module in log_modules(&name) {
+                    builder.filter(Some(&module), level);
+                }
+            }
+            builder.init();
+            Logger::Env
+        },
+
+        logger => {
+            return Err(LogError::UnknownLogger(logger.into()));
+        },
+    };
+
+    log::set_max_level(max_level);
+
+    Ok(logger)
+}
+
+/// The log targets to filter for a name.
+///
+/// Service names (e.g., `github`) refer to the module implementing the service as well as
+/// the service's own log target.
+fn log_modules(name: &str) -> Vec<String> {
+    if account::SERVICES.contains(&name) {
+        vec![name.into(), format!("devtodo::account::{}", name)]
+    } else {
+        vec![name.into()]
+    }
+}
+
+/// Parse a `module=level` log filter directive.
+fn parse_log_filter(directive: &str) -> Result<(String, LevelFilter), LogError> {
+    directive
+        .split_once('=')
+        .and_then(|(module, level)| {
+            let module = module.trim();
+            if module.is_empty() {
+                return None;
+            }
+            level
+                .trim()
+                .parse()
+                .ok()
+                .map(|level| (module.into(), level))
+        })
+        .ok_or_else(|| LogError::InvalidFilter(directive.into()))
+}
+
+/// Rewrite a configuration file using the current layout.
+///
+/// The original file is kept with a `.bak` extension.
+fn migrate_config(path: &Path, contents: &str) -> Result<(), SetupError> {
+    let origin = || ConfigOrigin::File(path.into());
+    let mut doc =
+        serde_yaml::from_str(contents).map_err(|err| SetupError::parse_config(origin(), err))?;
+    if !config::migrate(&mut doc).map_err(|err| SetupError::migrate_config(origin(), err))? {
+        info!(
+            "{} already uses configuration version {}",
+            path.display(),
+            config::CONFIG_VERSION,
+        );
+        return Ok(());
+    }
+
+    let migrated = serde_yaml::to_string(&doc)
+        .map_err(|err| SetupError::serialize_config(path.into(), err))?;
+    let backup = {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        PathBuf::from(backup)
+    };
+    fs::write(&backup, contents).map_err(|err| SetupError::write_config(backup, err))?;
+    fs::write(path, migrated).map_err(|err| SetupError::write_config(path.into(), err))
+}
+
+/// Import `VTODO` files written by other tools into a target.
+///
+/// Items with the same URL as an existing item in the target are skipped since the existing item
+/// already tracks it.
+fn import_items(name: &str, target: &SyncTarget, path: &Path) -> Result<(), SetupError> {
+    let paths = if path.is_dir() {
+        fs::read_dir(path)
+            .map_err(|err| SetupError::read_dir(path.into(), name.into(), err))?
+            .map(|entry| {
+                entry
+                    .map(|entry| entry.path())
+                    .map_err(|err| SetupError::read_entry(name.into(), err))
+            })
+            .filter_ok(|path| path.extension().is_some_and(|ext| ext == "ics"))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![path.into()]
+    };
+
+    let mut known_urls = read_target_items(name, target)?
+        .into_iter()
+        .map(|todo_file| todo_file.item.url().to_string())
+        .collect::<BTreeSet<_>>();
+    let write_mode = if target.atomic_writes {
+        WriteMode::Atomic
+    } else {
+        WriteMode::InPlace
+    };
+
+    let (mut imported, mut matched, mut failed) = (0, 0, 0);
+    for path in paths {
+        let vtodos = match TodoFile::read_foreign(&path) {
+            Ok(vtodos) => vtodos,
+            Err(err) => {
+                warn!("failed to read {}: {:?}", path.display(), err);
+                failed += 1;
+                continue;
+            },
+        };
+        for mut vtodo in vtodos {
+            let url = vtodo.get_only("URL").map(|url| url.value_as_string());
+            if let Some(url) = url {
+                if known_urls.contains(&url) {
+                    info!(
+                        "skipping an item from {} already in the {} target",
+                        path.display(),
+                        name,
+                    );
+                    matched += 1;
+                    continue;
+                }
+            } else {
+                // Items without a URL are not tied to any service.
+                vtodo.set(Property::new("URL", local::new_url()));
+            }
+
+            match TodoFile::import(&path, &target.directory, vtodo, write_mode) {
+                Ok(mut todo_file) => {
+                    // Sort the item into its collection.
+                    let directory = target.directory_for(todo_file.item.kind());
+                    if directory != target.directory {
+                        todo_file
+                            .move_to(&directory)
+                            .map_err(|err| SetupError::todo_file(path.clone(), err))?;
+                    }
+                    known_urls.insert(todo_file.item.url().into());
+                    imported += 1;
+                },
+                Err(err) => {
+                    warn!(
+                        "failed to import an item from {}: {:?}",
+                        path.display(),
+                        err
+                    );
+                    failed += 1;
+                },
+            }
+        }
+    }
+
+    println!(
+        "imported {} items into {} ({} already present, {} failed)",
+        imported, name, matched, failed,
+    );
+
+    Ok(())
+}
+
+/// Add an item which is not tied to any service to a target.
+fn add_item(
+    name: &str,
+    target: &SyncTarget,
+    state_dir: &Path,
+    mut item: todo::TodoItem,
+) -> Result<(), SetupError> {
+    let _lock = if target.lock {
+        Some(
+            vdir::DirectoryLock::acquire(&target.directory)
+                .map_err(|err| SetupError::lock(name.into(), err))?,
+        )
+    } else {
+        None
+    };
+    let write_mode = if target.atomic_writes {
+        WriteMode::Atomic
+    } else {
+        WriteMode::InPlace
+    };
+    if target.uid_scheme == UidScheme::Url {
+        item.use_url_uid();
+    }
+    if let Some(time) = target.due_time {
+        item.set_due_time(time.0);
+    }
+
+    let created = changelog::Entry::created(name, &item, Utc::now());
+    let directory = target.directory_for(item.kind());
+    let todo_file = TodoFile::from_item(&directory, item, write_mode)
+        .map_err(|err| SetupError::todo_file(directory, err))?;
+    changelog::append(state_dir, &created)?;
+    println!("{}: added {}", name, todo_file.path().display());
+
+    Ok(())
+}
+
+/// Mark items as completed.
+///
+/// Items with the pattern as their URL are completed in every target they are in. Otherwise,
+/// the pattern must match a single outstanding item.
+fn complete_items(
+    targets: &BTreeMap<String, SyncTarget>,
+    state_dir: &Path,
+    pattern: &str,
+) -> Result<(), SetupError> {
+    let mut by_url = Vec::new();
+    let mut found = Vec::new();
+    for res in read_all_items(targets) {
+        let (name, target, todo_files) = res?;
+        for todo_file in todo_files {
+            if todo_file.item.url() == pattern {
+                by_url.push((name, target, todo_file));
+            } else if !todo_file.item.status().is_done() && open::is_match(pattern, &todo_file.item)
+            {
+                found.push((name, target, todo_file));
+            }
+        }
+    }
+
+    let to_complete = if by_url.is_empty() {
+        match found.len() {
+            0 => return Err(SetupError::no_match(pattern.into())),
+            1 => found,
+            count => {
+                for (_, _, todo_file) in &found {
+                    println!("{}\t{}", todo_file.item.url(), todo_file.item.summary());
+                }
+                return Err(SetupError::ambiguous_match(pattern.into(), count));
+            },
+        }
+    } else {
+        by_url
+    };
+
+    // Group the items by target so that each target is only locked once.
+    let mut by_target: BTreeMap<&str, (&SyncTarget, BTreeSet<String>)> = BTreeMap::new();
+    for (name, target, todo_file) in to_complete {
+        by_target
+            .entry(name)
+            .or_insert_with(|| (target, BTreeSet::new()))
+            .1
+            .insert(todo_file.item.url().into());
+    }
+
+    let now = Utc::now();
+    let mut changes = Vec::new();
+    for (name, (target, urls)) in by_target {
+        let _lock = if target.lock {
+            Some(
+                vdir::DirectoryLock::acquire(&target.directory)
+                    .map_err(|err| SetupError::lock(name.into(), err))?,
+            )
+        } else {
+            None
+        };
+        let write_mode = if target.atomic_writes {
+            WriteMode::Atomic
+        } else {
+            WriteMode::InPlace
+        };
+
+        // A sync may have changed the files since they were searched, so read them again while
+        // holding the lock.
+        let todo_files = read_target_items(name, target)?
+            .into_iter()
+            .filter(|todo_file| urls.contains(todo_file.item.url()));
+        for mut todo_file in todo_files {
+            if todo_file.item.status() == TodoStatus::Completed {
+                println!("{}: {} is already completed", name, todo_file.item.url());
+                continue;
+            }
+
+            let snapshot = changelog::Snapshot::new(&todo_file.item);
+            todo_file
+                .complete(write_mode)
+                .map_err(|err| SetupError::todo_file(todo_file.path().into(), err))?;
+            changes.extend(changelog::Entry::changed(
+                name,
+                &snapshot,
+                &todo_file.item,
+                now,
+            ));
+            println!(
+                "{}: completed {}\t{}",
+                name,
+                todo_file.item.url(),
+                todo_file.item.summary(),
+            );
+        }
+    }
+    changelog::append(state_dir, &changes)?;
+
+    Ok(())
+}
+
+/// Read the items of a target, including its collections, for local inspection.
+fn read_target_items(name: &str, target: &SyncTarget) -> Result<Vec<TodoFile>, SetupError> {
+    let options = SyncOptions {
+        strict: false,
+        repair: false,
+        diff: false,
+        dry_run: false,
+        create_dirs: false,
+        no_prune: false,
+    };
+
+    let mut todo_files = read_directory(&target.directory, name, target, &options)?;
+    for collection_name in target.collections.keys() {
+        let collection_dir = target.directory.join(collection_name);
+        todo_files.extend(read_directory(&collection_dir, name, target, &options)?);
+    }
+
+    Ok(todo_files)
+}
+
+/// Options which affect how targets are synced.
+struct SyncOptions {
+    /// Fail on malformed files rather than ignoring them.
+    strict: bool,
+    /// Move malformed files out of the way.
+    repair: bool,
+    /// Print the changes which would be made instead of making them.
+    diff: bool,
+    /// Summarize the changes which would be made instead of making them.
+    dry_run: bool,
+    /// Create missing target directories.
+    create_dirs: bool,
+    /// Leave items which have expired under a retention policy in place.
+    no_prune: bool,
+}
+
+/// The changes a dry run would make to a target.
+#[derive(Default)]
+struct DryRunSummary {
+    created: usize,
+    updated: usize,
+    unchanged: usize,
+    expired: usize,
+}
+
+impl DryRunSummary {
+    fn print(&self, name: &str) {
+        print!(
+            "{}: {} to create, {} to update, {} unchanged",
+            name, self.created, self.updated, self.unchanged,
+        );
+        if self.expired > 0 {
+            print!(", {} to expire", self.expired);
+        }
+        println!();
+    }
+}
+
+impl SyncOptions {
+    /// Whether the sync must leave targets and state alone.
+    fn is_read_only(&self) -> bool {
+        self.diff || self.dry_run
+    }
+}
+
+/// What syncing targets has done.
+#[derive(Default)]
+struct SyncRecord {
+    /// Changes made to items.
+    changes: Vec<changelog::Entry>,
+    /// Timing information for each target.
+    timings: BTreeMap<String, TargetTiming>,
+    /// Whether synced items are completed locally while still open on their service.
+    completions: BTreeMap<String, bool>,
+    /// The items changed by syncing.
+    change_set: ChangeSet,
+    /// Items which profiles stopped returning that have been closed (by URL).
+    closed_missing: BTreeSet<String>,
+}
+
+fn read_directory(
+    dirpath: &Path,
+    name: &str,
+    target: &SyncTarget,
+    options: &SyncOptions,
+) -> Result<Vec<TodoFile>, SetupError> {
+    let mut todo_files = Vec::new();
+    let dir_iter = fs::read_dir(dirpath)
+        .map_err(|err| SetupError::read_dir(dirpath.into(), name.into(), err))?;
+    for entry in dir_iter {
+        let entry = entry.map_err(|err| SetupError::read_entry(name.into(), err))?;
+        let path = entry.path();
+
+        // Only look at `.ics` files.
+        if path.extension().map(|ext| ext != "ics").unwrap_or(true) {
+            continue;
+        }
+
+        // Check the filetype.
+        match entry.metadata() {
+            Ok(md) => {
+                let filetype = md.file_type();
+                if filetype.is_dir() {
+                    // Ignore directories.
+                    continue;
+                }
+                // Get the actual file we're dealing with here.
+                let real_filetype = if filetype.is_symlink() {
+                    match path.metadata() {
+                        Ok(real_md) => real_md.file_type(),
+                        Err(err) => {
+                            warn!(
+                                "failed to read target metadata for {}: {}; ignoring",
+                                path.display(),
+                                err,
+                            );
+                            continue;
+                        },
+                    }
+                } else {
+                    filetype
+                };
+                // Ignore non-files.
+                if !real_filetype.is_file() {
+                    continue;
+                }
+            },
+            Err(err) => {
+                warn!(
+                    "failed to read metadata for {}: {}; ignoring",
+                    path.display(),
+                    err,
+                );
+                continue;
+            },
+        }
+
+        match TodoFile::from_path(&path, &target.claim_prodids) {
+            Ok(Some(mut todo_file)) => {
+                todo_file.protect_properties(&target.protected_properties);
+                todo_files.push(todo_file);
+            },
+            Ok(None) => (),
+            Err(
+                err @ todo::TodoError::MalformedItem {
+                    ..
+                },
+            ) => {
+                // Ignored files are recreated by the sync, so they end up duplicated.
+                if options.repair {
+                    let new_path = TodoFile::set_aside(&path)
+                        .map_err(|err| SetupError::todo_file(path.clone(), err))?;
+                    warn!("{}; moved to {}", err, new_path.display());
+                } else if options.strict {
+                    return Err(SetupError::todo_file(path, err));
+                } else {
+                    warn!("{}; ignoring", err);
+                }
+            },
+            Err(err) => return Err(SetupError::todo_file(path, err)),
+        }
+    }
+
+    Ok(todo_files)
+}
+
+/// Remove files which share a URL with another file.
+///
+/// For each URL, the oldest file is kept (using the path to break ties). Duplicates are only
+/// moved aside if `set_aside` is set; otherwise they are just ignored.
+fn remove_duplicates(
+    mut todo_files: Vec<TodoFile>,
+    name: &str,
+    set_aside: bool,
+    errors: &mut Vec<(String, todo::TodoError)>,
+) -> Vec<TodoFile> {
+    todo_files.sort_by(|lhs, rhs| {
+        (lhs.item.url(), lhs.item.created(), lhs.path()).cmp(&(
+            rhs.item.url(),
+            rhs.item.created(),
+            rhs.path(),
+        ))
+    });
+
+    let mut kept: Vec<TodoFile> = Vec::with_capacity(todo_files.len());
+    for todo_file in todo_files {
+        let survivor = match kept.last() {
+            Some(last) if last.item.url() == todo_file.item.url() => last,
+            _ => {
+                kept.push(todo_file);
+                continue;
+            },
+        };
+
+        if !set_aside {
+            warn!(
+                "ignoring {} in the {} target; it duplicates {} for {}",
+                todo_file.path().display(),
+                name,
+                survivor.path().display(),
+                todo_file.item.url(),
+            );
+            continue;
+        }
+
+        // The duplicate may have been edited, so keep it around for the user to look at.
+        let path = todo_file.path().to_path_buf();
+        let survivor = survivor.path().to_path_buf();
+        let url = todo_file.item.url().to_string();
+        match todo_file.set_aside_duplicate() {
+            Ok(new_path) => {
+                warn!(
+                    "{} in the {} target duplicates {} for {}; moved to {}",
+                    path.display(),
+                    name,
+                    survivor.display(),
+                    url,
+                    new_path.display(),
+                );
+            },
+            Err(err) => {
+                errors.push((
+                    format!(
+                        "failed to move aside duplicate todo for {} in the {} target: {}",
+                        url, name, err,
+                    ),
+                    err,
+                ));
+            },
+        }
+    }
+
+    kept
+}
+
+// The marker appended to descriptions which have been truncated.
+const TRUNCATION_MARKER: &str = "\n\n[…]";
+
+/// Truncate a string to at most `len` bytes without splitting a character.
+fn truncate_to(text: &mut String, len: usize) {
+    if text.len() > len {
+        let mut end = len;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+}
+
+fn format_description(target: &SyncTarget, item: &SourceItem) -> String {
+    let mut description = item.description.clone();
+
+    let footer = target.description_footer.as_ref().map(|footer| {
+        let time = item
+            .updated_at
+            .map(|time| format!("{}", time.format(DATE_TIME_FMT)))
+            .unwrap_or_default();
+        footer.replace("{url}", &item.url).replace("{time}", &time)
+    });
+    // The footer is separated from the description by a blank line.
+    let footer_len = footer.as_ref().map_or(0, |footer| footer.len() + 2);
+
+    if let Some(max_length) = target.max_description_length {
+        // Room is left for the footer so that the result fits within the limit.
+        if !description.is_empty() && description.len() + footer_len > max_length {
+            let len = max_length.saturating_sub(footer_len + TRUNCATION_MARKER.len());
+            truncate_to(&mut description, len);
+            description.push_str(TRUNCATION_MARKER);
+        }
+    }
+
+    if let Some(footer) = footer {
+        if !description.is_empty() {
+            description.push_str("\n\n");
+        }
+        description.push_str(&footer);
+    }
+
+    // Footers which are too long on their own are cut off as well.
+    if let Some(max_length) = target.max_description_length {
+        truncate_to(&mut description, max_length);
+    }
+
+    description
+}
+
+/// Apply per-profile adjustments to items fetched for the profile.
+fn apply_profile(profile: &Profile, results: &mut Vec<SourceItem>) {
+    if profile.confidential == ConfidentialPolicy::Skip {
+        results.retain(|result| !result.confidential);
+    }
+
+    // Assignees are only of interest to team queries and only for the members of the team.
+    let team = if let QueryTarget::Team(users) = &profile.target {
+        users.as_slice()
+    } else {
+        &[]
+    };
+
+    for result in results {
+        result.assignees.retain(|assignee| team.contains(assignee));
+        result.due = result.due_dates.resolve(&profile.due_fallback.0);
+        let label_priority = result
+            .labels
+            .iter()
+            .filter_map(|label| profile.label_priorities.get(label))
+            .min();
+        if let Some(&priority) = label_priority {
+            result.priority = Some(priority);
+        }
+
+        if result.confidential {
+            match profile.confidential {
+                ConfidentialPolicy::Private => result.private = true,
+                ConfidentialPolicy::Redact => {
+                    // References include the repository name, so leave them out as well.
+                    result.redact();
+                    continue;
+                },
+                ConfidentialPolicy::Include | ConfidentialPolicy::Skip => (),
+            }
+        }
+
+        if profile.summary_prefix {
+            if let Some(reference) = account::short_reference(&result.url) {
+                result.summary = format!("{} {}", reference, result.summary);
+            }
+        }
+    }
+}
+
+/// Connected accounts.
+struct Accounts {
+    /// Connections by name.
+    ///
+    /// The users of an account are connected separately under their identity name.
+    sources: BTreeMap<String, Box<dyn ItemSource>>,
+    /// Names which refer to a list of accounts.
+    aliases: BTreeMap<String, Vec<String>>,
+}
+
+impl Accounts {
+    /// The name of the connection for a user of an account.
+    fn identity_name(account: &str, user: &str) -> String {
+        format!("{}/{}", account, user)
+    }
+
+    /// The accounts a name refers to.
+    ///
+    /// With `as_user`, the connections for that user of the accounts are used instead.
+    fn resolve<'a>(
+        &'a self,
+        name: &'a str,
+        as_user: Option<&str>,
+    ) -> Result<Vec<(&'a str, &'a dyn ItemSource)>, SetupError> {
+        let lookup = |name: &'a str| {
+            if !self.sources.contains_key(name) {
+                return Err(SetupError::no_such_account(name.into()));
+            }
+            let key = if let Some(user) = as_user {
+                Self::identity_name(name, user)
+            } else {
+                name.into()
+            };
+            self.sources
+                .get_key_value(&key)
+                .map(|(name, item_source)| (name.as_str(), item_source.as_ref()))
+                .ok_or_else(|| {
+                    SetupError::no_such_user(name.into(), as_user.unwrap_or_default().into())
+                })
+        };
+
+        if let Some(names) = self.aliases.get(name) {
+            names.iter().map(|name| lookup(name)).collect()
+        } else {
+            Ok(vec![lookup(name)?])
+        }
+    }
+}
+
+/// Store the token for an account in the keyring.
+fn set_auth(config: &Config, name: &str) -> Result<(), SetupError> {
+    let account = config
+        .accounts
+        .get(name)
+        .ok_or_else(|| SetupError::no_such_account(name.into()))?;
+    let service = account
+        .secret
+        .0
+        .iter()
+        .find_map(|secret| {
+            if let Secret::Keyring {
+                keyring,
+            } = secret
+            {
+                Some(keyring)
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| SetupError::no_keyring(name.into()))?;
+
+    let host = account::secret_host(account);
+    secret::store_keyring(service, host)?;
+    println!(
+        "stored the token for {} in the {} keyring entry",
+        host, service
+    );
+
+    Ok(())
+}
+
+async fn test_accounts(accounts: &Accounts, name: Option<&String>) -> Result<(), SetupError> {
+    let accounts_to_test = if let Some(name) = name {
+        accounts.resolve(name, None)?
+    } else {
+        accounts
+            .sources
+            .iter()
+            .map(|(name, item_source)| (name.as_str(), item_source.as_ref()))
+            .collect()
+    };
+
+    let mut failed = Vec::new();
+    for (name, item_source) in accounts_to_test {
+        let start = Instant::now();
+        let identity = item_source.identity().await;
+        let latency = start.elapsed();
+
+        match identity {
+            Ok(identity) => {
+                let remaining = identity
+                    .rate_limit_remaining
+                    .map(|remaining| remaining.to_string())
+                    .unwrap_or_else(|| "unknown".into());
+                println!(
+                    "{}: ok as {} ({} ms, {} requests remaining)",
+                    name,
+                    identity.login,
+                    latency.as_millis(),
+                    remaining,
+                );
+            },
+            Err(err) => {
+                println!("{}: failed: {} ({} ms)", name, err, latency.as_millis());
+                failed.push(name.into());
+            },
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(SetupError::account_test(failed))
+    }
+}
+
+/// A profile of a target to fetch using an account.
+struct FetchJob<'a> {
+    target: &'a str,
+    profile_name: &'a str,
+    profile: &'a Profile,
+    account_name: &'a str,
+    item_source: &'a dyn ItemSource,
+}
+
+/// The result of fetching a profile using an account.
+enum Fetched {
+    /// The items for the profile.
+    Items(Vec<SourceItem>),
+    /// The profile should be tried again on the next run for the given reason.
+    Deferred(String),
+    /// Fetching the profile failed.
+    Failed(account::ItemError),
+}
+
+/// Note how accounts fared while fetching profiles.
+fn record_account_statuses(
+    jobs: &[&FetchJob],
+    fetched: &[(Fetched, ProfileTiming)],
+    statuses: &mut BTreeMap<String, AccountStatus>,
+) {
+    let now = Utc::now();
+    for (job, (fetched, _)) in jobs.iter().zip(fetched) {
+        let status = statuses.entry(job.account_name.into()).or_default();
+        status.last_used = Some(now);
+        if let Some(rate_limit) = job.item_source.rate_limit() {
+            status.rate_limit_remaining = Some(rate_limit.remaining);
+            status.rate_limit_reset = rate_limit.reset_at;
+        }
+
+        let message = match fetched {
+            Fetched::Items(_) => continue,
+            Fetched::Deferred(reason) => reason.clone(),
+            Fetched::Failed(err) => err.to_string(),
+        };
+        status.last_error = Some(state::AccountError {
+            time: now,
+            message,
+        });
+    }
+}
+
+/// Show when targets were last synced and how accounts fared in the runs which used them.
+fn show_status(config: &Config, state: &State) {
+    let format_time = |time: Option<DateTime<Utc>>| {
+        time.map_or_else(
+            || "never".into(),
+            |time| format!("{}", time.format(DATE_TIME_FMT)),
+        )
+    };
+
+    for name in config.targets.keys() {
+        let deferred = state
+            .deferred_profiles
+            .iter()
+            .filter_map(|key| key.strip_prefix(name.as_str())?.strip_prefix('/'))
+            .collect::<Vec<_>>();
+        let deferred = if deferred.is_empty() {
+            String::new()
+        } else {
+            format!("; deferred profiles: {}", deferred.join(", "))
+        };
+        println!(
+            "target {}: last synced {}{}",
+            name,
+            format_time(state.last_synced.get(name).copied()),
+            deferred,
+        );
+    }
+
+    for (name, status) in &state.accounts {
+        let rate_limit = if let Some(remaining) = status.rate_limit_remaining {
+            format!(
+                "{} requests remaining (resets {})",
+                remaining,
+                status.rate_limit_reset.map_or_else(
+                    || "at an unknown time".into(),
+                    |reset| { format!("at {}", reset.format(DATE_TIME_FMT)) }
+                ),
+            )
+        } else {
+            "unknown rate limit".into()
+        };
+        let last_error = if let Some(error) = status.last_error.as_ref() {
+            format!(
+                "last error at {}: {}",
+                error.time.format(DATE_TIME_FMT),
+                error.message,
+            )
+        } else {
+            "no errors".into()
+        };
+        println!(
+            "account {}: last used {}; {}; {}",
+            name,
+            format_time(status.last_used),
+            rate_limit,
+            last_error,
+        );
+    }
+}
+
+/// Determine the fetches needed for the profiles of a target.
+fn plan_target<'a>(
+    name: &'a str,
+    target: &'a SyncTarget,
+    accounts: &'a Accounts,
+    state: &State,
+) -> Result<Vec<FetchJob<'a>>, SetupError> {
+    // Profiles deferred by the previous run go first.
+    let profiles = target.profiles.iter().sorted_by_key(|(profile_name, _)| {
+        !state
+            .deferred_profiles
+            .contains(&State::profile_key(name, profile_name))
+    });
+
+    let mut jobs = Vec::new();
+    for (profile_name, profile) in profiles {
+        for (account_name, item_source) in
+            accounts.resolve(&profile.account, profile.as_user.as_deref())?
+        {
+            jobs.push(FetchJob {
+                target: name,
+                profile_name,
+                profile,
+                account_name,
+                item_source,
+            });
+        }
+    }
+
+    Ok(jobs)
+}
+
+/// Fetch a profile using an account.
+async fn fetch_profile(job: &FetchJob<'_>) -> (Fetched, ProfileTiming) {
+    let start = Instant::now();
+    let requests_before = job.item_source.requests_made();
+    let fetched = if job.item_source.is_exhausted() {
+        Fetched::Deferred(format!(
+            "request budget exhausted for the {} account",
+            job.account_name,
+        ))
+    } else {
+        match job.item_source.fetch_items(job.profile).await {
+            Ok(results) => Fetched::Items(results),
+            Err(err) if err.is_deferrable() => Fetched::Deferred(err.to_string()),
+            Err(err) => Fetched::Failed(err),
+        }
+    };
+
+    let timing = ProfileTiming {
+        profile: job.profile_name.into(),
+        fetch: start.elapsed(),
+        requests: job.item_source.requests_made() - requests_before,
+    };
+
+    (fetched, timing)
+}
+
+/// Fetch profiles.
+///
+/// Different accounts are fetched from concurrently. Profiles using the same account are
+/// fetched in order. Results are returned in the order of the jobs.
+async fn fetch_profiles(jobs: &[&FetchJob<'_>]) -> Vec<(Fetched, ProfileTiming)> {
+    let by_account = jobs
+        .iter()
+        .enumerate()
+        .into_group_map_by(|(_, job)| job.account_name);
+
+    let accounts = by_account.into_values().map(|account_jobs| {
+        async move {
+            let mut fetched = Vec::new();
+            for (idx, job) in account_jobs {
+                fetched.push((idx, fetch_profile(job).await));
+            }
+            fetched
+        }
+    });
+    let mut fetched = future::join_all(accounts)
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    fetched.sort_by_key(|&(idx, _)| idx);
+
+    fetched.into_iter().map(|(_, fetched)| fetched).collect()
+}
+
+/// The items gathered for a target.
+struct Collected {
+    /// The items fetched for the target's profiles.
+    results: Vec<SourceItem>,
+    /// Timing information for the target.
+    timing: TargetTiming,
+    /// The items returned by profiles which close missing items.
+    returns: Vec<ProfileReturns>,
+}
+
+/// Gather the fetched items for the profiles of a target.
+fn collect_target<I>(
+    jobs: &[FetchJob],
+    fetched: I,
+    deferred_profiles: &mut BTreeSet<String>,
+    first_synced: &mut BTreeMap<String, DateTime<Utc>>,
+    write_backs: &mut WriteBacks,
+) -> Result<Collected, SetupError>
+where
+    I: IntoIterator<Item = (Fetched, ProfileTiming)>,
+{
+    let mut all_results = Vec::new();
+    let mut timing = TargetTiming::default();
+    let mut returns = Vec::new();
+    let mut fetched_profiles = BTreeSet::new();
+    let mut newly_deferred = BTreeSet::new();
+    for (job, (fetched, profile_timing)) in jobs.iter().zip(fetched) {
+        timing.profiles.push(profile_timing);
+
+        match fetched {
+            Fetched::Items(mut results) => {
+                fetched_profiles.insert(State::profile_key(job.target, job.profile_name));
+                if let Some(close_missing) = job.profile.close_missing() {
+                    returns.push(ProfileReturns {
+                        key: State::profile_key(job.target, job.profile_name),
+                        config: close_missing,
+                        urls: results.iter().map(|result| result.url.clone()).collect(),
+                    });
+                }
+                if let Some(window) = job.profile.initial_window {
+                    let first_synced = *first_synced
+                        .entry(State::profile_key(job.target, job.profile_name))
+                        .or_insert_with(Utc::now);
+                    let cutoff = first_synced - window.0;
+                    results.retain(|result| {
+                        result
+                            .updated_at
+                            .is_none_or(|updated_at| updated_at >= cutoff)
+                    });
+                }
+                apply_profile(job.profile, &mut results);
+                write_backs.add(job.account_name, job.profile, &results);
+                all_results.extend(results);
+            },
+            Fetched::Deferred(reason) => {
+                warn!(
+                    "{}; deferring the {} profile of the {} target to the next run",
+                    reason, job.profile_name, job.target,
+                );
+                newly_deferred.insert(State::profile_key(job.target, job.profile_name));
+            },
+            Fetched::Failed(err) => {
+                return Err(SetupError::fetch_items(
+                    job.account_name.into(),
+                    job.profile_name.into(),
+                    err,
+                ));
+            },
+        }
+    }
+
+    // Profiles using more than one account stay deferred if any of them was deferred.
+    for key in &fetched_profiles {
+        deferred_profiles.remove(key);
+    }
+    deferred_profiles.extend(newly_deferred);
+
+    Ok(Collected {
+        results: all_results,
+        timing,
+        returns,
+    })
+}
+
+/// Sync fetched items into a target.
+fn sync_target(
+    name: &str,
+    target: &SyncTarget,
+    all_results: Vec<SourceItem>,
+    mut timing: TargetTiming,
+    missing: &BTreeMap<String, TodoStatus>,
+    record: &mut SyncRecord,
+    options: &SyncOptions,
+) -> Result<(), SetupError> {
+    let mut errors = Vec::new();
+
+    if let Some(format) = target.report {
+        timing.log(name);
+        report::write(
+            &mut io::stdout().lock(),
+            name,
+            format,
+            &all_results,
+            &timing,
+        )
+        .map_err(|err| SetupError::report(name.into(), err))?;
+        record.timings.insert(name.into(), timing);
+        return Ok(());
+    }
+
+    if target.directory.as_os_str().is_empty() {
+        return Err(SetupError::no_directory(name.into()));
+    }
+
+    let create_dirs = target.create_directory || options.create_dirs;
+    if create_dirs && !options.is_read_only() && !target.directory.exists() {
+        vdir::create_directory(&target.directory).map_err(|err| {
+            SetupError::create_directory(target.directory.clone(), name.into(), err)
+        })?;
+    }
+
+    let _lock = if target.lock && !options.is_read_only() {
+        Some(
+            vdir::DirectoryLock::acquire(&target.directory)
+                .map_err(|err| SetupError::lock(name.into(), err))?,
+        )
+    } else {
+        None
+    };
+    let write_mode = if options.diff {
+        WriteMode::Diff
+    } else if options.dry_run {
+        WriteMode::DryRun
+    } else if target.atomic_writes {
+        WriteMode::Atomic
+    } else {
+        WriteMode::InPlace
+    };
+
+    let start = Instant::now();
+    let mut todo_files = if create_dirs && options.is_read_only() && !target.directory.exists() {
+        // The sync would create the directory.
+        Vec::new()
+    } else {
+        read_directory(&target.directory, name, target, options)?
+    };
+    if !options.is_read_only() {
+        vdir::write_metadata(&target.directory, &target.metadata)
+            .map_err(|err| SetupError::metadata(name.into(), err))?;
+    }
+    for (collection_name, collection) in &target.collections {
+        let collection_dir = target.directory.join(collection_name);
+        if options.is_read_only() {
+            if !collection_dir.is_dir() {
+                continue;
+            }
+        } else {
+            vdir::create_collection(&collection_dir, &collection.metadata)
+                .map_err(|err| SetupError::metadata(name.into(), err))?;
+        }
+        todo_files.extend(read_directory(&collection_dir, name, target, options)?);
+    }
+    let mut todo_files = remove_duplicates(todo_files, name, !options.is_read_only(), &mut errors);
+    // Items which move on the service keep their identifier, so track the URL they were last
+    // seen at.
+    let source_id_map = todo_files
+        .iter()
+        .filter_map(|todo_file| {
+            todo_file
+                .item
+                .source_id()
+                .map(|source_id| (source_id.to_string(), todo_file.item.url().to_string()))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut url_map = todo_files
+        .iter_mut()
+        .map(|todo_file| (todo_file.item.url().into(), &mut todo_file.item))
+        .collect::<ItemLookup>();
+
+    let now = Utc::now();
+    let mut all_new_items = Vec::new();
+    let mut fetched_urls = BTreeSet::new();
+    let mut completed_urls = BTreeSet::new();
+    let mut subtasks = Vec::new();
+    for mut result in all_results {
+        fetched_urls.insert(result.url.clone());
+        if target.subtasks {
+            let tasks = subtask::task_list(&result.url, &result.description);
+            fetched_urls.extend(tasks.iter().map(|task| task.url.clone()));
+            subtasks.push((result.url.clone(), result.status.is_done(), tasks));
+        }
+        result.description = format_description(target, &result);
+        let suppress_due = target.suppress_blocked_due && result.dependencies.blocked;
+        if suppress_due {
+            result.due = None;
+        }
+        if let Some(time) = target.due_time {
+            result.due = result.due.map(|due| due.at_time(time.0));
+        }
+        result.labels = target
+            .label_categories
+            .as_ref()
+            .map(|rules| {
+                result
+                    .labels
+                    .iter()
+                    .filter_map(|label| rules.category(label))
+                    .unique()
+                    .collect()
+            })
+            .unwrap_or_default();
+        if target.milestone_categories {
+            if let Some(milestone) = result.milestone.as_ref() {
+                if !result.labels.contains(milestone) {
+                    result.labels.push(milestone.clone());
+                }
+            }
+        }
+        result.labels.extend(
+            result
+                .assignees
+                .iter()
+                .map(|assignee| format!("@{}", assignee)),
+        );
+        if result.delegated {
+            if let Some(status) = target.delegated_status {
+                result.status = status;
+            }
+        }
+
+        if !url_map.contains_key(&result.url) {
+            let old_url = result
+                .source_id
+                .as_ref()
+                .and_then(|source_id| source_id_map.get(source_id));
+            if let Some(item) = old_url.and_then(|old_url| url_map.remove(old_url)) {
+                info!(
+                    target: "devtodo",
+                    "{}: item moved from {} to {}",
+                    name,
+                    item.url(),
+                    result.url,
+                );
+                url_map.insert(result.url.clone(), item);
+            }
+        }
+
+        if let Some(item) = url_map.get_mut(&result.url) {
+            let completed_locally = item.status() == TodoStatus::Completed
+                && !matches!(result.status, TodoStatus::Completed | TodoStatus::Cancelled);
+            record
+                .completions
+                .insert(result.url.clone(), completed_locally);
+            let snapshot = changelog::Snapshot::new(item);
+            result.update(item);
+            if suppress_due {
+                item.clear_due();
+            }
+            if snapshot.is_completed_by(item) {
+                completed_urls.insert(item.url().to_string());
+                record
+                    .change_set
+                    .completed
+                    .push(ChangedItem::new(name, item));
+            }
+            record
+                .changes
+                .extend(changelog::Entry::changed(name, &snapshot, item, now));
+        } else {
+            // Items which have already expired would just be pruned again later; skip them to
+            // avoid recreating them on every sync.
+            let is_expired = target
+                .retention
+                .as_ref()
+                .is_some_and(|retention| retention::is_expired_source(retention, &result, now));
+            if !is_expired {
+                all_new_items.push(result.into_item());
+            }
+        }
+    }
+
+    // Items which profiles have stopped returning.
+    let mut closed_missing = BTreeSet::new();
+    for (url, &status) in missing {
+        if fetched_urls.contains(url) {
+            continue;
+        }
+        let item = if let Some(item) = url_map.get_mut(url) {
+            item
+        } else {
+            continue;
+        };
+        closed_missing.insert(url.clone());
+        if item.status().is_done() {
+            continue;
+        }
+
+        info!(
+            target: "devtodo",
+            "{}: closing {} which is no longer returned",
+            name,
+            url,
+        );
+        let snapshot = changelog::Snapshot::new(item);
+        item.set_status(status);
+        if snapshot.is_completed_by(item) {
+            completed_urls.insert(url.clone());
+            record
+                .change_set
+                .completed
+                .push(ChangedItem::new(name, item));
+        }
+        record
+            .changes
+            .extend(changelog::Entry::changed(name, &snapshot, item, now));
+    }
+
+    all_new_items.extend(chore::materialize(&target.chores, &mut url_map));
+
+    // UIDs of new items are needed to relate subtasks to them.
+    if target.uid_scheme == UidScheme::Url {
+        for todo_item in &mut all_new_items {
+            todo_item.use_url_uid();
+        }
+    }
+    for (parent_url, parent_done, tasks) in subtasks {
+        let parent_uid = url_map
+            .get(&parent_url)
+            .map(|item| item.uid().to_string())
+            .or_else(|| {
+                all_new_items
+                    .iter()
+                    .find(|item| item.url() == parent_url)
+                    .map(|item| item.uid().to_string())
+            });
+        if let Some(parent_uid) = parent_uid {
+            all_new_items.extend(subtask::materialize(
+                &parent_url,
+                &parent_uid,
+                parent_done,
+                tasks,
+                &mut url_map,
+            ));
+        }
+    }
+
+    for todo_item in all_new_items
+        .iter_mut()
+        .chain(todo_files.iter_mut().map(|todo_file| &mut todo_file.item))
+    {
+        // Items not from services (and those written before the option was set) may still
+        // have due dates without a time.
+        if let Some(time) = target.due_time {
+            todo_item.set_due_time(time.0);
+        }
+        escalation::escalate(&target.escalation, todo_item, now);
+    }
+    for todo_file in &todo_files {
+        let item = &todo_file.item;
+        if !fetched_urls.contains(item.url()) {
+            let is_managed_locally = chore::is_chore(item.url()) || local::is_local(item.url());
+            if !is_managed_locally && !item.status().is_done() {
+                record.change_set.stale.push(ChangedItem::new(name, item));
+            }
+        } else if item.is_updated() && !completed_urls.contains(item.url()) {
+            record.change_set.updated.push(ChangedItem::new(name, item));
+        }
+    }
+    timing.merge = Some(start.elapsed());
+
+    let start = Instant::now();
+
+    let mut summary = DryRunSummary::default();
+    let mut write_item = |url: String, item| {
+        if let Err(err) = item {
+            error!(
+                "failed to write todo for {} in the {} target: {:?}",
+                url, name, err,
+            );
+            errors.push((
+                format!(
+                    "failed to write todo for {} in the {} target: {}",
+                    url, name, err,
+                ),
+                err,
+            ));
+        }
+    };
+
+    for todo_item in all_new_items {
+        let url = todo_item.url().into();
+        let directory = target.directory_for(todo_item.kind());
+        let created = changelog::Entry::created(name, &todo_item, now);
+        let changed = ChangedItem::new(name, &todo_item);
+        let res = TodoFile::from_item(directory, todo_item, write_mode).map(|_| ());
+        if res.is_ok() {
+            record.changes.extend(created);
+            record.change_set.new.push(changed);
+            summary.created += 1;
+        }
+        write_item(url, res);
+    }
+
+    for mut todo_file in todo_files {
+        let url = todo_file.item.url().into();
+        if let Some(retention) = target.retention.as_ref().filter(|_| !options.no_prune) {
+            if retention::is_expired(retention, &todo_file.item, now) {
+                if options.diff {
+                    println!("expire {}", todo_file.path().display());
+                    continue;
+                }
+                if options.dry_run {
+                    summary.expired += 1;
+                    continue;
+                }
+                write_item(
+                    url,
+                    retention::expire(retention, &target.directory, todo_file),
+                );
+                continue;
+            }
+        }
+
+        // Move items into the collection they belong to.
+        let directory = target.directory_for(todo_file.item.kind());
+        let moved = todo_file.directory() != directory;
+        if moved {
+            if options.diff {
+                println!(
+                    "move {} into {}",
+                    todo_file.path().display(),
+                    directory.display(),
+                );
+            } else if !options.dry_run {
+                if let Err(err) = todo_file.move_to(&directory) {
+                    write_item(url, Err(err));
+                    continue;
+                }
+            }
+        }
+
+        if moved || todo_file.item.is_updated() {
+            summary.updated += 1;
+        } else {
+            summary.unchanged += 1;
+        }
+        write_item(url, todo_file.write(write_mode));
+    }
+    timing.write = Some(start.elapsed());
+    timing.log(name);
+    record.timings.insert(name.into(), timing);
+
+    if options.dry_run {
+        summary.print(name);
+    }
+
+    if errors.is_empty() {
+        record.closed_missing.extend(closed_missing);
+        Ok(())
+    } else {
+        Err(SetupError::write_errors(errors))
+    }
+}
+
+/// Sync a target while keeping its directory in sync with a CalDAV collection.
+///
+/// Changes on the server are brought into the directory before `sync` is called and changes
+/// made to the directory are sent to the server afterwards.
+#[cfg(feature = "caldav")]
+fn sync_caldav<F>(
+    runtime: &Runtime,
+    name: &str,
+    target: &SyncTarget,
+    config: &config::Caldav,
+    known: &mut BTreeMap<String, CaldavResource>,
+    sync: F,
+) -> Result<(), SetupError>
+where
+    F: FnOnce() -> Result<(), SetupError>,
+{
+    let collection =
+        caldav::Collection::new(config).map_err(|err| SetupError::caldav(name.into(), err))?;
+    runtime
+        .block_on(collection.pull(&target.directory, known))
+        .map_err(|err| SetupError::caldav(name.into(), err))?;
+    sync()?;
+    runtime
+        .block_on(collection.push(&target.directory, known))
+        .map_err(|err| SetupError::caldav(name.into(), err))
+}
+
+#[cfg(not(feature = "caldav"))]
+fn sync_caldav<F>(
+    _: &Runtime,
+    name: &str,
+    _: &SyncTarget,
+    _: &config::Caldav,
+    _: &mut BTreeMap<String, CaldavResource>,
+    _: F,
+) -> Result<(), SetupError>
+where
+    F: FnOnce() -> Result<(), SetupError>,
+{
+    Err(SetupError::CaldavUnsupported {
+        name: name.into(),
+    })
+}
+
+/// The targets which keep their items in a directory (i.e., those which are not reports).
+fn directory_targets(
+    targets: &BTreeMap<String, SyncTarget>,
+) -> impl Iterator<Item = (&str, &SyncTarget)> {
+    targets
+        .iter()
+        .filter(|(_, target)| target.report.is_none())
+        .map(|(name, target)| (name.as_str(), target))
+}
+
+/// A target which keeps its items in a directory.
+fn directory_target<'a>(
+    targets: &'a BTreeMap<String, SyncTarget>,
+    name: &str,
+) -> Result<&'a SyncTarget, SetupError> {
+    targets
+        .get(name)
+        .filter(|target| target.report.is_none())
+        .ok_or_else(|| SetupError::no_such_target(name.into()))
+}
+
+/// Read the items of every target which keeps its items in a directory.
+fn read_all_items(
+    targets: &BTreeMap<String, SyncTarget>,
+) -> impl Iterator<Item = Result<(&str, &SyncTarget, Vec<TodoFile>), SetupError>> {
+    directory_targets(targets).map(|(name, target)| {
+        read_target_items(name, target).map(|todo_files| (name, target, todo_files))
+    })
+}
+
+/// Show the changes recorded by syncs.
+fn log_command(matches: &ArgMatches, state_dir: &Path) -> Result<(), SetupError> {
+    let since = matches
+        .get_one::<String>("SINCE")
+        .map(|since| {
+            todo::parse_human_duration(since)
+                .map(|duration| Utc::now() - duration)
+                .ok_or_else(|| SetupError::invalid_duration(since.clone()))
+        })
+        .transpose()?;
+    for entry in changelog::read(state_dir, since)? {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            entry.time.format(DATE_TIME_FMT),
+            entry.target,
+            entry.change,
+            entry.url,
+            entry.summary,
+        );
+    }
+
+    Ok(())
+}
+
+/// Write a review of recent changes and overdue items.
+fn report_command(
+    matches: &ArgMatches,
+    targets: &BTreeMap<String, SyncTarget>,
+    state_dir: &Path,
+) -> Result<(), SetupError> {
+    let now = Utc::now();
+    let since = matches
+        .get_one::<String>("SINCE")
+        .expect("since should have a value");
+    let since = review::parse_since(since, now)
+        .ok_or_else(|| SetupError::invalid_duration(since.clone()))?;
+    let format = match matches
+        .get_one::<String>("FORMAT")
+        .expect("format should have a value")
+        .as_ref()
+    {
+        "html" => review::ReviewFormat::Html,
+        _ => review::ReviewFormat::Markdown,
+    };
+
+    let mut review = review::Review::default();
+    review.add_changes(&changelog::read(state_dir, Some(since))?);
+    for res in read_all_items(targets) {
+        let (name, _, todo_files) = res?;
+        review.add_overdue(
+            name,
+            todo_files.iter().map(|todo_file| &todo_file.item),
+            now,
+        );
+    }
+
+    review
+        .write(&mut io::stdout().lock(), since, format)
+        .map_err(|err| SetupError::report("review".into(), err))
+}
+
+/// Open the URL of the item matching a pattern.
+fn open_command(
+    matches: &ArgMatches,
+    targets: &BTreeMap<String, SyncTarget>,
+) -> Result<(), SetupError> {
+    let pattern = matches
+        .get_many::<String>("PATTERN")
+        .expect("pattern should have a value")
+        .join(" ");
+
+    let mut found = Vec::new();
+    for res in read_all_items(targets) {
+        let (_, _, todo_files) = res?;
+        found.extend(
+            todo_files
+                .into_iter()
+                .map(|todo_file| todo_file.item)
+                .filter(|item| open::is_match(&pattern, item)),
+        );
+    }
+
+    match found.as_slice() {
+        [] => Err(SetupError::no_match(pattern)),
+        [item] => {
+            open::open_url(item.url()).map_err(|err| SetupError::open_url(item.url().into(), err))
+        },
+        items => {
+            for item in items {
+                println!("{}\t{}", item.url(), item.summary());
+            }
+            Err(SetupError::ambiguous_match(pattern, items.len()))
+        },
+    }
+}
+
+/// Add a local item to a target.
+fn add_command(
+    matches: &ArgMatches,
+    targets: &BTreeMap<String, SyncTarget>,
+    state_dir: &Path,
+) -> Result<(), SetupError> {
+    let name = matches
+        .get_one::<String>("TARGET")
+        .expect("target should have a value");
+    let target = directory_target(targets, name)?;
+    let summary = matches
+        .get_many::<String>("SUMMARY")
+        .expect("summary should have a value")
+        .join(" ");
+    let description = matches
+        .get_one::<String>("DESCRIPTION")
+        .cloned()
+        .unwrap_or_default();
+    let due = matches
+        .get_one::<String>("DUE")
+        .map(|due| {
+            local::parse_due(due, Utc::now()).ok_or_else(|| SetupError::invalid_due(due.clone()))
+        })
+        .transpose()?;
+
+    let item = local::new_item(summary, description, due);
+    add_item(name, target, state_dir, item)
+}
+
+/// Export a target into an archive or restore it from one.
+fn backup_command(
+    command: &str,
+    matches: &ArgMatches,
+    targets: &BTreeMap<String, SyncTarget>,
+    state_dir: &Path,
+) -> Result<(), SetupError> {
+    let name = matches
+        .get_one::<String>("TARGET")
+        .expect("target should have a value");
+    let target = directory_target(targets, name)?;
+    let path = Path::new(
+        matches
+            .get_one::<String>("FILE")
+            .expect("file should have a value"),
+    );
+
+    if command == "export" {
+        backup::export(&target.directory, state_dir, path)?;
+    } else {
+        backup::restore(
+            path,
+            &target.directory,
+            state_dir,
+            matches.get_flag("FORCE"),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Show items which are due soon.
+fn due_command(
+    matches: &ArgMatches,
+    targets: &BTreeMap<String, SyncTarget>,
+) -> Result<(), SetupError> {
+    let within = matches
+        .get_one::<String>("WITHIN")
+        .map(|within| {
+            todo::parse_human_duration(within)
+                .ok_or_else(|| SetupError::invalid_duration(within.clone()))
+        })
+        .transpose()?;
+    let filter = agenda::AgendaFilter {
+        within,
+        overdue_only: matches.get_flag("OVERDUE"),
+    };
+
+    let mut agenda = agenda::Agenda::new(filter, Utc::now());
+    for res in read_all_items(targets) {
+        let (name, _, todo_files) = res?;
+        agenda.add(name, todo_files.iter().map(|todo_file| &todo_file.item));
+    }
+
+    let mut out = io::stdout().lock();
+    let res = if matches.get_flag("JSON") {
+        agenda.write_json(&mut out)
+    } else {
+        agenda.write(&mut out)
+    };
+    res.map_err(|err| SetupError::report("agenda".into(), err))
+}
+
+/// Install systemd units to sync periodically.
+fn systemd_install_command(matches: &ArgMatches, config_path: &Path) -> Result<(), SetupError> {
+    let unit_dir = directories::BaseDirs::new()
+        .ok_or(SetupError::NoProjectDir)?
+        .config_dir()
+        .join("systemd/user");
+    let on_calendar = matches
+        .get_one::<String>("ON_CALENDAR")
+        .expect("on-calendar should have a value");
+    let paths = systemd::install(
+        &unit_dir,
+        config_path,
+        on_calendar,
+        matches.get_flag("FORCE"),
+    )?;
+    for path in paths {
+        println!("wrote {}", path.display());
+    }
+    println!("enable it with `systemctl --user daemon-reload && systemctl --user enable --now devtodo.timer`");
+
+    Ok(())
+}
+
+/// Serve the items of targets as calendar feeds.
+fn serve_command(
+    matches: &ArgMatches,
+    mut serve_config: ServeConfig,
+    targets: &BTreeMap<String, SyncTarget>,
+    state_dir: &Path,
+) -> Result<(), SetupError> {
+    if let Some(listen) = matches.get_one::<String>("LISTEN") {
+        serve_config.listen = listen.clone();
+    }
+
+    let feed = |name: &str| {
+        let target = directory_target(targets, name).ok()?;
+        Some(read_target_items(name, target).map(|todo_files| todo::calendar(&todo_files)))
+    };
+    // Syncs happen in other processes, so read the state for each check.
+    let last_synced = || {
+        let state = State::load(state_dir).unwrap_or_else(|err| {
+            warn!("failed to load state: {:?}", err);
+            State::default()
+        });
+        directory_targets(targets)
+            .map(|(name, _)| (name.into(), state.last_synced.get(name).copied()))
+            .collect()
+    };
+    serve::serve(&serve_config, feed, last_synced).map_err(SetupError::serve)
+}
+
+/// Search the items of all targets.
+fn search_command(
+    matches: &ArgMatches,
+    targets: &BTreeMap<String, SyncTarget>,
+) -> Result<(), SetupError> {
+    let query = matches
+        .get_many::<String>("QUERY")
+        .expect("query should have a value")
+        .join(" ");
+    let status = matches
+        .get_one::<String>("STATUS")
+        .map(|status| {
+            search::parse_status(status).ok_or_else(|| SetupError::invalid_status(status.clone()))
+        })
+        .transpose()?;
+    let kinds = matches
+        .get_one::<String>("KIND")
+        .map(|kind| search::parse_kinds(kind).ok_or_else(|| SetupError::invalid_kind(kind.clone())))
+        .transpose()?;
+    let search = search::Search::new(&query, status, kinds);
+
+    for res in read_all_items(targets) {
+        let (name, _, todo_files) = res?;
+        for todo_file in todo_files {
+            let item = &todo_file.item;
+            if search.is_match(item) {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    name,
+                    item.status().as_ref(),
+                    item.url(),
+                    item.summary(),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn try_main() -> Result<(), SetupError> {
+    let matches = Command::new("devtodo")
+        .version(clap::crate_version!())
+        .author("Ben Boeckel <mathstuf@gmail.com>")
+        .about("Query code hosting platforms for todo items to add to a calendar")
+        .arg(
+            Arg::new("CONFIG")
+                .short('c')
+                .long("config")
+                .help("Path to the configuration file")
+                .value_name("FILE")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("ALL_TARGETS")
+                .short('a')
+                .long("all-targets")
+                .help("Sync all targets")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("TARGET"),
+        )
+        .arg(
+            Arg::new("TARGET")
+                .short('t')
+                .long("target")
+                .help("Name of a target to sync")
+                .action(ArgAction::Append)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::new("STRICT")
+                .long("strict")
+                .help("Fail when existing files are malformed")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("REPAIR")
+                .long("repair")
+                .help("Move malformed existing files aside so that they are recreated")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("DRY_RUN")
+                .short('n')
+                .long("dry-run")
+                .help("Fetch items and report what would change without writing anything")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("CREATE_DIRS")
+                .long("create-dirs")
+                .help("Create target directories which do not exist")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("NO_PRUNE")
+                .long("no-prune")
+                .help("Keep finished items which have expired under a retention policy")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("DEBUG")
+                .short('d')
+                .long("debug")
+                .help("Increase verbosity")
+                .action(ArgAction::Count),
+        )
+        .arg(
+            Arg::new("DEBUG_HTTP")
+                .long("debug-http")
+                .help("Record HTTP requests and responses into a directory")
+                .value_name("DIR")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("LOGGER")
+                .short('l')
+                .long("logger")
+                .value_parser(PossibleValuesParser::new(["env"]))
+                .help("Logging backend")
+                .value_name("LOGGER")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("LOG_FILTER")
+                .long("log-filter")
+                .help("Log levels for specific modules (e.g., `github=trace,reqwest=warn`)")
+                .value_name("FILTER")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("CHANGES")
+                .long("changes")
+                .help("Write the changes made by syncing as JSON (`-` for standard output)")
+                .value_name("PATH")
+                .action(ArgAction::Set),
+        )
+        .subcommand(
+            Command::new("accounts")
+                .about("Manage configured accounts")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("test")
+                        .about("Test connectivity to accounts")
+                        .arg(
+                            Arg::new("NAME")
+                                .help("Name of the account to test (defaults to all accounts)")
+                                .action(ArgAction::Set),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("auth")
+                .about("Manage account credentials")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("set")
+                        .about("Prompt for the token of an account and store it in the keyring")
+                        .arg(
+                            Arg::new("ACCOUNT")
+                                .help("Name of the account")
+                                .required(true)
+                                .action(ArgAction::Set),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show when targets were last synced and how accounts fared"),
+        )
+        .subcommand(
+            Command::new("log").about("Show changes made to items").arg(
+                Arg::new("SINCE")
+                    .long("since")
+                    .help("Only show changes within this long ago (e.g., `1w`)")
+                    .value_name("DURATION")
+                    .action(ArgAction::Set),
+            ),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Summarize activity over a period of time")
+                .arg(
+                    Arg::new("SINCE")
+                        .long("since")
+                        .help("Start of the period (a day of the week, `today`, or a duration)")
+                        .value_name("WHEN")
+                        .default_value("monday")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("FORMAT")
+                        .long("format")
+                        .help("Format of the summary")
+                        .value_name("FORMAT")
+                        .default_value("markdown")
+                        .value_parser(PossibleValuesParser::new(["markdown", "html"]))
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("open")
+                .about("Open the URL of an item in the browser")
+                .arg(
+                    Arg::new("PATTERN")
+                        .help("Words to match against item summaries and URLs")
+                        .required(true)
+                        .action(ArgAction::Append),
+                ),
+        )
+        .subcommand(
+            Command::new("add")
+                .about("Add an item which is not tied to any service")
+                .arg(
+                    Arg::new("SUMMARY")
+                        .help("Summary of the item")
+                        .required(true)
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("TARGET")
+                        .short('t')
+                        .long("target")
+                        .help("Name of the target to add the item to")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("DUE")
+                        .long("due")
+                        .help("When the item is due (e.g., `2024-05-17`, `friday`, or `3d`)")
+                        .value_name("WHEN")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("DESCRIPTION")
+                        .long("description")
+                        .help("Description of the item")
+                        .value_name("TEXT")
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("complete")
+                .about("Mark an item as completed")
+                .arg(
+                    Arg::new("PATTERN")
+                        .help("An item's URL or words to match against item summaries and URLs")
+                        .required(true)
+                        .action(ArgAction::Append),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show the changes a sync would make without making them")
+                .arg(
+                    Arg::new("TARGET")
+                        .short('t')
+                        .long("target")
+                        .help("Name of a target to show changes for")
+                        .action(ArgAction::Append)
+                        .number_of_values(1),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Back up the files of a target and devtodo's state")
+                .arg(
+                    Arg::new("TARGET")
+                        .short('t')
+                        .long("target")
+                        .help("Name of the target to back up")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("FILE")
+                        .short('o')
+                        .long("output")
+                        .help("Path to write the backup to (a `.tar.zst` file)")
+                        .value_name("FILE")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Restore the files of a target and devtodo's state from a backup")
+                .arg(
+                    Arg::new("TARGET")
+                        .short('t')
+                        .long("target")
+                        .help("Name of the target to restore into")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("FILE")
+                        .help("Path to the backup")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("FORCE")
+                        .long("force")
+                        .help("Replace existing files")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import todo items written by other tools into a target")
+                .arg(
+                    Arg::new("PATH")
+                        .help("An `.ics` file or a directory of them")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("TARGET")
+                        .short('t')
+                        .long("target")
+                        .help("Name of the target to import into")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("due")
+                .about("List outstanding items by their due dates")
+                .arg(
+                    Arg::new("WITHIN")
+                        .long("within")
+                        .help("Only show items due within this long (e.g., `3d`)")
+                        .value_name("DURATION")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("OVERDUE")
+                        .long("overdue")
+                        .help("Only show overdue items")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("JSON")
+                        .long("json")
+                        .help("Output the items as JSON")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("systemd")
+                .about("Manage systemd integration")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("install")
+                        .about("Write a user service and timer which sync periodically")
+                        .arg(
+                            Arg::new("ON_CALENDAR")
+                                .long("on-calendar")
+                                .help("When to sync (a systemd calendar event, e.g., `hourly`)")
+                                .value_name("EVENT")
+                                .default_value("hourly")
+                                .action(ArgAction::Set),
+                        )
+                        .arg(
+                            Arg::new("FORCE")
+                                .long("force")
+                                .help("Replace existing units")
+                                .action(ArgAction::SetTrue),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Serve targets as read-only calendar feeds over HTTP")
+                .arg(
+                    Arg::new("LISTEN")
+                        .long("listen")
+                        .help("Address to listen on (e.g., `127.0.0.1:8080`)")
+                        .value_name("ADDR")
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search the summaries and descriptions of items")
+                .arg(
+                    Arg::new("QUERY")
+                        .help("Words which must appear in matching items")
+                        .required(true)
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("STATUS")
+                        .long("status")
+                        .help("Only show items with this status (e.g., `needs-action`)")
+                        .value_name("STATUS")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("KIND")
+                        .long("kind")
+                        .help("Only show items of this kind (e.g., `issue`, `pr`, or `mention`)")
+                        .value_name("KIND")
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Manage the configuration file")
+                .subcommand_required(true)
+                .subcommand(Command::new("migrate").about(
+                    "Rewrite the configuration file using the current layout (comments are \
+                         not preserved)",
+                )),
+        )
+        .subcommand(
+            Command::new("bug-report")
+                .about("Gather information for an issue report into an archive")
+                .arg(
+                    Arg::new("FILE")
+                        .short('o')
+                        .long("output")
+                        .help("Path to write the report to (a `.tar.zst` file)")
+                        .value_name("FILE")
+                        .default_value("devtodo-bug-report.tar.zst")
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("cache")
+                .about("Manage the response cache")
+                .subcommand_required(true)
+                .subcommand(Command::new("clear").about("Remove all cached responses")),
+        )
+        .get_matches();
+
+    let basedirs = ProjectDirs::from("net.benboeckel.devtodo", "", "devtodo")
+        .ok_or(SetupError::NoProjectDir)?;
+    let state_dir = basedirs
+        .state_dir()
+        .unwrap_or_else(|| basedirs.data_local_dir())
+        .to_path_buf();
+    let config_path = if let Some(config) = matches.get_one::<String>("CONFIG") {
+        Path::new(config).into()
+    } else {
+        basedirs.config_dir().join("devtodo.yaml")
+    };
+    let mut origin = ConfigOrigin::File(config_path.clone());
+    let contents = match fs::read_to_string(&config_path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            // Without a configuration file, a minimal configuration may be given in the
+            // environment instead.
+            let contents = config::from_env();
+            if contents.is_some() {
+                origin = ConfigOrigin::Environment;
+            }
+            contents.ok_or_else(|| SetupError::read_config(config_path.clone(), err))
+        },
+        res => res.map_err(|err| SetupError::read_config(config_path.clone(), err)),
+    };
+
+    // The configuration is loaded before the logger so that it may provide logging defaults.
+    let loaded = contents
+        .as_ref()
+        .ok()
+        .map(|contents| load_config(&origin, contents));
+    let logging = if let Some(Ok((config, _))) = loaded.as_ref() {
+        Some(&config.logging)
+    } else {
+        None
+    };
+    let bug_report = matches.subcommand_matches("bug-report");
+    // Only syncs are logged so that other commands (e.g., gathering a bug report) do not
+    // replace the log of the last sync.
+    let (run_log, run_log_err) = if matches.subcommand().is_some() {
+        (None, None)
+    } else {
+        match open_run_log(&state_dir) {
+            Ok(run_log) => (Some(run_log), None),
+            Err(err) => (None, Some(err)),
+        }
+    };
+    let _logger = init_logger(&matches, logging, run_log)?;
+    if let Some(err) = run_log_err {
+        warn!("failed to open the run log: {:?}", err);
+    }
+
+    if let Some(matches) = bug_report {
+        let output = matches
+            .get_one::<String>("FILE")
+            .expect("the output should have a default");
+        // The configuration may be what is broken, so use it as-is.
+        let contents = contents.as_ref().ok().map(String::as_str);
+        bugreport::create(contents, &state_dir, Path::new(output))?;
+        return Ok(());
+    }
+
+    let contents = contents?;
+    if let Some(("config", matches)) = matches.subcommand() {
+        if let Some(("migrate", _)) = matches.subcommand() {
+            // Migrating would write the configuration from the environment (including its
+            // token) into a new file.
+            return match origin {
+                ConfigOrigin::File(path) => migrate_config(&path, &contents),
+                ConfigOrigin::Environment => Err(SetupError::no_config_file(config_path)),
+            };
+        }
+    }
+
+    let (config, outdated) = loaded.expect("configuration contents should have been read")?;
+    if outdated {
+        warn!(
+            "the {} uses an older configuration layout; use `config migrate` to update it",
+            origin,
+        );
+    }
+
+    let targets = &config.targets;
+    match matches.subcommand() {
+        Some(("status", _)) => {
+            show_status(&config, &State::load(&state_dir)?);
+            return Ok(());
+        },
+        Some(("log", matches)) => return log_command(matches, &state_dir),
+        Some(("report", matches)) => return report_command(matches, targets, &state_dir),
+        Some(("open", matches)) => return open_command(matches, targets),
+        Some(("add", matches)) => return add_command(matches, targets, &state_dir),
+        Some(("complete", matches)) => {
+            let pattern = matches
+                .get_many::<String>("PATTERN")
+                .expect("pattern should have a value")
+                .join(" ");
+            return complete_items(targets, &state_dir, &pattern);
+        },
+        Some((command @ ("export" | "restore"), matches)) => {
+            return backup_command(command, matches, targets, &state_dir);
+        },
+        Some(("import", matches)) => {
+            let name = matches
+                .get_one::<String>("TARGET")
+                .expect("target should have a value");
+            let path = matches
+                .get_one::<String>("PATH")
+                .expect("path should have a value");
+            return import_items(name, directory_target(targets, name)?, Path::new(path));
+        },
+        Some(("due", matches)) => return due_command(matches, targets),
+        Some(("systemd", matches)) => {
+            if let Some(("install", matches)) = matches.subcommand() {
+                systemd_install_command(matches, &config_path)?;
+            }
+            return Ok(());
+        },
+        Some(("serve", matches)) => {
+            return serve_command(matches, config.serve, targets, &state_dir);
+        },
+        Some(("search", matches)) => return search_command(matches, targets),
+        Some(("auth", matches)) => {
+            if let Some(("set", matches)) = matches.subcommand() {
+                let name = matches
+                    .get_one::<String>("ACCOUNT")
+                    .expect("account should have a value");
+                return set_auth(&config, name);
+            }
+        },
+        _ => (),
+    }
+
+    let mut state = State::load(&state_dir)?;
+    // Profiles of targets not synced in this run keep their deferrals.
+    let mut deferred_profiles = state.deferred_profiles.clone();
+    let mut first_synced = state.first_synced.clone();
+
+    let cache_dir = basedirs.cache_dir();
+    if let Some(("cache", matches)) = matches.subcommand() {
+        if let Some(("clear", _)) = matches.subcommand() {
+            return cache::clear(cache_dir)
+                .map_err(|err| SetupError::clear_cache(cache_dir.into(), err));
+        }
+    }
+
+    let capture = matches
+        .get_one::<String>("DEBUG_HTTP")
+        .map(|dir| {
+            let path = PathBuf::from(dir);
+            HttpCapture::new(path.clone())
+                .map(Arc::new)
+                .map_err(|err| SetupError::http_capture(path, err))
+        })
+        .transpose()?;
+    // Connectivity tests should always talk to the service.
+    let use_cache = !matches!(matches.subcommand(), Some(("accounts", _)));
+    let cache = config
+        .cache
+        .max_age
+        .filter(|_| use_cache)
+        .and_then(|max_age| max_age.0.to_std().ok())
+        .map(|max_age| Arc::new(Cache::new(cache_dir.into(), max_age)));
+    let context = ConnectContext {
+        capture,
+        cache,
+    };
+
+    let sources = config
+        .accounts
+        .into_iter()
+        .flat_map(|(name, mut account)| {
+            // Each user is connected with the account's settings and its own secrets.
+            let users = mem::take(&mut account.users)
+                .into_iter()
+                .map(|(user, secret)| {
+                    let identity = Account {
+                        secret,
+                        ..account.clone()
+                    };
+                    (Accounts::identity_name(&name, &user), identity)
+                })
+                .collect::<Vec<_>>();
+            iter::once((name, account)).chain(users)
+        })
+        .map(|(name, account)| {
+            let item_source = account::connect(account, &context)
+                .map_err(|err| SetupError::account(name.clone(), err))?;
+            Ok((name, item_source))
+        })
+        .collect::<Result<BTreeMap<_, _>, SetupError>>()?;
+    let accounts = Accounts {
+        sources,
+        aliases: config.account_aliases,
+    };
+    // Requests to services are made asynchronously, but everything else is synchronous, so
+    // drive them from a single thread.
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(SetupError::runtime)?;
+
+    if let Some(("accounts", matches)) = matches.subcommand() {
+        if let Some(("test", matches)) = matches.subcommand() {
+            return runtime.block_on(test_accounts(&accounts, matches.get_one::<String>("NAME")));
+        }
+    }
+
+    let targets = if matches.get_flag("ALL_TARGETS") {
+        config.targets.keys().cloned().collect()
+    } else {
+        matches
+            .subcommand_matches("diff")
+            .and_then(|matches| matches.get_many::<String>("TARGET"))
+            .or_else(|| matches.get_many::<String>("TARGET"))
+            .map(|values| values.map(Into::into).collect())
+            .unwrap_or(config.default_targets)
+    };
+
+    let targets_to_use = config
+        .targets
+        .into_iter()
+        .filter(|(name, _)| targets.iter().any(|target| target == name))
+        .collect::<BTreeMap<_, _>>();
+    let json_report = targets_to_use
+        .values()
+        .any(|target| target.report == Some(ReportFormat::Json));
+
+    let diff_matches = matches.subcommand_matches("diff");
+    let dry_run = matches.get_flag("DRY_RUN");
+    let options = SyncOptions {
+        strict: matches.get_flag("STRICT"),
+        // Files are left alone when only showing changes.
+        repair: matches.get_flag("REPAIR") && diff_matches.is_none() && !dry_run,
+        diff: diff_matches.is_some(),
+        dry_run,
+        create_dirs: matches.get_flag("CREATE_DIRS"),
+        no_prune: matches.get_flag("NO_PRUNE"),
+    };
+
+    let mut errors = Vec::new();
+    let mut record = SyncRecord::default();
+    let mut write_backs = WriteBacks::default();
+    let mut fetched = BTreeMap::new();
+    let mut fetch_timings = BTreeMap::new();
+    let mut returns = BTreeMap::new();
+    let mut plans = Vec::new();
+    for (name, target) in &targets_to_use {
+        // Failures for one target should not keep other targets from syncing.
+        match plan_target(name, target, &accounts, &state) {
+            Ok(jobs) => plans.push((name.clone(), jobs)),
+            Err(err) => {
+                error!("failed to fetch items for the {} target: {:?}", name, err);
+                errors.push((name.clone(), err));
+            },
+        }
+    }
+    // Profiles of all targets are fetched together so that accounts are used concurrently
+    // even when each target only uses one of them.
+    let all_jobs = plans.iter().flat_map(|(_, jobs)| jobs).collect::<Vec<_>>();
+    let all_fetched = runtime.block_on(fetch_profiles(&all_jobs));
+    record_account_statuses(&all_jobs, &all_fetched, &mut state.accounts);
+    let mut all_fetched = all_fetched.into_iter();
+    for (name, jobs) in &plans {
+        let target_fetched = all_fetched.by_ref().take(jobs.len()).collect::<Vec<_>>();
+        let res = collect_target(
+            jobs,
+            target_fetched,
+            &mut deferred_profiles,
+            &mut first_synced,
+            &mut write_backs,
+        );
+        match res {
+            Ok(collected) => {
+                fetched.insert(name.clone(), collected.results);
+                fetch_timings.insert(name.clone(), collected.timing);
+                returns.insert(name.clone(), collected.returns);
+            },
+            Err(err) => {
+                error!("failed to fetch items for the {} target: {:?}", name, err);
+                errors.push((name.clone(), err));
+            },
+        }
+    }
+
+    route::route(&config.routes, &mut fetched);
+    // Missing items may have been routed to any target.
+    let now = Utc::now();
+    let mut missing = MissingItems::new(state.returned.clone());
+    for target_returns in returns.values() {
+        for profile_returns in target_returns {
+            missing.expire(profile_returns, now);
+        }
+    }
+    let to_close = missing.take_to_close();
+
+    for (name, target) in targets_to_use {
+        let (results, timing) = if let (Some(results), Some(timing)) =
+            (fetched.remove(&name), fetch_timings.remove(&name))
+        {
+            (results, timing)
+        } else {
+            continue;
+        };
+        // The server is left alone when the directory is.
+        let caldav = target.caldav.as_ref().filter(|_| !options.is_read_only());
+        let res = if let Some(caldav) = caldav {
+            let known = state.caldav.entry(name.clone()).or_default();
+            sync_caldav(&runtime, &name, &target, caldav, known, || {
+                sync_target(
+                    &name,
+                    &target,
+                    results,
+                    timing,
+                    &to_close,
+                    &mut record,
+                    &options,
+                )
+            })
+        } else {
+            sync_target(
+                &name,
+                &target,
+                results,
+                timing,
+                &to_close,
+                &mut record,
+                &options,
+            )
+        };
+        match res {
+            Ok(()) => {
+                // Items returned for the target's profiles are only noted once the target has
+                // been synced so that items are not forgotten before they can be closed.
+                for profile_returns in returns.remove(&name).unwrap_or_default() {
+                    missing.observe(profile_returns, now);
+                }
+                if !options.is_read_only() {
+                    state.last_synced.insert(name, Utc::now());
+                }
+            },
+            Err(err) => {
+                error!("failed to sync the {} target: {:?}", name, err);
+                errors.push((name, err));
+            },
+        }
+    }
+
+    if !options.is_read_only() {
+        let written_back = mem::take(&mut state.written_back);
+        state.written_back = runtime.block_on(write_backs.apply(
+            &accounts.sources,
+            &record.completions,
+            written_back,
+        ));
+        state.deferred_profiles = deferred_profiles;
+        state.first_synced = first_synced;
+        for url in &record.closed_missing {
+            missing.closed(url);
+        }
+        state.returned = missing.into_returned();
+        state.save(&state_dir)?;
+        changelog::append(&state_dir, &record.changes)?;
+        if let Err(err) = timing::save(&state_dir, &record.timings) {
+            warn!("failed to save timing information: {:?}", err);
+        }
+
+        if !record.change_set.is_empty() {
+            for hook in &config.hooks {
+                if let Err(err) = changeset::run_hook(hook, &record.change_set) {
+                    error!("{:?}", err);
+                }
+            }
+        }
+        for notification in &config.notifications {
+            let message = if let Some(message) =
+                notify::Message::new(&record.change_set, &notification.changes)
+            {
+                message
+            } else {
+                continue;
+            };
+            let res = notify::connect(notification)
+                .and_then(|notifier| runtime.block_on(notifier.notify(&message)));
+            if let Err(err) = res {
+                error!("failed to notify {}: {:?}", notification.url, err);
+            }
+        }
+    }
+
+    // JSON reports end with the changes made to the other targets.
+    if json_report {
+        report::write_changes(&mut io::stdout().lock(), &record.change_set)
+            .map_err(SetupError::report_changes)?;
+    }
+
+    if let Some(path) = matches.get_one::<String>("CHANGES") {
+        let res = if path == "-" {
+            record.change_set.write(&mut io::stdout().lock())
+        } else {
+            File::create(path).and_then(|mut file| record.change_set.write(&mut file))
+        };
+        res.map_err(|err| SetupError::write_changes(path.into(), err))?;
+    }
+
+    if let Some(max_size) = config.cache.max_size {
+        if let Err(err) = cache::prune(cache_dir, max_size) {
+            warn!("failed to prune the cache directory: {:?}", err);
+        }
+    }
+
+    if errors.is_empty() {
         Ok(())
     } else {
-        Err(SetupError::write_errors(errors))
+        Err(SetupError::target_errors(errors))
     }
 }
 
 fn main() {
     setup_panic!();
 
+    // Panics are reported by `human_panic`; other failures point to `bug-report` instead.
     if let Err(err) = try_main() {
-        error!("{:?}", err);
-        panic!("{:?}", err);
+        let err = format!("{:?}", err);
+        error!("{}", redact::redact(&err));
+        eprintln!("Run `devtodo bug-report` to gather information for an issue report.");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::config::SyncTarget;
+    use crate::timing::TargetTiming;
+    use crate::{sync_target, SyncOptions, SyncRecord};
+
+    /// A file for an item as written by devtodo.
+    fn todo_file(uid: &str, created: &str) -> String {
+        [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            concat!(
+                "PRODID:-//IDN benboeckel.net//",
+                env!("CARGO_PKG_NAME"),
+                "/0.1.0 vobject//EN",
+            ),
+            "BEGIN:VTODO",
+            &format!("UID:{}", uid),
+            &format!("CREATED:{}", created),
+            &format!("DTSTAMP:{}", created),
+            "STATUS:NEEDS-ACTION",
+            "URL:https://github.com/owner/repo/issues/1",
+            "SUMMARY:An issue",
+            "DESCRIPTION:",
+            "CATEGORIES:issue",
+            "END:VTODO",
+            "END:VCALENDAR",
+            "",
+        ]
+        .join("\r\n")
+    }
+
+    /// The contents of the files in a directory.
+    fn directory_contents(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let contents = fs::read(&path).unwrap();
+                (path, contents)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diff_leaves_directory_untouched() {
+        let dir = env::temp_dir().join(format!("devtodo-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        // The second file duplicates the first.
+        fs::write(
+            dir.join("first.ics"),
+            todo_file("first", "20260101T000000Z"),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("second.ics"),
+            todo_file("second", "20260102T000000Z"),
+        )
+        .unwrap();
+        let before = directory_contents(&dir);
+
+        let target: SyncTarget =
+            serde_yaml::from_str(&format!("{{directory: {}, profiles: {{}}}}", dir.display()))
+                .unwrap();
+        let options = SyncOptions {
+            strict: false,
+            repair: false,
+            diff: true,
+            dry_run: false,
+            create_dirs: false,
+            no_prune: false,
+        };
+        let res = sync_target(
+            "test",
+            &target,
+            Vec::new(),
+            TargetTiming::default(),
+            &BTreeMap::new(),
+            &mut SyncRecord::default(),
+            &options,
+        );
+        let after = directory_contents(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(res.is_ok());
+        assert_eq!(after, before);
     }
 }
@@ -0,0 +1,99 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+
+use chrono::{DateTime, Utc};
+
+use crate::config::CloseMissing;
+use crate::todo::TodoStatus;
+
+/// The items returned by a profile (by URL) and when they stopped being returned.
+pub type Returned = BTreeMap<String, Option<DateTime<Utc>>>;
+
+/// The items returned by a profile which closes missing items.
+pub struct ProfileReturns {
+    /// The profile (keyed as `target/profile`).
+    pub key: String,
+    /// How the profile closes missing items.
+    pub config: CloseMissing,
+    /// The URLs of the returned items.
+    pub urls: BTreeSet<String>,
+}
+
+/// Tracks items which profiles stop returning.
+///
+/// Items are tracked until a target closes them so that items in targets which fail to sync
+/// (or are not synced at all) are closed by a later run.
+#[derive(Default)]
+pub struct MissingItems {
+    /// The items returned by each profile which closes missing items (keyed as `target/profile`).
+    returned: BTreeMap<String, Returned>,
+    /// The items to close and the status to give them (by URL).
+    close: BTreeMap<String, TodoStatus>,
+}
+
+impl MissingItems {
+    pub fn new(returned: BTreeMap<String, Returned>) -> Self {
+        Self {
+            returned,
+            close: BTreeMap::new(),
+        }
+    }
+
+    /// Find items which a profile has stopped returning for longer than its grace period.
+    pub fn expire(&mut self, returns: &ProfileReturns, now: DateTime<Utc>) {
+        let previous = if let Some(previous) = self.returned.get(&returns.key) {
+            previous
+        } else {
+            return;
+        };
+
+        let expired = previous.iter().filter(|(url, missing_since)| {
+            let missing_since = missing_since.unwrap_or(now);
+            !returns.urls.contains(*url) && now - missing_since >= returns.config.grace.0
+        });
+        for (url, _) in expired {
+            self.close.insert(url.clone(), returns.config.status);
+        }
+    }
+
+    /// Take the items to close and the status to give them (by URL).
+    pub fn take_to_close(&mut self) -> BTreeMap<String, TodoStatus> {
+        mem::take(&mut self.close)
+    }
+
+    /// Note the items returned by a profile once its target has been synced.
+    ///
+    /// Items which are no longer returned are tracked until they are closed.
+    pub fn observe(&mut self, returns: ProfileReturns, now: DateTime<Utc>) {
+        let previous = self.returned.remove(&returns.key).unwrap_or_default();
+        let mut returned = returns
+            .urls
+            .into_iter()
+            .map(|url| (url, None))
+            .collect::<Returned>();
+        for (url, missing_since) in previous {
+            returned
+                .entry(url)
+                .or_insert_with(|| Some(missing_since.unwrap_or(now)));
+        }
+        self.returned.insert(returns.key, returned);
+    }
+
+    /// Stop tracking an item which has been closed.
+    pub fn closed(&mut self, url: &str) {
+        for returned in self.returned.values_mut() {
+            returned.remove(url);
+        }
+    }
+
+    /// The items returned by each profile to remember for the next run.
+    pub fn into_returned(self) -> BTreeMap<String, Returned> {
+        self.returned
+    }
+}
@@ -0,0 +1,158 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use thiserror::Error;
+
+use crate::changeset::{ChangeSet, ChangedItem};
+use crate::config::{ChangeCategory, Notification};
+use crate::review;
+use crate::secret::SecretError;
+
+#[cfg(feature = "gotify")]
+mod gotify;
+#[cfg(feature = "matrix")]
+mod matrix;
+
+#[derive(Debug, Error)]
+pub enum NotifyError {
+    #[cfg(not(all(feature = "gotify", feature = "matrix")))]
+    #[error("unsupported service: {}", service)]
+    UnsupportedService { service: &'static str },
+    #[error("unknown service: {}", service)]
+    UnknownService { service: String },
+    #[error("failed to get the secret")]
+    Secret {
+        #[from]
+        source: SecretError,
+    },
+    #[cfg(any(feature = "gotify", feature = "matrix"))]
+    #[error("missing setting for {}: {}", service, setting)]
+    MissingSetting {
+        service: &'static str,
+        setting: &'static str,
+    },
+    #[cfg(any(feature = "gotify", feature = "matrix"))]
+    #[error("failed to notify {}: {}", service, message)]
+    Send {
+        service: &'static str,
+        message: String,
+    },
+}
+
+impl NotifyError {
+    #[cfg(any(feature = "gotify", feature = "matrix"))]
+    fn missing_setting(service: &'static str, setting: &'static str) -> Self {
+        NotifyError::MissingSetting {
+            service,
+            setting,
+        }
+    }
+
+    #[cfg(any(feature = "gotify", feature = "matrix"))]
+    fn send(service: &'static str, message: String) -> Self {
+        NotifyError::Send {
+            service,
+            message,
+        }
+    }
+}
+
+/// The result of sending a notification.
+pub type NotifyFuture<'a> = Pin<Box<dyn Future<Output = Result<(), NotifyError>> + Send + 'a>>;
+
+/// A service which is told about changes made by syncing.
+pub trait Notifier {
+    fn notify<'a>(&'a self, message: &'a Message) -> NotifyFuture<'a>;
+}
+
+/// A message describing changes.
+#[cfg_attr(not(any(feature = "gotify", feature = "matrix")), allow(dead_code))]
+pub struct Message {
+    /// The message as plain text.
+    pub text: String,
+    /// The message as HTML.
+    #[cfg_attr(not(feature = "matrix"), allow(dead_code))]
+    pub html: String,
+}
+
+impl Message {
+    /// Describe the changes in the given categories.
+    ///
+    /// Returns `None` if there are no such changes.
+    pub fn new(changes: &ChangeSet, categories: &[ChangeCategory]) -> Option<Self> {
+        let categories = if categories.is_empty() {
+            ChangeCategory::DEFAULT
+        } else {
+            categories
+        };
+
+        let mut text = String::new();
+        let mut html = String::new();
+        for category in categories {
+            let (title, items): (_, &[ChangedItem]) = match category {
+                ChangeCategory::New => ("New", &changes.new),
+                ChangeCategory::Updated => ("Updated", &changes.updated),
+                ChangeCategory::Completed => ("Completed", &changes.completed),
+                ChangeCategory::Stale => ("Stale", &changes.stale),
+            };
+            if items.is_empty() {
+                continue;
+            }
+
+            text.push_str(&format!("{}:\n", title));
+            html.push_str(&format!("<p>{}:</p><ul>", title));
+            for item in items {
+                text.push_str(&format!("- {} <{}>\n", item.summary, item.url));
+                html.push_str(&format!(
+                    "<li><a href=\"{}\">{}</a></li>",
+                    review::html_escape(&item.url),
+                    review::html_escape(&item.summary),
+                ));
+            }
+            html.push_str("</ul>");
+        }
+
+        if text.is_empty() {
+            None
+        } else {
+            Some(Self {
+                text,
+                html,
+            })
+        }
+    }
+}
+
+/// Connect to a notification service.
+pub fn connect(notification: &Notification) -> Result<Box<dyn Notifier>, NotifyError> {
+    match notification.service.as_ref() {
+        #[cfg(feature = "gotify")]
+        "gotify" => Ok(Box::new(gotify::Gotify::new(notification)?)),
+        #[cfg(not(feature = "gotify"))]
+        "gotify" => {
+            Err(NotifyError::UnsupportedService {
+                service: "gotify",
+            })
+        },
+        #[cfg(feature = "matrix")]
+        "matrix" => Ok(Box::new(matrix::Matrix::new(notification)?)),
+        #[cfg(not(feature = "matrix"))]
+        "matrix" => {
+            Err(NotifyError::UnsupportedService {
+                service: "matrix",
+            })
+        },
+
+        service => {
+            Err(NotifyError::UnknownService {
+                service: service.into(),
+            })
+        },
+    }
+}
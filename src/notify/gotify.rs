@@ -0,0 +1,107 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use reqwest::Client;
+use serde::Serialize;
+use url::Url;
+
+use crate::config::Notification;
+use crate::notify::{Message, Notifier, NotifyError, NotifyFuture};
+use crate::secret;
+
+const SERVICE: &str = "gotify";
+
+// The title of messages.
+const TITLE: &str = "devtodo";
+
+/// A message for the Gotify API.
+#[derive(Serialize)]
+struct GotifyMessage<'a> {
+    title: &'static str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<i64>,
+}
+
+/// Pushes changes to a Gotify server.
+pub struct Gotify {
+    client: Client,
+    server: Url,
+    token: String,
+    priority: Option<i64>,
+}
+
+impl Gotify {
+    pub fn new(notification: &Notification) -> Result<Self, NotifyError> {
+        let server = Url::parse(&notification.url)
+            .map_err(|err| NotifyError::send(SERVICE, format!("invalid server: {}", err)))?;
+        let host = server.host_str().unwrap_or_default();
+        let token = secret::resolve(&notification.secret, host)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| NotifyError::missing_setting(SERVICE, "secret"))?;
+
+        let mut builder = Client::builder();
+        if let Some(timeout) = notification
+            .timeout
+            .and_then(|timeout| timeout.0.to_std().ok())
+        {
+            builder = builder.timeout(timeout);
+        }
+        let client = builder
+            .build()
+            .map_err(|err| NotifyError::send(SERVICE, err.to_string()))?;
+
+        Ok(Self {
+            client,
+            server,
+            token,
+            priority: notification.priority,
+        })
+    }
+
+    /// The endpoint to create messages.
+    fn message_url(&self) -> Result<Url, NotifyError> {
+        let mut url = self.server.clone();
+        url.path_segments_mut()
+            .map_err(|()| NotifyError::send(SERVICE, "invalid server URL".into()))?
+            .pop_if_empty()
+            .push("message");
+        Ok(url)
+    }
+
+    /// Send a message.
+    async fn send(&self, message: &Message) -> Result<(), NotifyError> {
+        let content = GotifyMessage {
+            title: TITLE,
+            message: &message.text,
+            priority: self.priority,
+        };
+
+        let rsp = self
+            .client
+            .post(self.message_url()?)
+            // Application tokens are given in a header to keep them out of request logs.
+            .header("X-Gotify-Key", &self.token)
+            .json(&content)
+            .send()
+            .await
+            .map_err(|err| NotifyError::send(SERVICE, err.to_string()))?;
+        let status = rsp.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            let body = rsp.text().await.unwrap_or_default();
+            Err(NotifyError::send(SERVICE, format!("{}: {}", status, body)))
+        }
+    }
+}
+
+impl Notifier for Gotify {
+    fn notify<'a>(&'a self, message: &'a Message) -> NotifyFuture<'a> {
+        Box::pin(self.send(message))
+    }
+}
@@ -0,0 +1,121 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use reqwest::Client;
+use serde::Serialize;
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::Notification;
+use crate::notify::{Message, Notifier, NotifyError, NotifyFuture};
+use crate::secret;
+
+const SERVICE: &str = "matrix";
+
+/// The content of a room message.
+#[derive(Serialize)]
+struct RoomMessage<'a> {
+    msgtype: &'static str,
+    body: &'a str,
+    format: &'static str,
+    formatted_body: &'a str,
+}
+
+/// Posts changes to a Matrix room.
+pub struct Matrix {
+    client: Client,
+    homeserver: Url,
+    room: String,
+    token: String,
+}
+
+impl Matrix {
+    pub fn new(notification: &Notification) -> Result<Self, NotifyError> {
+        let homeserver = Url::parse(&notification.url)
+            .map_err(|err| NotifyError::send(SERVICE, format!("invalid homeserver: {}", err)))?;
+        let room = notification
+            .room
+            .clone()
+            .ok_or_else(|| NotifyError::missing_setting(SERVICE, "room"))?;
+        let host = homeserver.host_str().unwrap_or_default();
+        let token = secret::resolve(&notification.secret, host)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| NotifyError::missing_setting(SERVICE, "secret"))?;
+
+        let mut builder = Client::builder();
+        if let Some(timeout) = notification
+            .timeout
+            .and_then(|timeout| timeout.0.to_std().ok())
+        {
+            builder = builder.timeout(timeout);
+        }
+        let client = builder
+            .build()
+            .map_err(|err| NotifyError::send(SERVICE, err.to_string()))?;
+
+        Ok(Self {
+            client,
+            homeserver,
+            room,
+            token,
+        })
+    }
+
+    /// The endpoint to send a message to the room.
+    fn send_url(&self) -> Result<Url, NotifyError> {
+        let mut url = self.homeserver.clone();
+        // Each message needs a unique transaction ID.
+        let txn_id = Uuid::new_v4().to_string();
+        url.path_segments_mut()
+            .map_err(|()| NotifyError::send(SERVICE, "invalid homeserver URL".into()))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &self.room,
+                "send",
+                "m.room.message",
+                &txn_id,
+            ]);
+        Ok(url)
+    }
+
+    /// Send a message.
+    async fn send(&self, message: &Message) -> Result<(), NotifyError> {
+        let content = RoomMessage {
+            // Notices are not meant to be replied to by bots.
+            msgtype: "m.notice",
+            body: &message.text,
+            format: "org.matrix.custom.html",
+            formatted_body: &message.html,
+        };
+
+        let rsp = self
+            .client
+            .put(self.send_url()?)
+            .bearer_auth(&self.token)
+            .json(&content)
+            .send()
+            .await
+            .map_err(|err| NotifyError::send(SERVICE, err.to_string()))?;
+        let status = rsp.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            let body = rsp.text().await.unwrap_or_default();
+            Err(NotifyError::send(SERVICE, format!("{}: {}", status, body)))
+        }
+    }
+}
+
+impl Notifier for Matrix {
+    fn notify<'a>(&'a self, message: &'a Message) -> NotifyFuture<'a> {
+        Box::pin(self.send(message))
+    }
+}
@@ -0,0 +1,54 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::env;
+use std::io;
+use std::process::Command;
+
+use crate::todo::TodoItem;
+
+/// Whether `needle` appears in `haystack` in order, but not necessarily contiguously.
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|c| haystack.any(|h| h == c))
+}
+
+/// Whether an item matches a pattern.
+///
+/// Each word of the pattern must either appear (in order) within the item's summary or as a
+/// substring of its URL. Matching ignores case.
+pub fn is_match(pattern: &str, item: &TodoItem) -> bool {
+    let summary = item.summary().to_lowercase();
+    let url = item.url().to_lowercase();
+    pattern
+        .to_lowercase()
+        .split_whitespace()
+        .all(|word| is_subsequence(word, &summary) || url.contains(word))
+}
+
+/// Open a URL in the user's browser.
+///
+/// The `BROWSER` environment variable is used if set, otherwise the platform's default handler.
+pub fn open_url(url: &str) -> io::Result<()> {
+    let mut command = if let Some(browser) = env::var_os("BROWSER") {
+        Command::new(browser)
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+
+    let status = command.arg(url).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("browser exited with {}", status)))
+    }
+}
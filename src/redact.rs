@@ -0,0 +1,46 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::sync::{PoisonError, RwLock};
+
+/// The text used in place of secrets.
+pub const REDACTED: &str = "<redacted>";
+
+/// The secrets to redact.
+///
+/// Kept sorted with the longest secrets first so that a secret containing another is
+/// redacted as a whole.
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Register a secret which should never appear in output.
+///
+/// Logs, error messages, and HTTP captures are passed through `redact` so that their output
+/// may be shared in bug reports.
+pub fn register(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+
+    let mut secrets = SECRETS.write().unwrap_or_else(PoisonError::into_inner);
+    if !secrets.iter().any(|known| known == secret) {
+        secrets.push(secret.into());
+        secrets.sort_by_key(|secret| Reverse(secret.len()));
+    }
+}
+
+/// Remove any registered secrets from text.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().unwrap_or_else(PoisonError::into_inner);
+    let mut text = Cow::Borrowed(text);
+    for secret in secrets.iter() {
+        if text.contains(secret.as_str()) {
+            text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+        }
+    }
+    text
+}
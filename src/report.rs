@@ -0,0 +1,118 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::account::SourceItem;
+use crate::changeset::ChangeSet;
+use crate::config::ReportFormat;
+use crate::timing::TargetTiming;
+
+#[derive(Serialize)]
+struct ReportItem<'a> {
+    url: &'a str,
+    summary: &'a str,
+    kind: &'a str,
+    status: &'a str,
+    due: Option<String>,
+}
+
+impl<'a> From<&'a SourceItem> for ReportItem<'a> {
+    fn from(item: &'a SourceItem) -> Self {
+        Self {
+            url: &item.url,
+            summary: &item.summary,
+            kind: item.kind.as_ref(),
+            status: item.status.as_ref(),
+            due: item.due.map(|due| format!("{}", due)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    target: &'a str,
+    items: Vec<ReportItem<'a>>,
+    timing: &'a TargetTiming,
+}
+
+#[derive(Serialize)]
+struct ChangesReport<'a> {
+    changes: &'a ChangeSet,
+}
+
+/// Write a report of the items fetched for a target.
+pub fn write<W>(
+    out: &mut W,
+    target: &str,
+    format: ReportFormat,
+    items: &[SourceItem],
+    timing: &TargetTiming,
+) -> io::Result<()>
+where
+    W: Write,
+{
+    match format {
+        ReportFormat::Text => {
+            for item in items {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    target,
+                    item.status.as_ref(),
+                    item.kind.as_ref(),
+                    item.due.map(|due| format!("{}", due)).unwrap_or_default(),
+                    item.summary,
+                    item.url,
+                )?;
+            }
+        },
+        ReportFormat::Json => {
+            let report = Report {
+                target,
+                items: items.iter().map(Into::into).collect(),
+                timing,
+            };
+            serde_json::to_writer(&mut *out, &report)?;
+            writeln!(out)?;
+        },
+        ReportFormat::Markdown => {
+            writeln!(out, "# {}", target)?;
+            writeln!(out)?;
+            for item in items {
+                write!(
+                    out,
+                    "- [{}] [{}]({}) ({}",
+                    if item.status.is_done() { "x" } else { " " },
+                    item.summary,
+                    item.url,
+                    item.kind.as_ref(),
+                )?;
+                if let Some(due) = item.due {
+                    write!(out, ", due {}", due)?;
+                }
+                writeln!(out, ")")?;
+            }
+            writeln!(out)?;
+        },
+    }
+
+    Ok(())
+}
+
+/// Write the changes made by a run as a JSON report record.
+pub fn write_changes<W>(out: &mut W, change_set: &ChangeSet) -> io::Result<()>
+where
+    W: Write,
+{
+    let report = ChangesReport {
+        changes: change_set,
+    };
+    serde_json::to_writer(&mut *out, &report)?;
+    writeln!(out)
+}
@@ -0,0 +1,53 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use log::info;
+
+use crate::account::SourceItem;
+use crate::config::{Retention, RetentionAction};
+use crate::todo::{TodoError, TodoFile, TodoItem};
+
+/// The subdirectory of a target into which expired items are archived.
+///
+/// Subdirectories are ignored when reading a target, so archived items are not synced.
+const ARCHIVE_DIR: &str = "archive";
+
+/// Whether an item has expired under a retention policy.
+pub fn is_expired(retention: &Retention, item: &TodoItem, now: DateTime<Utc>) -> bool {
+    item.done_at()
+        .is_some_and(|completed| now - completed > retention.keep.0)
+}
+
+/// Whether an item from a service had already expired when it was fetched.
+///
+/// Finished items were finished by the time they were last updated on the service.
+pub fn is_expired_source(retention: &Retention, item: &SourceItem, now: DateTime<Utc>) -> bool {
+    item.status.is_done()
+        && item
+            .updated_at
+            .is_some_and(|updated_at| now - updated_at > retention.keep.0)
+}
+
+/// Archive or remove an expired item.
+pub fn expire(
+    retention: &Retention,
+    directory: &Path,
+    mut todo_file: TodoFile,
+) -> Result<(), TodoError> {
+    match retention.action {
+        RetentionAction::Archive => {
+            info!("archiving finished item {}", todo_file.item.url());
+            todo_file.move_to(directory.join(ARCHIVE_DIR))
+        },
+        RetentionAction::Delete => {
+            info!("removing finished item {}", todo_file.item.url());
+            todo_file.remove()
+        },
+    }
+}
@@ -0,0 +1,211 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+
+use crate::changelog::{Change, Entry};
+use crate::todo::{self, TodoItem};
+
+/// The format of a review.
+#[derive(Debug, Clone, Copy)]
+pub enum ReviewFormat {
+    Markdown,
+    Html,
+}
+
+/// Parse the start of a review period.
+///
+/// Accepts a day of the week (meaning its most recent occurrence), `today`, or a duration.
+pub fn parse_since(since: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let start_of_day = |days_ago: i64| {
+        let date = now.date_naive() - Duration::days(days_ago);
+        Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN))
+    };
+
+    if since == "today" {
+        return Some(start_of_day(0));
+    }
+    if let Ok(weekday) = since.parse::<Weekday>() {
+        let days_ago =
+            (7 + now.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+        return Some(start_of_day(days_ago.into()));
+    }
+
+    todo::parse_human_duration(since).map(|duration| now - duration)
+}
+
+/// The repository an item URL belongs to.
+fn repository(url: &str) -> String {
+    let path = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .split('/')
+        .collect::<Vec<_>>();
+    match path.as_slice() {
+        [_, owner, repo, ..] => format!("{}/{}", owner, repo),
+        [host, ..] if !host.is_empty() => (*host).into(),
+        _ => "other".into(),
+    }
+}
+
+#[derive(Default)]
+struct Section {
+    /// Items grouped by repository.
+    repositories: BTreeMap<String, Vec<(String, String)>>,
+}
+
+impl Section {
+    fn add(&mut self, url: &str, summary: &str) {
+        self.repositories
+            .entry(repository(url))
+            .or_default()
+            .push((url.into(), summary.into()));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.repositories.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct TargetReview {
+    completed: Section,
+    new: Section,
+    overdue: Section,
+}
+
+/// A summary of activity over a period of time.
+#[derive(Default)]
+pub struct Review {
+    targets: BTreeMap<String, TargetReview>,
+}
+
+impl Review {
+    /// Add the changes recorded within the period.
+    pub fn add_changes(&mut self, entries: &[Entry]) {
+        for entry in entries {
+            let target = self.targets.entry(entry.target.clone()).or_default();
+            match entry.change {
+                Change::Created => target.new.add(&entry.url, &entry.summary),
+                Change::Completed => target.completed.add(&entry.url, &entry.summary),
+                Change::StatusChanged {
+                    ..
+                }
+                | Change::DueChanged {
+                    ..
+                }
+                | Change::Moved {
+                    ..
+                } => (),
+            }
+        }
+    }
+
+    /// Add the items of a target which are overdue.
+    pub fn add_overdue<'a, I>(&mut self, target: &str, items: I, now: DateTime<Utc>)
+    where
+        I: IntoIterator<Item = &'a TodoItem>,
+    {
+        let overdue = items.into_iter().filter(|item| {
+            !item.status().is_done() && item.due().is_some_and(|due| due.deadline() < now)
+        });
+        for item in overdue {
+            self.targets
+                .entry(target.into())
+                .or_default()
+                .overdue
+                .add(item.url(), item.summary());
+        }
+    }
+
+    /// Write the review.
+    pub fn write<W>(
+        &self,
+        out: &mut W,
+        since: DateTime<Utc>,
+        format: ReviewFormat,
+    ) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let title = format!("Review since {}", since.format("%Y-%m-%d"));
+        match format {
+            ReviewFormat::Markdown => writeln!(out, "# {}", title)?,
+            ReviewFormat::Html => {
+                writeln!(out, "<!DOCTYPE html>")?;
+                writeln!(
+                    out,
+                    "<html><head><meta charset=\"utf-8\"><title>{}</title></head><body>",
+                    html_escape(&title)
+                )?;
+                writeln!(out, "<h1>{}</h1>", html_escape(&title))?;
+            },
+        }
+
+        for (name, target) in &self.targets {
+            match format {
+                ReviewFormat::Markdown => writeln!(out, "\n## {}", name)?,
+                ReviewFormat::Html => writeln!(out, "<h2>{}</h2>", html_escape(name))?,
+            }
+
+            let sections = [
+                ("Completed", &target.completed),
+                ("New", &target.new),
+                ("Overdue", &target.overdue),
+            ];
+            for (heading, section) in sections {
+                if section.is_empty() {
+                    continue;
+                }
+
+                match format {
+                    ReviewFormat::Markdown => {
+                        writeln!(out, "\n### {}", heading)?;
+                        for (repo, items) in &section.repositories {
+                            writeln!(out, "\n- {}", repo)?;
+                            for (url, summary) in items {
+                                writeln!(out, "  - [{}]({})", summary, url)?;
+                            }
+                        }
+                    },
+                    ReviewFormat::Html => {
+                        writeln!(out, "<h3>{}</h3>", heading)?;
+                        writeln!(out, "<ul>")?;
+                        for (repo, items) in &section.repositories {
+                            writeln!(out, "<li>{}<ul>", html_escape(repo))?;
+                            for (url, summary) in items {
+                                writeln!(
+                                    out,
+                                    "<li><a href=\"{}\">{}</a></li>",
+                                    html_escape(url),
+                                    html_escape(summary),
+                                )?;
+                            }
+                            writeln!(out, "</ul></li>")?;
+                        }
+                        writeln!(out, "</ul>")?;
+                    },
+                }
+            }
+        }
+
+        if let ReviewFormat::Html = format {
+            writeln!(out, "</body></html>")?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
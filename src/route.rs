@@ -0,0 +1,122 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use log::{debug, warn};
+
+use crate::account::{self, SourceItem};
+use crate::config::Route;
+
+/// Whether a string matches a pattern where `*` matches any sequence of characters.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = if let Some(rest) = text.strip_prefix(first) {
+        rest
+    } else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+    if let Some((last, middle)) = parts.split_last() {
+        for part in middle {
+            if let Some(idx) = rest.find(part) {
+                rest = &rest[idx + part.len()..];
+            } else {
+                return false;
+            }
+        }
+        rest.ends_with(last)
+    } else {
+        // No `*` in the pattern.
+        rest.is_empty()
+    }
+}
+
+/// The repository an item belongs to from its URL (e.g., `owner/repo`).
+fn repository(url: &str) -> Option<String> {
+    account::short_reference(url).and_then(|reference| {
+        reference
+            .rfind(['#', '!'])
+            .map(|idx| reference[..idx].to_string())
+    })
+}
+
+impl Route {
+    /// Whether the route applies to an item.
+    ///
+    /// Patterns containing `://` are matched against the URL of the item and others against
+    /// its repository.
+    fn matches(&self, item: &SourceItem) -> bool {
+        if self.pattern.contains("://") {
+            glob_matches(&self.pattern, &item.url)
+        } else {
+            repository(&item.url).is_some_and(|repo| glob_matches(&self.pattern, &repo))
+        }
+    }
+}
+
+/// Move fetched items into the targets their routes send them to.
+///
+/// The first matching route is used. Items without a matching route stay in the target which
+/// fetched them, as do items routed to targets which are not being synced so that their files
+/// in the fetching target are still kept up to date.
+pub fn route(routes: &[Route], fetched: &mut BTreeMap<String, Vec<SourceItem>>) {
+    if routes.is_empty() {
+        return;
+    }
+
+    let synced = fetched.keys().cloned().collect::<BTreeSet<_>>();
+    let mut routed: BTreeMap<String, Vec<SourceItem>> = BTreeMap::new();
+    for (name, items) in fetched.iter_mut() {
+        let mut stay = Vec::new();
+        let mut unsynced: BTreeMap<&str, usize> = BTreeMap::new();
+        for item in items.drain(..) {
+            match routes.iter().find(|route| route.matches(&item)) {
+                Some(route) if route.target != *name => {
+                    if !synced.contains(&route.target) {
+                        *unsynced.entry(&route.target).or_default() += 1;
+                        stay.push(item);
+                        continue;
+                    }
+
+                    debug!(
+                        "routing {} from the {} target to the {} target",
+                        item.url, name, route.target,
+                    );
+                    routed.entry(route.target.clone()).or_default().push(item);
+                },
+                _ => stay.push(item),
+            }
+        }
+        *items = stay;
+
+        for (target, count) in unsynced {
+            warn!(
+                "{} items were routed to the {} target which is not being synced; keeping them \
+                 in the {} target",
+                count, target, name,
+            );
+        }
+    }
+
+    for (name, items) in routed {
+        let target_items = fetched
+            .get_mut(&name)
+            .expect("items should only be routed to targets being synced");
+        // The same item may have been fetched for multiple targets.
+        let mut urls = target_items
+            .iter()
+            .map(|item| item.url.clone())
+            .collect::<BTreeSet<_>>();
+        target_items.extend(
+            items
+                .into_iter()
+                .filter(|item| urls.insert(item.url.clone())),
+        );
+    }
+}
@@ -0,0 +1,68 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::todo::{TodoItem, TodoKind, TodoStatus};
+
+/// Parse a status given on the command line (e.g., `needs-action`).
+pub fn parse_status(status: &str) -> Option<TodoStatus> {
+    TodoStatus::parse(&status.to_uppercase())
+}
+
+/// Parse a kind given on the command line.
+///
+/// Besides the category names of kinds, `issue` and `pr` match all kinds of issues and pull
+/// requests respectively.
+pub fn parse_kinds(kind: &str) -> Option<Vec<TodoKind>> {
+    match kind {
+        "issue" => Some(vec![TodoKind::Issue, TodoKind::AssignedIssue]),
+        "pr" => {
+            Some(vec![
+                TodoKind::PullRequest,
+                TodoKind::AssignedPullRequest,
+                TodoKind::ReviewRequest,
+            ])
+        },
+        kind => TodoKind::from_category(kind).map(|kind| vec![kind]),
+    }
+}
+
+/// A full-text search over items.
+pub struct Search {
+    words: Vec<String>,
+    status: Option<TodoStatus>,
+    kinds: Option<Vec<TodoKind>>,
+}
+
+impl Search {
+    /// Search for items containing all of the words in a query.
+    pub fn new(query: &str, status: Option<TodoStatus>, kinds: Option<Vec<TodoKind>>) -> Self {
+        Self {
+            words: query.split_whitespace().map(str::to_lowercase).collect(),
+            status,
+            kinds,
+        }
+    }
+
+    /// Whether an item matches the search.
+    ///
+    /// Words may appear in either the summary or the description and matching ignores case.
+    pub fn is_match(&self, item: &TodoItem) -> bool {
+        if self.status.is_some_and(|status| status != item.status()) {
+            return false;
+        }
+        if let Some(kinds) = self.kinds.as_ref() {
+            if !kinds.contains(&item.kind()) {
+                return false;
+            }
+        }
+
+        let summary = item.summary().to_lowercase();
+        let description = item.description().to_lowercase();
+        self.words
+            .iter()
+            .all(|word| summary.contains(word) || description.contains(word))
+    }
+}
@@ -0,0 +1,289 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use itertools::Itertools;
+
+use log::debug;
+use thiserror::Error;
+
+use crate::config::{Secret, Secrets};
+use crate::redact;
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("failed to run credential helper {}", helper)]
+    RunHelper { helper: String, source: io::Error },
+    #[error("credential helper {} failed: {}", helper, status)]
+    HelperFailed { helper: String, status: ExitStatus },
+    #[error("credential helper {} did not provide a password for {}", helper, host)]
+    NoPassword { helper: String, host: String },
+    #[error("the {} environment variable is not set", name)]
+    MissingEnv { name: String },
+    #[error("no secret command given")]
+    NoCommand {},
+    #[error("failed to run secret command `{}`", command)]
+    RunCommand { command: String, source: io::Error },
+    #[error("secret command `{}` failed: {}", command, status)]
+    CommandFailed { command: String, status: ExitStatus },
+    #[error("failed to read the secret from {}", path.display())]
+    ReadFile { path: PathBuf, source: io::Error },
+    #[error("the secret from {} is empty", origin)]
+    Empty { origin: String },
+    #[cfg(feature = "keyring")]
+    #[error("keyring error for {} on {}", service, host)]
+    Keyring {
+        service: String,
+        host: String,
+        source: keyring::Error,
+    },
+    #[cfg(feature = "keyring")]
+    #[error("failed to read the secret")]
+    Prompt { source: io::Error },
+    #[cfg(not(feature = "keyring"))]
+    #[error("keyring support is not enabled (requires the `keyring` feature)")]
+    KeyringUnsupported {},
+}
+
+impl SecretError {
+    fn run_helper(helper: String, source: io::Error) -> Self {
+        SecretError::RunHelper {
+            helper,
+            source,
+        }
+    }
+
+    fn helper_failed(helper: String, status: ExitStatus) -> Self {
+        SecretError::HelperFailed {
+            helper,
+            status,
+        }
+    }
+
+    fn no_password(helper: String, host: String) -> Self {
+        SecretError::NoPassword {
+            helper,
+            host,
+        }
+    }
+
+    fn missing_env(name: String) -> Self {
+        SecretError::MissingEnv {
+            name,
+        }
+    }
+
+    fn run_command(command: String, source: io::Error) -> Self {
+        SecretError::RunCommand {
+            command,
+            source,
+        }
+    }
+
+    fn command_failed(command: String, status: ExitStatus) -> Self {
+        SecretError::CommandFailed {
+            command,
+            status,
+        }
+    }
+
+    fn read_file(path: PathBuf, source: io::Error) -> Self {
+        SecretError::ReadFile {
+            path,
+            source,
+        }
+    }
+
+    fn empty(origin: String) -> Self {
+        SecretError::Empty {
+            origin,
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    fn keyring(service: String, host: String, source: keyring::Error) -> Self {
+        SecretError::Keyring {
+            service,
+            host,
+            source,
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    fn prompt(source: io::Error) -> Self {
+        SecretError::Prompt {
+            source,
+        }
+    }
+}
+
+/// The secret from the contents of a file or the output of a command.
+///
+/// Surrounding whitespace (e.g., a trailing newline) is removed.
+fn from_contents<F>(contents: &str, origin: F) -> Result<String, SecretError>
+where
+    F: FnOnce() -> String,
+{
+    let value = contents.trim();
+    if value.is_empty() {
+        return Err(SecretError::empty(origin()));
+    }
+    Ok(value.into())
+}
+
+/// Read a secret from an environment variable.
+fn from_env(name: &str) -> Result<String, SecretError> {
+    env::var(name)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| SecretError::missing_env(name.into()))
+}
+
+/// Read a secret from the output of a command.
+fn from_command(command: &[String]) -> Result<String, SecretError> {
+    let (program, args) = command.split_first().ok_or(SecretError::NoCommand {})?;
+    let command_line = || format!("{}", command.iter().format(" "));
+    debug!("running secret command {}", program);
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|err| SecretError::run_command(command_line(), err))?;
+    if !output.status.success() {
+        return Err(SecretError::command_failed(command_line(), output.status));
+    }
+
+    from_contents(&String::from_utf8_lossy(&output.stdout), || {
+        format!("`{}`", command_line())
+    })
+}
+
+/// Read a secret from a file.
+fn from_file(path: &Path) -> Result<String, SecretError> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| SecretError::read_file(path.into(), err))?;
+    from_contents(&contents, || path.display().to_string())
+}
+
+/// Read a secret from the platform keyring.
+#[cfg(feature = "keyring")]
+fn from_keyring(service: &str, host: &str) -> Result<String, SecretError> {
+    debug!(
+        "reading the secret for {} from the {} keyring entry",
+        host, service
+    );
+    keyring::Entry::new(service, host)
+        .and_then(|entry| entry.get_password())
+        .map_err(|err| SecretError::keyring(service.into(), host.into(), err))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn from_keyring(_: &str, _: &str) -> Result<String, SecretError> {
+    Err(SecretError::KeyringUnsupported {})
+}
+
+/// Prompt for a secret and store it in the platform keyring.
+#[cfg(feature = "keyring")]
+pub fn store_keyring(service: &str, host: &str) -> Result<(), SecretError> {
+    let value =
+        rpassword::prompt_password(format!("Token for {}: ", host)).map_err(SecretError::prompt)?;
+    let value = from_contents(&value, || "the prompt".into())?;
+    keyring::Entry::new(service, host)
+        .and_then(|entry| entry.set_password(&value))
+        .map_err(|err| SecretError::keyring(service.into(), host.into(), err))
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn store_keyring(_: &str, _: &str) -> Result<(), SecretError> {
+    Err(SecretError::KeyringUnsupported {})
+}
+
+/// The command to run for a credential helper.
+///
+/// As with git, helpers starting with `!` are run by the shell.
+fn helper_command(helper: &str) -> Command {
+    if let Some(script) = helper.strip_prefix('!') {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!("{} \"$@\"", script))
+            .arg(script);
+        command
+    } else {
+        Command::new(helper)
+    }
+}
+
+/// Ask a git credential helper for the password for a host.
+fn from_helper(helper: &str, host: &str) -> Result<String, SecretError> {
+    debug!("asking credential helper {} for {}", helper, host);
+    let mut child = helper_command(helper)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| SecretError::run_helper(helper.into(), err))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        write!(stdin, "protocol=https\nhost={}\n\n", host)
+            .map_err(|err| SecretError::run_helper(helper.into(), err))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| SecretError::run_helper(helper.into(), err))?;
+    if !output.status.success() {
+        return Err(SecretError::helper_failed(helper.into(), output.status));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("password="))
+        .filter(|password| !password.is_empty())
+        .map(Into::into)
+        .ok_or_else(|| SecretError::no_password(helper.into(), host.into()))
+}
+
+/// Get the values of secrets.
+///
+/// Credential helpers and the keyring are asked for the password for `host`. The values are registered for
+/// redaction.
+pub fn resolve(secrets: &Secrets, host: &str) -> Result<Vec<String>, SecretError> {
+    let values = secrets
+        .0
+        .iter()
+        .map(|secret| {
+            match secret {
+                Secret::Plain(value)
+                | Secret::Inline {
+                    plain: value,
+                } => Ok(value.clone()),
+                Secret::Env {
+                    env,
+                } => from_env(env),
+                Secret::Command {
+                    command,
+                } => from_command(command),
+                Secret::File {
+                    file,
+                } => from_file(file),
+                Secret::Helper {
+                    helper,
+                } => from_helper(helper, host),
+                Secret::Keyring {
+                    keyring,
+                } => from_keyring(keyring, host),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    values.iter().for_each(|value| redact::register(value));
+
+    Ok(values)
+}
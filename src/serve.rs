@@ -0,0 +1,307 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+
+use crate::config::ServeConfig;
+use crate::systemd;
+
+// How long to wait for a client to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// The maximum size of the request line and headers.
+const MAX_HEADER_SIZE: u64 = 16 * 1024;
+// The maximum number of connections handled at once.
+const MAX_CONNECTIONS: usize = 64;
+// How long to wait for new connections before checking in with the watchdog.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+// The path prefix for calendar feeds.
+const CALENDAR_PREFIX: &str = "/calendars/";
+// The extension for calendar feeds.
+const CALENDAR_EXTENSION: &str = ".ics";
+// The path of the health check.
+const HEALTH_PATH: &str = "/healthz";
+
+/// When targets were last synced (`None` if they never have been).
+pub type LastSynced = BTreeMap<String, Option<DateTime<Utc>>>;
+
+#[derive(Serialize)]
+struct TargetHealth {
+    last_sync: Option<DateTime<Utc>>,
+    age_seconds: Option<i64>,
+    stale: bool,
+}
+
+#[derive(Serialize)]
+struct Health {
+    healthy: bool,
+    targets: BTreeMap<String, TargetHealth>,
+}
+
+impl Health {
+    fn new(config: &ServeConfig, last_synced: LastSynced) -> Self {
+        let now = Utc::now();
+        let targets = last_synced
+            .into_iter()
+            .map(|(name, last_sync)| {
+                let age = last_sync.map(|last_sync| now - last_sync);
+                let stale = config
+                    .max_sync_age
+                    .is_some_and(|max_age| age.is_none_or(|age| age > max_age.0));
+                let health = TargetHealth {
+                    last_sync,
+                    age_seconds: age.map(|age| age.num_seconds()),
+                    stale,
+                };
+                (name, health)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        Self {
+            healthy: targets.values().all(|target| !target.stale),
+            targets,
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    token: Option<String>,
+}
+
+impl Request {
+    fn read(stream: &TcpStream) -> io::Result<Option<Self>> {
+        // Clients may not send unbounded headers.
+        let mut reader = BufReader::new(stream.take(MAX_HEADER_SIZE));
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+            _ => return Ok(None),
+        };
+
+        let mut token = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 {
+                break;
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("authorization") {
+                    token = value.trim().strip_prefix("Bearer ").map(Into::into);
+                }
+            }
+        }
+
+        // Clients which cannot set headers (e.g., calendar subscriptions) may pass the token in
+        // the URL instead.
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let query_token = query
+            .split('&')
+            .find_map(|param| param.strip_prefix("token="))
+            .map(Into::into);
+
+        Ok(Some(Self {
+            method,
+            path: path.into(),
+            token: token.or(query_token),
+        }))
+    }
+}
+
+/// Compare tokens without exiting early so that timing does not reveal how much matched.
+fn tokens_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |acc, (e, a)| acc | (e ^ a))
+            == 0
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body,
+    )?;
+    stream.flush()
+}
+
+fn handle<F, E, L>(
+    mut stream: TcpStream,
+    config: &ServeConfig,
+    feed: &F,
+    last_synced: &L,
+) -> io::Result<()>
+where
+    F: Fn(&str) -> Option<Result<String, E>>,
+    E: fmt::Debug,
+    L: Fn() -> LastSynced,
+{
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let request = if let Some(request) = Request::read(&stream)? {
+        request
+    } else {
+        return respond(
+            &mut stream,
+            "400 Bad Request",
+            "text/plain",
+            "bad request\n",
+        );
+    };
+    debug!("{} {}", request.method, request.path);
+
+    if request.method != "GET" {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n",
+        );
+    }
+    // Monitoring should not need access to the feeds.
+    if request.path == HEALTH_PATH {
+        let health = Health::new(config, last_synced());
+        let status = if health.healthy {
+            "200 OK"
+        } else {
+            "503 Service Unavailable"
+        };
+        let body = serde_json::to_string(&health).expect("health should always be serializable");
+        return respond(&mut stream, status, "application/json", &body);
+    }
+    if !config.tokens.is_empty() {
+        let authorized = request.token.as_ref().is_some_and(|actual| {
+            config
+                .tokens
+                .iter()
+                .any(|expected| tokens_match(expected, actual))
+        });
+        if !authorized {
+            return respond(
+                &mut stream,
+                "401 Unauthorized",
+                "text/plain",
+                "unauthorized\n",
+            );
+        }
+    }
+
+    let target = request
+        .path
+        .strip_prefix(CALENDAR_PREFIX)
+        .and_then(|rest| rest.strip_suffix(CALENDAR_EXTENSION));
+    match target.and_then(feed) {
+        Some(Ok(calendar)) => respond(&mut stream, "200 OK", "text/calendar", &calendar),
+        Some(Err(err)) => {
+            error!("failed to build the feed for {}: {:?}", request.path, err);
+            respond(
+                &mut stream,
+                "500 Internal Server Error",
+                "text/plain",
+                "internal error\n",
+            )
+        },
+        None => respond(&mut stream, "404 Not Found", "text/plain", "not found\n"),
+    }
+}
+
+/// Serve read-only calendar feeds of targets over HTTP.
+///
+/// Feeds are available at `/calendars/<target>.ics`. The `feed` function builds the calendar for
+/// a target or returns `None` if there is no such target. Each connection is handled on its own
+/// thread so that slow clients do not hold up others.
+///
+/// The age of the last sync of each target is available at `/healthz` using `last_synced`.
+/// It responds with an error status if any target has not been synced within the configured
+/// maximum age.
+pub fn serve<F, E, L>(config: &ServeConfig, feed: F, last_synced: L) -> io::Result<()>
+where
+    F: Fn(&str) -> Option<Result<String, E>> + Sync,
+    E: fmt::Debug,
+    L: Fn() -> LastSynced + Sync,
+{
+    let listener = TcpListener::bind(&config.listen)?;
+    info!("serving calendar feeds on {}", listener.local_addr()?);
+    if config.tokens.is_empty() {
+        warn!("no tokens are configured; feeds are available without authentication");
+    }
+    // Connections are polled for so that the watchdog hears from the loop while it is idle.
+    listener.set_nonblocking(true)?;
+    let mut watchdog = systemd::Watchdog::new();
+    let poll_interval = watchdog.as_ref().map_or(ACCEPT_POLL_INTERVAL, |watchdog| {
+        watchdog.interval().min(ACCEPT_POLL_INTERVAL)
+    });
+    systemd::ready();
+
+    let connections = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        loop {
+            if let Some(watchdog) = watchdog.as_mut() {
+                watchdog.ping();
+            }
+
+            let mut stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(poll_interval);
+                    continue;
+                },
+                Err(err) => {
+                    warn!("failed to accept a connection: {:?}", err);
+                    thread::sleep(poll_interval);
+                    continue;
+                },
+            };
+
+            let res = stream.set_nonblocking(false);
+            if connections.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
+                let res = res.and_then(|()| {
+                    respond(
+                        &mut stream,
+                        "503 Service Unavailable",
+                        "text/plain",
+                        "too many connections\n",
+                    )
+                });
+                if let Err(err) = res {
+                    warn!("failed to reject a connection: {:?}", err);
+                }
+                continue;
+            }
+
+            connections.fetch_add(1, Ordering::SeqCst);
+            let (feed, last_synced, connections) = (&feed, &last_synced, &connections);
+            scope.spawn(move || {
+                let res = res.and_then(|()| handle(stream, config, feed, last_synced));
+                if let Err(err) = res {
+                    warn!("failed to handle a request: {:?}", err);
+                }
+                connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    })
+}
@@ -0,0 +1,149 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("failed to read state file {}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to parse state file {}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("failed to create state directory {}", path.display())]
+    CreateDirectory { path: PathBuf, source: io::Error },
+    #[error("failed to write state file {}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+}
+
+impl StateError {
+    fn read(path: PathBuf, source: io::Error) -> Self {
+        Self::Read {
+            path,
+            source,
+        }
+    }
+
+    fn parse(path: PathBuf, source: serde_json::Error) -> Self {
+        Self::Parse {
+            path,
+            source,
+        }
+    }
+
+    fn create_directory(path: PathBuf, source: io::Error) -> Self {
+        Self::CreateDirectory {
+            path,
+            source,
+        }
+    }
+
+    fn write(path: PathBuf, source: io::Error) -> Self {
+        Self::Write {
+            path,
+            source,
+        }
+    }
+}
+
+const STATE_FILE: &str = "state.json";
+
+/// Information kept between runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    /// Profiles which were skipped in the previous run (as `target/profile`).
+    #[serde(default)]
+    pub deferred_profiles: BTreeSet<String>,
+    /// When profiles with an initial window were first synced (keyed as `target/profile`).
+    #[serde(default)]
+    pub first_synced: BTreeMap<String, DateTime<Utc>>,
+    /// When targets were last synced without errors.
+    #[serde(default)]
+    pub last_synced: BTreeMap<String, DateTime<Utc>>,
+    /// Items completed locally which have been written back to their service (by URL).
+    #[serde(default)]
+    pub written_back: BTreeSet<String>,
+    /// The items returned by profiles which close missing items (keyed as `target/profile`, then
+    /// by URL) and when they stopped being returned.
+    #[serde(default)]
+    pub returned: BTreeMap<String, BTreeMap<String, Option<DateTime<Utc>>>>,
+    /// The resources of CalDAV collections as of the last sync (by target and file name).
+    #[serde(default)]
+    pub caldav: BTreeMap<String, BTreeMap<String, CaldavResource>>,
+    /// How accounts fared as of the last run which used them.
+    #[serde(default)]
+    pub accounts: BTreeMap<String, AccountStatus>,
+}
+
+/// How an account fared as of the last run which used it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AccountStatus {
+    /// When the account was last used.
+    pub last_used: Option<DateTime<Utc>>,
+    /// The number of requests remaining in the service's rate limit.
+    pub rate_limit_remaining: Option<i64>,
+    /// When the service's rate limit resets.
+    pub rate_limit_reset: Option<DateTime<Utc>>,
+    /// The last error from fetching items using the account.
+    pub last_error: Option<AccountError>,
+}
+
+/// An error from fetching items using an account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountError {
+    /// When the error happened.
+    pub time: DateTime<Utc>,
+    /// The description of the error.
+    pub message: String,
+}
+
+/// A resource in a CalDAV collection as of the last sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaldavResource {
+    /// The entity tag of the resource on the server.
+    pub etag: String,
+    /// A hash of the contents of the resource.
+    pub hash: String,
+}
+
+impl State {
+    /// The key used to refer to a profile within a target.
+    pub fn profile_key(target: &str, profile: &str) -> String {
+        format!("{}/{}", target, profile)
+    }
+
+    /// Load the state from a directory.
+    ///
+    /// A missing state file is treated as an empty state.
+    pub fn load(dir: &Path) -> Result<Self, StateError> {
+        let path = dir.join(STATE_FILE);
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(StateError::read(path, err)),
+        };
+
+        serde_json::from_slice(&contents).map_err(|err| StateError::parse(path, err))
+    }
+
+    /// Save the state into a directory.
+    pub fn save(&self, dir: &Path) -> Result<(), StateError> {
+        fs::create_dir_all(dir).map_err(|err| StateError::create_directory(dir.into(), err))?;
+        let path = dir.join(STATE_FILE);
+        let contents =
+            serde_json::to_vec_pretty(self).expect("state should always be serializable");
+        fs::write(&path, contents).map_err(|err| StateError::write(path, err))
+    }
+}
@@ -0,0 +1,264 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeSet;
+
+use itertools::Itertools;
+use uuid::Uuid;
+
+use crate::account::ItemLookup;
+use crate::todo::{TodoItem, TodoKind, TodoStatus};
+
+// The prefix of the URL fragment given to subtasks of an item.
+const URL_FRAGMENT_PREFIX: &str = "#devtodo-task-";
+// The number of hexadecimal digits of the entry's hash used in the URL.
+const HASH_LENGTH: usize = 12;
+// Markers for list entries.
+const LIST_MARKERS: &[&str] = &["- ", "* ", "+ "];
+
+/// An entry in a task list.
+pub struct Subtask {
+    /// The URL used to identify the subtask.
+    ///
+    /// It is the URL of its parent with a fragment derived from the text of the entry.
+    pub url: String,
+    summary: String,
+    checked: bool,
+}
+
+/// Parse a task list entry (e.g., `- [x] text`).
+fn parse_entry(line: &str) -> Option<(&str, bool)> {
+    let line = line.trim_start();
+    let entry = LIST_MARKERS
+        .iter()
+        .find_map(|marker| line.strip_prefix(marker))
+        .or_else(|| {
+            // Ordered lists (e.g., `1. `).
+            let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+            if rest.len() == line.len() {
+                return None;
+            }
+            rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") "))
+        })?;
+
+    let (checked, text) = if let Some(text) = entry.strip_prefix("[ ]") {
+        (false, text)
+    } else if let Some(text) = entry
+        .strip_prefix("[x]")
+        .or_else(|| entry.strip_prefix("[X]"))
+    {
+        (true, text)
+    } else {
+        return None;
+    };
+
+    if !text.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+
+    Some((text, checked))
+}
+
+/// The URL for a subtask of an item.
+///
+/// The text of the entry is hashed so that the URL stays the same as long as the entry's text
+/// does, even if entries are reordered.
+fn url(parent_url: &str, text: &str) -> String {
+    let hash = Uuid::new_v5(&Uuid::NAMESPACE_OID, text.as_bytes());
+    let hash = format!("{}", hash.simple());
+    format!(
+        "{}{}{}",
+        parent_url,
+        URL_FRAGMENT_PREFIX,
+        &hash[..HASH_LENGTH]
+    )
+}
+
+/// Find the task list entries in the markdown description of an item.
+///
+/// Entries within fenced code blocks are ignored. Entries with the same text are only
+/// included once.
+pub fn task_list(parent_url: &str, description: &str) -> Vec<Subtask> {
+    let mut in_code_block = false;
+    description
+        .lines()
+        .filter(|line| {
+            if line.trim_start().starts_with("```") {
+                in_code_block = !in_code_block;
+                return false;
+            }
+            !in_code_block
+        })
+        .filter_map(parse_entry)
+        .unique_by(|&(text, _)| text)
+        .map(|(text, checked)| {
+            Subtask {
+                url: url(parent_url, text),
+                summary: text.into(),
+                checked,
+            }
+        })
+        .collect()
+}
+
+/// Materialize the subtasks of an item.
+///
+/// Subtasks are related to the item with the given UID. Unchecked entries of items which are
+/// done are cancelled, as are existing subtasks of the item which are no longer listed (e.g.,
+/// their entry was removed or edited). Subtasks which are done locally are not reopened.
+/// Returns any newly created items.
+pub fn materialize<I>(
+    parent_url: &str,
+    parent_uid: &str,
+    parent_done: bool,
+    subtasks: I,
+    existing_items: &mut ItemLookup,
+) -> Vec<TodoItem>
+where
+    I: IntoIterator<Item = Subtask>,
+{
+    let mut listed = BTreeSet::new();
+    let new_items = subtasks
+        .into_iter()
+        .filter_map(|subtask| {
+            listed.insert(subtask.url.clone());
+
+            let status = if subtask.checked {
+                TodoStatus::Completed
+            } else if parent_done {
+                TodoStatus::Cancelled
+            } else {
+                TodoStatus::NeedsAction
+            };
+
+            if let Some(item) = existing_items.get_mut(&subtask.url) {
+                item.set_summary(subtask.summary);
+                if subtask.checked || !item.status().is_done() {
+                    item.set_status(status);
+                }
+                item.set_related_to(parent_uid);
+
+                None
+            } else {
+                let mut item = TodoItem::builder()
+                    .kind(TodoKind::Todo)
+                    .status(status)
+                    .url(subtask.url)
+                    .summary(subtask.summary)
+                    .related_to(parent_uid.into())
+                    .build()
+                    .expect("all item fields should be provided");
+                // Subtasks always use UIDs derived from their URL so that they are stable.
+                item.use_url_uid();
+
+                Some(item)
+            }
+        })
+        .collect();
+
+    let prefix = format!("{}{}", parent_url, URL_FRAGMENT_PREFIX);
+    let unlisted = existing_items
+        .iter_mut()
+        .filter(|(url, _)| url.starts_with(&prefix) && !listed.contains(*url));
+    for (_, item) in unlisted {
+        if !item.status().is_done() {
+            item.set_status(TodoStatus::Cancelled);
+        }
+    }
+
+    new_items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_entry, task_list, url};
+
+    const PARENT_URL: &str = "https://github.com/owner/repo/issues/1";
+
+    #[test]
+    fn parse_entry_markers() {
+        for line in ["- [ ] task", "* [ ] task", "+ [ ] task", "  - [ ] task"] {
+            assert_eq!(parse_entry(line), Some(("task", false)), "{}", line);
+        }
+        assert_eq!(parse_entry("- [x] task"), Some(("task", true)));
+        assert_eq!(parse_entry("- [X] task"), Some(("task", true)));
+    }
+
+    #[test]
+    fn parse_entry_ordered_lists() {
+        assert_eq!(parse_entry("1. [ ] task"), Some(("task", false)));
+        assert_eq!(parse_entry("12) [x] task"), Some(("task", true)));
+        assert_eq!(parse_entry(". [ ] task"), None);
+        assert_eq!(parse_entry("1 [ ] task"), None);
+    }
+
+    #[test]
+    fn parse_entry_not_entries() {
+        for line in [
+            "",
+            "task",
+            "- task",
+            "- [ ]",
+            "- [ ]   ",
+            "- [ ]task",
+            "-[ ] task",
+            "- [y] task",
+            "[ ] task",
+        ] {
+            assert_eq!(parse_entry(line), None, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn task_list_skips_code_fences() {
+        let description = [
+            "- [ ] before",
+            "```markdown",
+            "- [ ] inside",
+            "```",
+            "- [x] after",
+        ]
+        .join("\n");
+        let tasks = task_list(PARENT_URL, &description);
+
+        let summaries = tasks
+            .iter()
+            .map(|task| (task.summary.as_str(), task.checked))
+            .collect::<Vec<_>>();
+        assert_eq!(summaries, [("before", false), ("after", true)]);
+    }
+
+    #[test]
+    fn task_list_skips_duplicates() {
+        let description = ["- [ ] task", "- [x] task", "- [ ] other"].join("\n");
+        let tasks = task_list(PARENT_URL, &description);
+
+        let summaries = tasks
+            .iter()
+            .map(|task| (task.summary.as_str(), task.checked))
+            .collect::<Vec<_>>();
+        // The first entry wins.
+        assert_eq!(summaries, [("task", false), ("other", false)]);
+    }
+
+    #[test]
+    fn task_list_urls_depend_on_text() {
+        let tasks = task_list(PARENT_URL, "- [ ] task\n- [ ] other");
+        let reordered = task_list(PARENT_URL, "- [x] other\n- [ ] task");
+
+        assert_eq!(tasks[0].url, reordered[1].url);
+        assert_eq!(tasks[1].url, reordered[0].url);
+        assert_ne!(tasks[0].url, tasks[1].url);
+        assert_eq!(tasks[0].url, url(PARENT_URL, "task"));
+        assert!(tasks[0]
+            .url
+            .starts_with(&format!("{}#devtodo-task-", PARENT_URL)));
+    }
+}
@@ -0,0 +1,224 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SystemdError {
+    #[error("{} already exists; use `--force` to replace it", path.display())]
+    Exists { path: PathBuf },
+    #[error("failed to write {}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+    #[error("failed to determine the path to devtodo")]
+    CurrentExe { source: io::Error },
+}
+
+impl SystemdError {
+    fn exists(path: PathBuf) -> Self {
+        SystemdError::Exists {
+            path,
+        }
+    }
+
+    fn write(path: PathBuf, source: io::Error) -> Self {
+        SystemdError::Write {
+            path,
+            source,
+        }
+    }
+
+    fn current_exe(source: io::Error) -> Self {
+        SystemdError::CurrentExe {
+            source,
+        }
+    }
+}
+
+// The name of the units written by `install`.
+const UNIT_NAME: &str = "devtodo";
+
+/// Send a state update to the service manager.
+///
+/// Nothing is sent unless the process was started by systemd with `Type=notify`.
+#[cfg(unix)]
+fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket_path = if let Some(path) = env::var_os("NOTIFY_SOCKET") {
+        path
+    } else {
+        return;
+    };
+
+    let send = || -> io::Result<()> {
+        let socket = UnixDatagram::unbound()?;
+        let path = Path::new(&socket_path);
+        // Sockets starting with `@` are in the abstract namespace.
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.to_str().and_then(|path| path.strip_prefix('@')) {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            let addr = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        socket.send_to(state.as_bytes(), path)?;
+        Ok(())
+    };
+
+    debug!("notifying the service manager: {}", state);
+    if let Err(err) = send() {
+        warn!("failed to notify the service manager: {}", err);
+    }
+}
+
+#[cfg(not(unix))]
+fn notify(_: &str) {}
+
+/// Tell the service manager that startup has finished.
+pub fn ready() {
+    notify("READY=1");
+}
+
+/// Keep-alive messages for the service manager's watchdog.
+///
+/// Messages are only sent when `ping` is called, so a process which stops making progress is
+/// noticed by the service manager.
+pub struct Watchdog {
+    /// How often to send messages.
+    interval: Duration,
+    /// When the last message was sent.
+    last_sent: Option<Instant>,
+}
+
+impl Watchdog {
+    /// The watchdog for the process, if the service manager is watching it.
+    pub fn new() -> Option<Self> {
+        let usec = env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse::<u64>().ok())?;
+        // The watchdog may be meant for another process.
+        let for_us = env::var("WATCHDOG_PID")
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok())
+            .is_none_or(|pid| pid == process::id());
+        if !for_us {
+            return None;
+        }
+
+        Some(Self {
+            interval: Duration::from_micros(usec) / 2,
+            last_sent: None,
+        })
+    }
+
+    /// How often `ping` needs to be called.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Note that the process is making progress.
+    ///
+    /// Messages are sent at most every half of the requested interval.
+    pub fn ping(&mut self) {
+        let now = Instant::now();
+        let due = self
+            .last_sent
+            .is_none_or(|last_sent| now - last_sent >= self.interval);
+        if due {
+            notify("WATCHDOG=1");
+            self.last_sent = Some(now);
+        }
+    }
+}
+
+/// Quote a path for use in a unit file command line.
+fn quote(path: &Path) -> String {
+    let path = path.display().to_string();
+    format!(
+        "\"{}\"",
+        path.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%"),
+    )
+}
+
+fn service_unit(exe: &Path, config: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Sync forge items into todo lists\n\
+         Wants=network-online.target\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         ExecStart={} --config {}\n",
+        quote(exe),
+        quote(config),
+    )
+}
+
+fn timer_unit(on_calendar: &str) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Periodically sync forge items into todo lists\n\
+         \n\
+         [Timer]\n\
+         OnCalendar={}\n\
+         Persistent=true\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        on_calendar,
+    )
+}
+
+/// Write a user service and timer which sync using a configuration file.
+///
+/// The timer uses `on_calendar` as its schedule (e.g., `hourly`). Existing units are only
+/// replaced if `force` is set. Returns the paths of the written units.
+pub fn install(
+    dir: &Path,
+    config: &Path,
+    on_calendar: &str,
+    force: bool,
+) -> Result<Vec<PathBuf>, SystemdError> {
+    let exe = env::current_exe().map_err(SystemdError::current_exe)?;
+    let config = config.canonicalize().unwrap_or_else(|_| config.into());
+    let units = [
+        (
+            format!("{}.service", UNIT_NAME),
+            service_unit(&exe, &config),
+        ),
+        (format!("{}.timer", UNIT_NAME), timer_unit(on_calendar)),
+    ];
+
+    let paths = units
+        .iter()
+        .map(|(name, _)| dir.join(name))
+        .collect::<Vec<_>>();
+    if !force {
+        if let Some(path) = paths.iter().find(|path| path.exists()) {
+            return Err(SystemdError::exists(path.clone()));
+        }
+    }
+
+    fs::create_dir_all(dir).map_err(|err| SystemdError::write(dir.into(), err))?;
+    for (path, (_, contents)) in paths.iter().zip(units.iter()) {
+        fs::write(path, contents).map_err(|err| SystemdError::write(path.clone(), err))?;
+    }
+
+    Ok(paths)
+}
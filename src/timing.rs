@@ -0,0 +1,85 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use log::info;
+use serde::{Serialize, Serializer};
+
+/// The file in the state directory holding timing information of the last run.
+pub const TIMING_FILE: &str = "last-run-timing.json";
+
+fn as_millis<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_u128(duration.as_millis())
+}
+
+fn as_millis_opt<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match duration {
+        Some(duration) => as_millis(duration, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Timing information for fetching a profile.
+#[derive(Debug, Serialize)]
+pub struct ProfileTiming {
+    pub profile: String,
+    /// How long fetching items took.
+    #[serde(rename = "fetch_ms", serialize_with = "as_millis")]
+    pub fetch: Duration,
+    /// The number of requests made to the service.
+    pub requests: usize,
+}
+
+/// Timing information for syncing a target.
+#[derive(Debug, Default, Serialize)]
+pub struct TargetTiming {
+    pub profiles: Vec<ProfileTiming>,
+    /// How long merging fetched items into existing items took.
+    #[serde(rename = "merge_ms", serialize_with = "as_millis_opt")]
+    pub merge: Option<Duration>,
+    /// How long writing items took.
+    #[serde(rename = "write_ms", serialize_with = "as_millis_opt")]
+    pub write: Option<Duration>,
+}
+
+impl TargetTiming {
+    /// Log the timing information.
+    pub fn log(&self, target: &str) {
+        for profile in &self.profiles {
+            info!(
+                "{}/{}: fetched in {} ms with {} requests",
+                target,
+                profile.profile,
+                profile.fetch.as_millis(),
+                profile.requests,
+            );
+        }
+        if let Some(merge) = self.merge {
+            info!("{}: merged in {} ms", target, merge.as_millis());
+        }
+        if let Some(write) = self.write {
+            info!("{}: wrote in {} ms", target, write.as_millis());
+        }
+    }
+}
+
+/// Save the timing information for targets synced by a run.
+pub fn save(dir: &Path, timings: &BTreeMap<String, TargetTiming>) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let contents = serde_json::to_string_pretty(timings)?;
+    fs::write(dir.join(TIMING_FILE), contents)
+}
@@ -4,6 +4,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs;
 use std::io;
@@ -11,19 +12,40 @@ use std::iter;
 use std::ops;
 use std::path::{Path, PathBuf};
 
-use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use derive_builder::Builder;
 use itertools::Itertools;
+use log::warn;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
 use thiserror::Error;
 use uuid::Uuid;
 use vobject::{Component, Property};
 
+use crate::diff;
+
 #[derive(Debug, Error)]
 pub enum TodoError {
     #[error("failed to read file {}", path.display())]
     ReadFile { path: PathBuf, source: io::Error },
     #[error("failed to write file {}", path.display())]
     WriteFile { path: PathBuf, source: io::Error },
+    #[error("failed to create directory {}", path.display())]
+    CreateDirectory { path: PathBuf, source: io::Error },
+    #[error("failed to move file {} to {}", path.display(), target.display())]
+    MoveFile {
+        path: PathBuf,
+        target: PathBuf,
+        source: io::Error,
+    },
+    #[error("failed to remove file {}", path.display())]
+    RemoveFile { path: PathBuf, source: io::Error },
+    #[error("malformed {} property in {}", property, path.display())]
+    MalformedItem {
+        path: PathBuf,
+        property: &'static str,
+    },
     #[error("failed to parse vobject component")]
     ParseComponent {
         #[from]
@@ -45,6 +67,35 @@ impl TodoError {
             source,
         }
     }
+
+    fn create_directory(path: PathBuf, source: io::Error) -> Self {
+        Self::CreateDirectory {
+            path,
+            source,
+        }
+    }
+
+    fn move_file(path: PathBuf, target: PathBuf, source: io::Error) -> Self {
+        Self::MoveFile {
+            path,
+            target,
+            source,
+        }
+    }
+
+    fn malformed_item(path: PathBuf, property: &'static str) -> Self {
+        Self::MalformedItem {
+            path,
+            property,
+        }
+    }
+
+    fn remove_file(path: PathBuf, source: io::Error) -> Self {
+        Self::RemoveFile {
+            path,
+            source,
+        }
+    }
 }
 
 type TodoResult<T> = Result<T, TodoError>;
@@ -52,27 +103,122 @@ type TodoResult<T> = Result<T, TodoError>;
 pub struct TodoFile {
     path: PathBuf,
     component: Component,
+    /// The contents of the file as last read or written.
+    contents: String,
     pub item: TodoItem,
+    /// Properties which must be left as they are in the file.
+    protected: Vec<String>,
 }
 
+/// The subdirectory malformed files are moved into.
+///
+/// Subdirectories are ignored when reading a target, so these files are not synced.
+const MALFORMED_DIR: &str = "malformed";
+/// The subdirectory files duplicating another file are moved into.
+///
+/// Subdirectories are ignored when reading a target, so these files are not synced.
+const DUPLICATES_DIR: &str = "duplicates";
+
 static PRODID_PREFIX: &str = concat!("-//IDN benboeckel.net//", env!("CARGO_PKG_NAME"), "/",);
 static PRODID_SUFFIX: &str = concat!(env!("CARGO_PKG_VERSION"), " vobject", "//EN",);
 
+/// Whether a `PRODID` belongs to files which devtodo manages.
+///
+/// Only the prefix is compared so that files written by any version of devtodo are recognized.
+/// Files with a `PRODID` starting with any of the `claimed` prefixes are also managed; they
+/// are taken over by devtodo when next written.
+pub fn is_managed_prodid(prodid: &str, claimed: &[String]) -> bool {
+    prodid.starts_with(PRODID_PREFIX)
+        || claimed
+            .iter()
+            .any(|prefix| !prefix.is_empty() && prodid.starts_with(prefix.as_str()))
+}
+
+/// How files are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Overwrite files in place.
+    InPlace,
+    /// Write a temporary file and rename it over the file.
+    Atomic,
+    /// Print a diff of the changes to the file instead of writing it.
+    Diff,
+    /// Leave the file alone.
+    DryRun,
+}
+
+impl WriteMode {
+    fn write(self, path: &Path, contents: &str) -> TodoResult<()> {
+        match self {
+            WriteMode::InPlace => {
+                fs::write(path, contents.as_bytes())
+                    .map_err(|err| TodoError::write_file(path.into(), err))
+            },
+            WriteMode::Atomic => {
+                // Hidden files without the `.ics` extension are ignored by readers of the
+                // directory.
+                let file_name = path
+                    .file_name()
+                    .expect("todo files should have a file name")
+                    .to_string_lossy();
+                let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+                fs::write(&tmp_path, contents.as_bytes())
+                    .map_err(|err| TodoError::write_file(tmp_path.clone(), err))?;
+                fs::rename(&tmp_path, path)
+                    .map_err(|err| TodoError::move_file(tmp_path, path.into(), err))
+            },
+            WriteMode::Diff => {
+                let old = match fs::read_to_string(path) {
+                    Ok(old) => old,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+                    Err(err) => return Err(TodoError::read_file(path.into(), err)),
+                };
+                let name = path.display().to_string();
+                if let Some(diff) = diff::unified(&name, &name, &old, contents) {
+                    print!("{}", diff);
+                }
+                Ok(())
+            },
+            WriteMode::DryRun => Ok(()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Updated {
     Yes,
     No,
 }
 
+/// Gather the items of files into a single calendar.
+pub fn calendar<'a, I>(files: I) -> String
+where
+    I: IntoIterator<Item = &'a TodoFile>,
+{
+    let mut component = Component::new("VCALENDAR");
+    component.set(Property::new("VERSION", "2.0"));
+    component.set(Property::new(
+        "PRODID",
+        format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX),
+    ));
+    component.subcomponents.extend(
+        files
+            .into_iter()
+            .filter_map(|file| TodoFile::extract_component(&file.component)),
+    );
+
+    vobject::write_component(&component)
+}
+
 impl TodoFile {
-    pub fn from_item<P>(dir: P, item: TodoItem) -> TodoResult<Self>
+    pub fn from_item<P>(dir: P, item: TodoItem, mode: WriteMode) -> TodoResult<Self>
     where
         P: AsRef<Path>,
     {
-        Self::from_item_impl(dir.as_ref(), item)
+        Self::from_item_impl(dir.as_ref(), item, mode)
     }
 
-    fn from_item_impl(dir: &Path, item: TodoItem) -> TodoResult<Self> {
+    fn from_item_impl(dir: &Path, item: TodoItem, mode: WriteMode) -> TodoResult<Self> {
         let path = dir.join(format!("{}.ics", item.uid.0));
         let subcomponent = item.vtodo();
         let mut component = Component::new("VCALENDAR");
@@ -83,33 +229,258 @@ impl TodoFile {
         ));
         component.subcomponents.push(subcomponent);
 
-        fs::write(&path, vobject::write_component(&component).as_bytes())
-            .map_err(|err| TodoError::write_file(path.clone(), err))?;
+        let contents = vobject::write_component(&component);
+        mode.write(&path, &contents)?;
 
         Ok(Self {
             path,
             component,
+            contents,
             item,
+            protected: Vec::new(),
+        })
+    }
+
+    /// Read the `VTODO` components of a file written by any tool.
+    pub fn read_foreign<P>(path: P) -> TodoResult<Vec<Component>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents =
+            fs::read_to_string(path).map_err(|err| TodoError::read_file(path.into(), err))?;
+        let component = vobject::parse_component(&contents)?;
+
+        Ok(if component.name == "VTODO" {
+            vec![component]
+        } else {
+            component
+                .subcomponents
+                .into_iter()
+                .filter(|subcomponent| subcomponent.name == "VTODO")
+                .collect()
         })
     }
 
-    pub fn write(&mut self) -> TodoResult<()> {
+    /// Create a file from a `VTODO` written by another tool.
+    ///
+    /// Properties required for items which are missing are filled in where possible. The kind of
+    /// the item is guessed from its URL if its categories do not say. Properties of the component
+    /// which devtodo does not manage are kept.
+    pub fn import(
+        source: &Path,
+        dir: &Path,
+        mut vtodo: Component,
+        mode: WriteMode,
+    ) -> TodoResult<Self> {
+        let malformed = |property| TodoError::malformed_item(source.into(), property);
+
+        let url = vtodo
+            .get_only("URL")
+            .ok_or_else(|| malformed("URL"))?
+            .value_as_string();
+        if vtodo.get_only("SUMMARY").is_none() {
+            return Err(malformed("SUMMARY"));
+        }
+
+        let uid = vtodo
+            .get_only("UID")
+            .map(|uid| Uid(uid.value_as_string()))
+            .filter(|uid| !uid.is_empty() && !dir.join(format!("{}.ics", uid.0)).exists())
+            .unwrap_or_default();
+        vtodo.set(Property::new("UID", uid.0.clone()));
+
+        let now = format!("{}", Utc::now().format(DATE_TIME_FMT));
+        if vtodo.get_only("CREATED").is_none() {
+            let created = vtodo
+                .get_only("DTSTAMP")
+                .map_or_else(|| now.clone(), Property::value_as_string);
+            vtodo.set(Property::new("CREATED", created));
+        }
+        if vtodo.get_only("DTSTAMP").is_none() {
+            vtodo.set(Property::new("DTSTAMP", now));
+        }
+        if vtodo.get_only("STATUS").is_none() {
+            vtodo.set(Property::new("STATUS", TodoStatus::NeedsAction));
+        }
+        if vtodo.get_only("DESCRIPTION").is_none() {
+            vtodo.set(Property::new("DESCRIPTION", ""));
+        }
+
+        let mut categories = all_categories(&vtodo);
+        let has_kind = categories
+            .iter()
+            .any(|category| TodoKind::from_category(category).is_some());
+        if !has_kind {
+            let kind = if url.contains("/pull/") || url.contains("/merge_requests/") {
+                TodoKind::PullRequest
+            } else if url.contains("/issues/") {
+                TodoKind::Issue
+            } else {
+                TodoKind::Todo
+            };
+            categories.push(kind.category().into());
+            vtodo.set(Property::new(
+                "CATEGORIES",
+                format!("{}", categories.iter().format(",")),
+            ));
+        }
+
+        let item = TodoItem::from_component(vtodo.clone(), None).map_err(malformed)?;
+
+        let path = dir.join(format!("{}.ics", item.uid.0));
+        let mut component = Component::new("VCALENDAR");
+        component.set(Property::new("VERSION", "2.0"));
+        component.set(Property::new(
+            "PRODID",
+            format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX),
+        ));
+        component.subcomponents.push(vtodo);
+
+        let mut file = Self {
+            path,
+            component,
+            contents: String::new(),
+            item,
+            protected: Vec::new(),
+        };
+        // Make sure that any properties filled in while reading the item are written.
+        file.item.updated = true;
+        file.write(mode)?;
+
+        Ok(file)
+    }
+
+    pub fn write(&mut self, mode: WriteMode) -> TodoResult<()> {
         if self.sync() == Updated::Yes {
-            fs::write(
-                &self.path,
-                vobject::write_component(&self.component).as_bytes(),
-            )
-            .map_err(|err| TodoError::write_file(self.path.clone(), err))?;
+            let contents = vobject::write_component(&self.component);
+            // Leave files which would not change alone so that their modification times (and
+            // any synchronization state based on them) are stable.
+            if contents != self.contents {
+                mode.write(&self.path, &contents)?;
+                self.contents = contents;
+            }
         }
 
         Ok(())
     }
 
+    /// Mark the item as completed.
+    ///
+    /// Syncing leaves the status of existing items alone, so the properties are set on the
+    /// file directly.
+    pub fn complete(&mut self, mode: WriteMode) -> TodoResult<()> {
+        let now = Utc::now();
+        let vtodo = Self::extract_component_as_mut(&mut self.component)
+            .expect("How did the component become invalid?");
+        vtodo.set(Property::new("STATUS", TodoStatus::Completed));
+        vtodo.set(Property::new(
+            "COMPLETED",
+            format!("{}", now.format(DATE_TIME_FMT)),
+        ));
+        vtodo.set(Property::new("PERCENT-COMPLETE", "100"));
+        self.item.set_status(TodoStatus::Completed);
+        self.item.completed = Some(now);
+
+        self.write(mode)
+    }
+
+    /// Move the file into another directory.
+    pub fn move_to<P>(&mut self, dir: P) -> TodoResult<()>
+    where
+        P: AsRef<Path>,
+    {
+        self.move_to_impl(dir.as_ref())
+    }
+
+    fn move_to_impl(&mut self, dir: &Path) -> TodoResult<()> {
+        fs::create_dir_all(dir).map_err(|err| TodoError::create_directory(dir.into(), err))?;
+        let file_name = self
+            .path
+            .file_name()
+            .expect("todo files should have a file name");
+        let target = dir.join(file_name);
+        fs::rename(&self.path, &target)
+            .map_err(|err| TodoError::move_file(self.path.clone(), target.clone(), err))?;
+        self.path = target;
+
+        Ok(())
+    }
+
+    /// The path to the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The directory containing the file.
+    pub fn directory(&self) -> &Path {
+        self.path
+            .parent()
+            .expect("todo files should be within a directory")
+    }
+
+    /// Move a malformed file out of the way so that it is no longer synced.
+    ///
+    /// Returns the new path of the file.
+    pub fn set_aside(path: &Path) -> TodoResult<PathBuf> {
+        Self::move_aside(path, MALFORMED_DIR)
+    }
+
+    /// Move a file duplicating another file out of the way so that it is no longer synced.
+    ///
+    /// Returns the new path of the file.
+    pub fn set_aside_duplicate(self) -> TodoResult<PathBuf> {
+        Self::move_aside(&self.path, DUPLICATES_DIR)
+    }
+
+    /// Move a file into a subdirectory next to it.
+    fn move_aside(path: &Path, subdir: &str) -> TodoResult<PathBuf> {
+        let dir = path
+            .parent()
+            .expect("todo files should be within a directory")
+            .join(subdir);
+        fs::create_dir_all(&dir).map_err(|err| TodoError::create_directory(dir.clone(), err))?;
+        let target = dir.join(
+            path.file_name()
+                .expect("todo files should have a file name"),
+        );
+        fs::rename(path, &target)
+            .map_err(|err| TodoError::move_file(path.into(), target.clone(), err))?;
+
+        Ok(target)
+    }
+
+    /// Remove the file.
+    pub fn remove(self) -> TodoResult<()> {
+        fs::remove_file(&self.path).map_err(|err| TodoError::remove_file(self.path.clone(), err))
+    }
+
+    /// Leave properties as they are in the file when updating it.
+    ///
+    /// Property names are case-insensitive.
+    pub fn protect_properties(&mut self, names: &[String]) {
+        self.protected = names.iter().map(|name| name.to_ascii_uppercase()).collect();
+    }
+
     fn sync(&mut self) -> Updated {
         if self.item.updated {
             let vtodo = Self::extract_component_as_mut(&mut self.component)
                 .expect("How did the component become invalid?");
+            // Only properties devtodo manages are touched by updates; other properties and
+            // subcomponents (e.g., `VALARM`) added by other clients are kept.
+            let protected = self
+                .protected
+                .iter()
+                .map(|name| (name, vtodo.props.get(name).cloned()))
+                .collect::<Vec<_>>();
             self.item.update_component(vtodo);
+            for (name, props) in protected {
+                if let Some(props) = props {
+                    vtodo.props.insert(name.clone(), props);
+                } else {
+                    vtodo.props.remove(name);
+                }
+            }
             self.item.updated = false;
 
             Updated::Yes
@@ -118,32 +489,61 @@ impl TodoFile {
         }
     }
 
-    pub fn from_path<P>(path: P) -> TodoResult<Option<Self>>
+    /// Read a file.
+    ///
+    /// Files which are not managed by devtodo are skipped. See `is_managed_prodid` for how
+    /// `claimed` is used.
+    pub fn from_path<P>(path: P, claimed: &[String]) -> TodoResult<Option<Self>>
     where
         P: Into<PathBuf>,
     {
-        Self::from_path_impl(path.into())
+        Self::from_path_impl(path.into(), claimed)
     }
 
-    fn from_path_impl(path: PathBuf) -> TodoResult<Option<Self>> {
+    fn from_path_impl(path: PathBuf, claimed: &[String]) -> TodoResult<Option<Self>> {
         let contents =
             fs::read_to_string(&path).map_err(|err| TodoError::read_file(path.clone(), err))?;
-        let component = vobject::parse_component(&contents)?;
+        let mut component = vobject::parse_component(&contents)?;
+
+        let is_claimed = component
+            .get_only("PRODID")
+            .map(|prodid| prodid.value_as_string())
+            .is_some_and(|prodid| {
+                !prodid.starts_with(PRODID_PREFIX) && is_managed_prodid(&prodid, claimed)
+            });
+        if is_claimed {
+            component.set(Property::new(
+                "PRODID",
+                format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX),
+            ));
+        }
 
-        Ok(Self::extract_component(&component)
-            .and_then(TodoItem::from_component)
-            .map(|item| {
-                Self {
-                    path,
-                    component,
-                    item,
-                }
-            }))
+        let item_component = if let Some(item_component) = Self::extract_component(&component) {
+            item_component
+        } else {
+            return Ok(None);
+        };
+        // Files from other programs do not record the kind of the item.
+        let default_kind = if is_claimed {
+            Some(TodoKind::Unknown)
+        } else {
+            None
+        };
+        let item = TodoItem::from_component(item_component, default_kind)
+            .map_err(|property| TodoError::malformed_item(path.clone(), property))?;
+
+        Ok(Some(Self {
+            path,
+            component,
+            contents,
+            item,
+            protected: Vec::new(),
+        }))
     }
 
     fn is_our_component(component: &Component) -> Option<()> {
         let prodid = component.get_only("PRODID")?;
-        if !prodid.value_as_string().starts_with(PRODID_PREFIX) {
+        if !is_managed_prodid(&prodid.value_as_string(), &[]) {
             return None;
         }
         if component.subcomponents.len() != 1 {
@@ -186,6 +586,39 @@ pub enum TodoStatus {
     Cancelled,
 }
 
+impl TodoStatus {
+    /// Parse a status from its iCalendar name (e.g., `NEEDS-ACTION`).
+    pub fn parse(s: &str) -> Option<Self> {
+        [
+            Self::NeedsAction,
+            Self::Completed,
+            Self::InProcess,
+            Self::Cancelled,
+        ]
+        .iter()
+        .find(|status| status.as_ref() == s)
+        .copied()
+    }
+
+    /// Whether the status indicates that no more work is needed.
+    pub fn is_done(self) -> bool {
+        match self {
+            Self::NeedsAction | Self::InProcess => false,
+            Self::Completed | Self::Cancelled => true,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TodoStatus {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Self::parse(&value).ok_or_else(|| D::Error::custom(format!("unknown status: {}", value)))
+    }
+}
+
 impl AsRef<str> for TodoStatus {
     fn as_ref(&self) -> &str {
         match self {
@@ -203,7 +636,11 @@ pub enum TodoKind {
     AssignedIssue,
     PullRequest,
     AssignedPullRequest,
+    ReviewRequest,
+    Mention,
     Todo,
+    /// An item whose kind is not known (e.g., from a file claimed from another program).
+    Unknown,
 }
 
 static ALL_TODO_KINDS: &[TodoKind] = &[
@@ -211,21 +648,58 @@ static ALL_TODO_KINDS: &[TodoKind] = &[
     TodoKind::AssignedIssue,
     TodoKind::PullRequest,
     TodoKind::AssignedPullRequest,
+    TodoKind::ReviewRequest,
+    TodoKind::Mention,
     TodoKind::Todo,
+    TodoKind::Unknown,
 ];
 
 impl TodoKind {
+    /// Parse a kind from its category name (e.g., `review-request`).
+    pub fn from_category(category: &str) -> Option<Self> {
+        ALL_TODO_KINDS
+            .iter()
+            .find(|kind| kind.category() == category)
+            .copied()
+    }
+
+    /// The noun used to describe items of the kind (e.g., `pull request`).
+    pub fn noun(self) -> &'static str {
+        match self {
+            Self::Issue | Self::AssignedIssue => "issue",
+            Self::PullRequest | Self::AssignedPullRequest => "pull request",
+            Self::ReviewRequest => "review request",
+            Self::Mention => "mention",
+            Self::Todo => "todo",
+            Self::Unknown => "item",
+        }
+    }
+
     fn category(self) -> &'static str {
         match self {
             Self::Issue => "issue",
             Self::AssignedIssue => "assigned-issue",
             Self::PullRequest => "pull-request",
             Self::AssignedPullRequest => "assigned-pull-request",
+            Self::ReviewRequest => "review-request",
+            Self::Mention => "mention",
             Self::Todo => "todo",
+            Self::Unknown => "unknown",
         }
     }
 }
 
+impl<'de> Deserialize<'de> for TodoKind {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Self::from_category(&value)
+            .ok_or_else(|| D::Error::custom(format!("unknown kind: {}", value)))
+    }
+}
+
 impl AsRef<str> for TodoKind {
     fn as_ref(&self) -> &str {
         self.category()
@@ -234,6 +708,8 @@ impl AsRef<str> for TodoKind {
 
 pub const DATE_TIME_FMT: &str = "%Y%m%dT%H%M%SZ";
 pub const DATE_FMT: &str = "%Y%m%d";
+// Date-times without a time zone are in the local time of the reader.
+const FLOATING_DATE_TIME_FMT: &str = "%Y%m%dT%H%M%S";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Due {
@@ -242,11 +718,84 @@ pub enum Due {
 }
 
 impl Due {
-    fn from_str(s: &str) -> Option<Self> {
-        Some(match NaiveDateTime::parse_from_str(s, DATE_TIME_FMT) {
-            Ok(dt) => Due::DateTime(Utc.from_utc_datetime(&dt)),
-            Err(_) => NaiveDate::parse_from_str(s, DATE_FMT).map(Due::Date).ok()?,
-        })
+    /// The point in time at which the item becomes overdue.
+    ///
+    /// Items due on a date are due by the end of that day.
+    pub fn deadline(&self) -> DateTime<Utc> {
+        match self {
+            Due::Date(d) => {
+                Utc.from_utc_datetime(&(*d + Duration::days(1)).and_time(NaiveTime::MIN))
+            },
+            Due::DateTime(dt) => *dt,
+        }
+    }
+
+    /// Give a due date without a time the given local time of day.
+    pub fn at_time(self, time: NaiveTime) -> Self {
+        match self {
+            Due::Date(d) => {
+                let local = d.and_time(time);
+                // Times skipped by daylight saving transitions are treated as UTC.
+                let dt = Local
+                    .from_local_datetime(&local)
+                    .earliest()
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|| Utc.from_utc_datetime(&local));
+                Due::DateTime(dt)
+            },
+            due @ Due::DateTime(_) => due,
+        }
+    }
+
+    /// Parse a due date from a property.
+    ///
+    /// The `VALUE` and `TZID` parameters are honored if present. Date-times without a time zone
+    /// (or with one which is not known) are local times.
+    fn from_property(property: &Property) -> Option<Self> {
+        let value = property.value_as_string();
+        let tzid = property.params.get("TZID").map(String::as_str);
+        match property.params.get("VALUE").map(String::as_str) {
+            Some("DATE") => {
+                NaiveDate::parse_from_str(&value, DATE_FMT)
+                    .map(Due::Date)
+                    .ok()
+            },
+            Some("DATE-TIME") => Self::parse_date_time(&value, tzid),
+            _ => {
+                Self::parse_date_time(&value, tzid).or_else(|| {
+                    NaiveDate::parse_from_str(&value, DATE_FMT)
+                        .map(Due::Date)
+                        .ok()
+                })
+            },
+        }
+    }
+
+    fn parse_date_time(s: &str, tzid: Option<&str>) -> Option<Self> {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, DATE_TIME_FMT) {
+            return Some(Due::DateTime(Utc.from_utc_datetime(&dt)));
+        }
+        let local = NaiveDateTime::parse_from_str(s, FLOATING_DATE_TIME_FMT).ok()?;
+        let dt = if let Some(tz) = tzid.and_then(|tzid| tzid.parse::<Tz>().ok()) {
+            tz.from_local_datetime(&local)
+                .earliest()
+                .map(|dt| dt.with_timezone(&Utc))
+        } else {
+            Local
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+        dt.map(Due::DateTime)
+    }
+
+    /// A property holding the due date with the parameters describing its value.
+    fn property(&self, name: &str) -> Property {
+        let mut property = Property::new(name, format!("{}", self));
+        if let Due::Date(_) = self {
+            property.params.insert("VALUE".into(), "DATE".into());
+        }
+        property
     }
 }
 
@@ -259,6 +808,331 @@ impl fmt::Display for Due {
     }
 }
 
+/// Parse a human-written duration such as `2h`, `3d`, or `1d4h30m`.
+pub fn parse_human_duration(s: &str) -> Option<Duration> {
+    if s.is_empty() {
+        return None;
+    }
+
+    let seconds = Estimate::parse_units(
+        s,
+        &[
+            ('w', SECONDS_PER_WEEK),
+            ('d', SECONDS_PER_DAY),
+            ('h', SECONDS_PER_HOUR),
+            ('m', SECONDS_PER_MINUTE),
+            ('s', 1),
+        ],
+    )?;
+
+    Some(Duration::seconds(seconds))
+}
+
+/// An estimate of the effort required to complete an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Estimate(Duration);
+
+const SECONDS_PER_MINUTE: i64 = 60;
+const SECONDS_PER_HOUR: i64 = 60 * SECONDS_PER_MINUTE;
+const SECONDS_PER_DAY: i64 = 24 * SECONDS_PER_HOUR;
+const SECONDS_PER_WEEK: i64 = 7 * SECONDS_PER_DAY;
+
+impl Estimate {
+    /// Parse a human-written duration such as `2h` or `1d4h30m`.
+    pub fn from_human(s: &str) -> Option<Self> {
+        parse_human_duration(s).map(Self)
+    }
+
+    /// Parse an iCalendar duration value such as `P1DT2H`.
+    fn from_str(s: &str) -> Option<Self> {
+        let rest = s.strip_prefix('P')?;
+        let (date, time) = rest.split_once('T').unwrap_or((rest, ""));
+        // At least one unit is required, and `T` must be followed by a time.
+        if rest.is_empty() || rest.ends_with('T') {
+            return None;
+        }
+        let seconds = Self::parse_units(date, &[('W', SECONDS_PER_WEEK), ('D', SECONDS_PER_DAY)])?
+            + Self::parse_units(
+                time,
+                &[('H', SECONDS_PER_HOUR), ('M', SECONDS_PER_MINUTE), ('S', 1)],
+            )?;
+
+        Some(Self(Duration::seconds(seconds)))
+    }
+
+    fn parse_units(s: &str, units: &[(char, i64)]) -> Option<i64> {
+        let mut seconds = 0i64;
+        let mut value: Option<i64> = None;
+        for c in s.chars() {
+            if let Some(digit) = c.to_digit(10) {
+                value = Some(
+                    value
+                        .unwrap_or(0)
+                        .checked_mul(10)?
+                        .checked_add(digit.into())?,
+                );
+            } else {
+                let &(_, scale) = units.iter().find(|&&(unit, _)| unit == c)?;
+                seconds = seconds.checked_add(value.take()?.checked_mul(scale)?)?;
+            }
+        }
+
+        // Trailing numbers without a unit are not valid.
+        if value.is_some() {
+            return None;
+        }
+
+        Some(seconds)
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.0.num_seconds();
+        if seconds <= 0 {
+            return write!(f, "PT0S");
+        }
+
+        let (days, seconds) = (seconds / SECONDS_PER_DAY, seconds % SECONDS_PER_DAY);
+        let (hours, seconds) = (seconds / SECONDS_PER_HOUR, seconds % SECONDS_PER_HOUR);
+        let (minutes, seconds) = (seconds / SECONDS_PER_MINUTE, seconds % SECONDS_PER_MINUTE);
+
+        write!(f, "P")?;
+        if days > 0 {
+            write!(f, "{}D", days)?;
+        }
+        if hours > 0 || minutes > 0 || seconds > 0 {
+            write!(f, "T")?;
+            if hours > 0 {
+                write!(f, "{}H", hours)?;
+            }
+            if minutes > 0 {
+                write!(f, "{}M", minutes)?;
+            }
+            if seconds > 0 {
+                write!(f, "{}S", seconds)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A recurrence rule for an item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    /// The `RRULE` value (e.g., `FREQ=WEEKLY;BYDAY=MO`).
+    pub rule: String,
+    /// The date of the first occurrence.
+    pub start: NaiveDate,
+}
+
+impl Recurrence {
+    fn from_component(component: &Component) -> Option<Self> {
+        let rule = component.get_only("RRULE")?.value_as_string();
+        let start = match Due::from_property(component.get_only("DTSTART")?)? {
+            Due::Date(start) => start,
+            Due::DateTime(_) => return None,
+        };
+
+        Some(Self {
+            rule,
+            start,
+        })
+    }
+}
+
+/// Local adjustments to an item as its due date approaches.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Escalation {
+    /// The priority to give the item.
+    pub priority: Option<u8>,
+    /// Categories to add to the item.
+    pub categories: Vec<String>,
+}
+
+impl Escalation {
+    fn from_component(component: &Component) -> Self {
+        let priority = component
+            .get_only("X-DEVTODO-ESCALATED-PRIORITY")
+            .and_then(|prop| prop.value_as_string().parse().ok());
+        let categories = component
+            .get_only("X-DEVTODO-ESCALATED-CATEGORIES")
+            .map(|prop| {
+                prop.value_as_string()
+                    .split(',')
+                    .filter(|category| !category.is_empty())
+                    .map(Into::into)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            priority,
+            categories,
+        }
+    }
+}
+
+// Categories used for items which are blocked by or are blocking other items.
+const BLOCKED_CATEGORY: &str = "blocked";
+const BLOCKING_CATEGORY: &str = "blocking";
+
+// Category used for items which the user has handed off to someone else.
+const DELEGATED_CATEGORY: &str = "delegated";
+// The category for items which have moved on their service.
+const MOVED_CATEGORY: &str = "moved";
+
+// The classification of private items.
+const CLASS_PRIVATE: &str = "PRIVATE";
+
+/// How an item depends on other items.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dependencies {
+    /// The item is waiting on other items.
+    pub blocked: bool,
+    /// Other items are waiting on the item.
+    pub blocking: bool,
+}
+
+impl Dependencies {
+    fn from_categories(categories: &[&str]) -> Self {
+        Self {
+            blocked: categories.contains(&BLOCKED_CATEGORY),
+            blocking: categories.contains(&BLOCKING_CATEGORY),
+        }
+    }
+
+    fn categories<'a>(self) -> impl Iterator<Item = &'a str> {
+        let blocked = Some(BLOCKED_CATEGORY).filter(|_| self.blocked);
+        let blocking = Some(BLOCKING_CATEGORY).filter(|_| self.blocking);
+        blocked.into_iter().chain(blocking)
+    }
+}
+
+/// All categories of a component.
+///
+/// Some clients write a `CATEGORIES` property per category rather than a single list.
+fn all_categories(component: &Component) -> Vec<String> {
+    component
+        .get_all("CATEGORIES")
+        .iter()
+        .flat_map(|prop| {
+            prop.value_as_string()
+                .split(',')
+                .filter(|category| !category.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The categories derived from labels which were last written to a component.
+fn label_categories(component: &Component) -> Vec<String> {
+    component
+        .get_only("X-DEVTODO-LABEL-CATEGORIES")
+        .map(|prop| {
+            prop.value_as_string()
+                .split(',')
+                .filter(|category| !category.is_empty())
+                .map(Into::into)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The names of the extra properties which were last written to a component.
+fn extra_property_names(component: &Component) -> Vec<String> {
+    component
+        .get_only("X-DEVTODO-EXTRA-PROPERTIES")
+        .map(|prop| {
+            prop.value_as_string()
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(Into::into)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A file or patch associated with an item.
+///
+/// The URL is always recorded. The contents of small files may also be embedded; they are
+/// written as a separate binary `ATTACH` property right after the one with the URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    /// The URL of the attachment.
+    pub url: String,
+    /// The MIME type of the attachment, if known.
+    pub format: Option<String>,
+    /// The base64-encoded contents of the attachment, if embedded.
+    pub content: Option<String>,
+}
+
+impl Attachment {
+    pub fn new<U>(url: U) -> Self
+    where
+        U: Into<String>,
+    {
+        Self {
+            url: url.into(),
+            format: None,
+            content: None,
+        }
+    }
+
+    pub fn with_format<F>(mut self, format: F) -> Self
+    where
+        F: Into<String>,
+    {
+        self.format = Some(format.into());
+        self
+    }
+
+    /// Read the attachments from their `ATTACH` properties.
+    fn from_properties(props: &[Property]) -> Vec<Self> {
+        let mut attachments: Vec<Self> = Vec::new();
+        for prop in props {
+            let is_binary = prop
+                .params
+                .get("VALUE")
+                .is_some_and(|value| value.eq_ignore_ascii_case("BINARY"));
+            if is_binary {
+                // Embedded contents belong to the attachment before them.
+                if let Some(attachment) = attachments.last_mut() {
+                    attachment.content = Some(prop.value_as_string());
+                }
+            } else {
+                attachments.push(Self {
+                    url: prop.value_as_string(),
+                    format: prop.params.get("FMTTYPE").cloned(),
+                    content: None,
+                });
+            }
+        }
+        attachments
+    }
+
+    /// The `ATTACH` properties for the attachment.
+    fn properties(&self) -> impl Iterator<Item = Property> {
+        let with_format = |mut prop: Property| {
+            if let Some(format) = self.format.as_ref() {
+                prop.params.insert("FMTTYPE".into(), format.clone());
+            }
+            prop
+        };
+
+        let url = with_format(Property::new("ATTACH", &self.url));
+        let content = self.content.as_ref().map(|content| {
+            let mut prop = with_format(Property::new("ATTACH", content));
+            prop.params.insert("ENCODING".into(), "BASE64".into());
+            prop.params.insert("VALUE".into(), "BINARY".into());
+            prop
+        });
+        iter::once(url).chain(content)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Uid(String);
 
@@ -276,6 +1150,17 @@ impl ops::DerefMut for Uid {
     }
 }
 
+impl Uid {
+    /// A UID derived from an item's URL.
+    ///
+    /// The same URL always results in the same UID.
+    pub fn from_url(url: &str) -> Self {
+        let uuid = Uuid::new_v5(&Uuid::NAMESPACE_URL, url.as_bytes());
+
+        Self(format!("{}", uuid.hyphenated()))
+    }
+}
+
 impl Default for Uid {
     fn default() -> Self {
         let uuid = Uuid::new_v4();
@@ -295,11 +1180,59 @@ pub struct TodoItem {
     #[builder(default)]
     #[builder(setter(strip_option))]
     due: Option<Due>,
+    #[builder(default)]
+    #[builder(setter(strip_option))]
+    estimate: Option<Estimate>,
     status: TodoStatus,
     url: String,
     summary: String,
     #[builder(default)]
     description: String,
+    #[builder(default)]
+    attachments: Vec<Attachment>,
+    #[builder(default)]
+    #[builder(setter(strip_option))]
+    recurrence: Option<Recurrence>,
+    #[builder(default)]
+    #[builder(setter(skip))]
+    escalation: Escalation,
+    #[builder(default)]
+    dependencies: Dependencies,
+    #[builder(default)]
+    delegated: bool,
+    /// Categories derived from labels on the item.
+    #[builder(default)]
+    label_categories: Vec<String>,
+    /// Extra properties from the service (by name without the `X-` prefix).
+    #[builder(default)]
+    extra_properties: BTreeMap<String, String>,
+    /// Whether the item is marked as private.
+    #[builder(default)]
+    private: bool,
+    /// The identifier of the item on its service.
+    #[builder(default)]
+    #[builder(setter(strip_option))]
+    source_id: Option<String>,
+    /// Whether the item has moved on its service.
+    #[builder(default)]
+    #[builder(setter(skip))]
+    moved: bool,
+    /// The title of the milestone the item belongs to.
+    #[builder(default)]
+    #[builder(setter(strip_option))]
+    milestone: Option<String>,
+    /// The priority of the item on its service (1 is the highest, 9 the lowest).
+    #[builder(default)]
+    #[builder(setter(strip_option))]
+    priority: Option<u8>,
+    /// The UID of the item this item is a part of.
+    #[builder(default)]
+    #[builder(setter(strip_option))]
+    related_to: Option<String>,
+
+    #[builder(default)]
+    #[builder(setter(skip))]
+    completed: Option<DateTime<Utc>>,
 
     #[builder(default = "Utc::now()")]
     #[builder(setter(skip))]
@@ -323,6 +1256,37 @@ impl TodoItem {
         }
     }
 
+    /// Derive the UID of the item from its URL.
+    ///
+    /// Only meaningful for items which have not been written yet.
+    pub fn use_url_uid(&mut self) {
+        self.uid = Uid::from_url(&self.url);
+    }
+
+    /// Give a due date without a time the given local time of day.
+    pub fn set_due_time(&mut self, time: NaiveTime) {
+        if let Some(due) = self.due {
+            self.set_due(due.at_time(time));
+        }
+    }
+
+    /// Remove the due date of the item.
+    pub fn clear_due(&mut self) {
+        if self.due.is_some() {
+            self.due = None;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_estimate(&mut self, new_estimate: Option<Estimate>) {
+        if self.estimate != new_estimate {
+            self.estimate = new_estimate;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
     pub fn set_status(&mut self, new_status: TodoStatus) {
         if self.status != new_status {
             self.status = new_status;
@@ -331,6 +1295,14 @@ impl TodoItem {
         }
     }
 
+    pub fn set_kind(&mut self, new_kind: TodoKind) {
+        if self.kind != new_kind {
+            self.kind = new_kind;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
     pub fn set_summary<S>(&mut self, new_summary: S)
     where
         S: Into<String>,
@@ -358,45 +1330,293 @@ impl TodoItem {
         }
     }
 
+    pub fn set_attachments(&mut self, new_attachments: Vec<Attachment>) {
+        if self.attachments != new_attachments {
+            self.attachments = new_attachments;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_recurrence(&mut self, new_recurrence: Recurrence) {
+        if self.recurrence.as_ref() != Some(&new_recurrence) {
+            self.recurrence = Some(new_recurrence);
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_escalation(&mut self, new_escalation: Escalation) {
+        if self.escalation != new_escalation {
+            self.escalation = new_escalation;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_dependencies(&mut self, new_dependencies: Dependencies) {
+        if self.dependencies != new_dependencies {
+            self.dependencies = new_dependencies;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_delegated(&mut self, new_delegated: bool) {
+        if self.delegated != new_delegated {
+            self.delegated = new_delegated;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_private(&mut self, new_private: bool) {
+        if self.private != new_private {
+            self.private = new_private;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    /// Change the URL of the item.
+    ///
+    /// The item is marked as having moved if the URL differs.
+    pub fn set_url<U>(&mut self, new_url: U)
+    where
+        U: Into<String>,
+    {
+        let new_url = new_url.into();
+        if self.url != new_url {
+            self.url = new_url;
+            self.moved = true;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_source_id<S>(&mut self, new_source_id: S)
+    where
+        S: Into<String>,
+    {
+        let new_source_id = Some(new_source_id.into());
+        if self.source_id != new_source_id {
+            self.source_id = new_source_id;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_milestone(&mut self, new_milestone: Option<String>) {
+        if self.milestone != new_milestone {
+            self.milestone = new_milestone;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_priority(&mut self, new_priority: Option<u8>) {
+        if self.priority != new_priority {
+            self.priority = new_priority;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_related_to<S>(&mut self, new_related_to: S)
+    where
+        S: Into<String>,
+    {
+        let new_related_to = Some(new_related_to.into());
+        if self.related_to != new_related_to {
+            self.related_to = new_related_to;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_extra_properties(&mut self, new_extra_properties: BTreeMap<String, String>) {
+        if self.extra_properties != new_extra_properties {
+            self.extra_properties = new_extra_properties;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn set_label_categories(&mut self, new_label_categories: Vec<String>) {
+        if self.label_categories != new_label_categories {
+            self.label_categories = new_label_categories;
+            self.last_modified = Utc::now();
+            self.updated = true;
+        }
+    }
+
+    pub fn uid(&self) -> &str {
+        &self.uid.0
+    }
+
+    pub fn kind(&self) -> TodoKind {
+        self.kind
+    }
+
+    pub fn due(&self) -> Option<Due> {
+        self.due
+    }
+
+    pub fn status(&self) -> TodoStatus {
+        self.status
+    }
+
+    /// When the item was completed or cancelled.
+    ///
+    /// If the completion time was not recorded, the last modification time is used. Cancellation
+    /// times are never recorded.
+    pub fn done_at(&self) -> Option<DateTime<Utc>> {
+        match self.status {
+            TodoStatus::Completed => Some(self.completed.unwrap_or(self.last_modified)),
+            TodoStatus::Cancelled => Some(self.last_modified),
+            TodoStatus::NeedsAction | TodoStatus::InProcess => None,
+        }
+    }
+
     pub fn url(&self) -> &str {
         &self.url
     }
 
-    fn from_component(component: Component) -> Option<Self> {
-        let uid = Uid(component.get_only("UID")?.value_as_string());
-        let kind = {
-            let categories_value = component.get_only("CATEGORIES")?.value_as_string();
-            let categories = categories_value.split(',').collect::<Vec<_>>();
-            *ALL_TODO_KINDS
+    pub fn source_id(&self) -> Option<&str> {
+        self.source_id.as_deref()
+    }
+
+    /// Whether the item has changes which have not been written.
+    pub fn is_updated(&self) -> bool {
+        self.updated
+    }
+
+    pub fn summary(&self) -> &str {
+        &self.summary
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    /// Extract an item from a component.
+    ///
+    /// Items without a kind use `default_kind` if given. On failure, the name of the offending
+    /// property is returned.
+    fn from_component(
+        component: Component,
+        default_kind: Option<TodoKind>,
+    ) -> Result<Self, &'static str> {
+        let uid = Uid(component.get_only("UID").ok_or("UID")?.value_as_string());
+        let (kind, dependencies, delegated, moved) = {
+            let categories_value = all_categories(&component);
+            let categories = categories_value
                 .iter()
-                .find(|kind| categories.contains(&kind.category()))?
+                .map(AsRef::as_ref)
+                .collect::<Vec<_>>();
+            let kind = ALL_TODO_KINDS
+                .iter()
+                .find(|kind| categories.contains(&kind.category()))
+                .copied()
+                .or(default_kind)
+                .ok_or("CATEGORIES")?;
+            (
+                kind,
+                Dependencies::from_categories(&categories),
+                categories.contains(&DELEGATED_CATEGORY),
+                categories.contains(&MOVED_CATEGORY),
+            )
         };
+        // `DTSTAMP` is refreshed whenever the item changes, but older files only recorded the
+        // creation time there.
         let created = {
-            let dtstamp = component.get_only("DTSTAMP")?.value_as_string();
-            let dt = NaiveDateTime::parse_from_str(&dtstamp, DATE_TIME_FMT).ok()?;
+            let created = component
+                .get_only("CREATED")
+                .or_else(|| component.get_only("DTSTAMP"))
+                .ok_or("CREATED")?
+                .value_as_string();
+            let dt =
+                NaiveDateTime::parse_from_str(&created, DATE_TIME_FMT).map_err(|_| "CREATED")?;
 
             Utc.from_utc_datetime(&dt)
         };
         let due = if let Some(due) = component.get_only("DUE") {
-            Some(Due::from_str(&due.value_as_string())?)
+            Some(Due::from_property(due).ok_or("DUE")?)
         } else {
             None
         };
-        let status = match component.get_only("STATUS")?.value_as_string().as_ref() {
+        // The estimate is stored as `X-ESTIMATE` because `DURATION` may not be used alongside
+        // `DUE` in a `VTODO`.
+        // A bad estimate is dropped rather than making the whole item unreadable.
+        let estimate = component.get_only("X-ESTIMATE").and_then(|estimate| {
+            let value = estimate.value_as_string();
+            let estimate = Estimate::from_str(&value);
+            if estimate.is_none() {
+                warn!("ignoring invalid estimate `{}` on {}", value, uid.0);
+            }
+            estimate
+        });
+        let status = match component
+            .get_only("STATUS")
+            .ok_or("STATUS")?
+            .value_as_string()
+            .as_ref()
+        {
             "NEEDS-ACTION" => TodoStatus::NeedsAction,
             "COMPLETED" => TodoStatus::Completed,
             "IN-PROCESS" => TodoStatus::InProcess,
             "CANCELLED" => TodoStatus::Cancelled,
-            _ => return None,
+            _ => return Err("STATUS"),
         };
-        let url = component.get_only("URL")?.value_as_string();
-        let summary = component.get_only("SUMMARY")?.value_as_string();
-        let description = component.get_only("DESCRIPTION")?.value_as_string();
+        let url = component.get_only("URL").ok_or("URL")?.value_as_string();
+        let summary = component
+            .get_only("SUMMARY")
+            .ok_or("SUMMARY")?
+            .value_as_string();
+        let description = component
+            .get_only("DESCRIPTION")
+            .ok_or("DESCRIPTION")?
+            .value_as_string();
+        let attachments = Attachment::from_properties(component.get_all("ATTACH"));
+        let recurrence = Recurrence::from_component(&component);
+        let escalation = Escalation::from_component(&component);
+        let label_categories = label_categories(&component);
+        let extra_properties = extra_property_names(&component)
+            .into_iter()
+            .filter_map(|name| {
+                let value = component.get_only(format!("X-{}", name))?.value_as_string();
+                Some((name, value))
+            })
+            .collect();
+        let private = component
+            .get_only("CLASS")
+            .is_some_and(|class| class.value_as_string() == CLASS_PRIVATE);
+        let source_id = component
+            .get_only("X-DEVTODO-SOURCE-ID")
+            .map(|source_id| source_id.value_as_string());
+        let milestone = component
+            .get_only("X-DEVTODO-MILESTONE")
+            .map(|milestone| milestone.value_as_string());
+        let priority = component
+            .get_only("X-DEVTODO-PRIORITY")
+            .and_then(|prop| prop.value_as_string().parse().ok());
+        let related_to = component
+            .get_only("RELATED-TO")
+            .map(|related_to| related_to.value_as_string());
+        let completed = component.get_only("COMPLETED").and_then(|completed| {
+            NaiveDateTime::parse_from_str(&completed.value_as_string(), DATE_TIME_FMT)
+                .ok()
+                .map(|dt| Utc.from_utc_datetime(&dt))
+        });
         let (last_modified, updated) = if let Some(last_modified) =
             component.get_only("LAST-MODIFIED")
         {
             let dt = NaiveDateTime::parse_from_str(&last_modified.value_as_string(), DATE_TIME_FMT)
-                .ok()?;
+                .map_err(|_| "LAST-MODIFIED")?;
 
             (Utc.from_utc_datetime(&dt), false)
         } else {
@@ -404,15 +1624,30 @@ impl TodoItem {
             (Utc::now(), true)
         };
 
-        Some(TodoItem {
+        Ok(TodoItem {
             uid,
             kind,
             created,
             due,
+            estimate,
             status,
             url,
             summary,
             description,
+            attachments,
+            recurrence,
+            escalation,
+            dependencies,
+            delegated,
+            label_categories,
+            extra_properties,
+            private,
+            source_id,
+            moved,
+            milestone,
+            priority,
+            related_to,
+            completed,
             last_modified,
             updated,
         })
@@ -422,10 +1657,6 @@ impl TodoItem {
         let mut component = Component::new("VTODO");
 
         // Initialize the component.
-        component.set(Property::new(
-            "DTSTAMP",
-            format!("{}", Utc::now().format(DATE_TIME_FMT)),
-        ));
         component.set(Property::new("UID", self.uid.0.clone()));
         component.set(Property::new(
             "CREATED",
@@ -445,44 +1676,581 @@ impl TodoItem {
         component.set(Property::new("DESCRIPTION", &self.description));
         component.set(Property::new("URL", &self.url));
         if let Some(due) = self.due {
-            component.set(Property::new("DUE", format!("{}", due)));
+            // Keep the existing value as written (e.g., with a `TZID` or as a floating time) if it
+            // is the same point in time.
+            if component.get_only("DUE").and_then(Due::from_property) != Some(due) {
+                component.set(due.property("DUE"));
+            }
+        } else {
+            component.remove("DUE");
+        }
+        if let Some(estimate) = self.estimate {
+            component.set(Property::new("X-ESTIMATE", format!("{}", estimate)));
+        } else {
+            component.remove("X-ESTIMATE");
+        }
+        component.remove("ATTACH");
+        for prop in self.attachments.iter().flat_map(Attachment::properties) {
+            component.push(prop);
+        }
+        if let Some(recurrence) = self.recurrence.as_ref() {
+            component.set(Due::Date(recurrence.start).property("DTSTART"));
+            component.set(Property::new("RRULE", &recurrence.rule));
         }
 
-        component.set(Property::new(
-            "LAST-MODIFIED",
-            format!("{}", self.last_modified.format(DATE_TIME_FMT)),
-        ));
-
-        if let Some(prop) = component.get_only("CATEGORIES") {
-            let value = prop.value_as_string();
-            let categories = value.split(',');
-            let all_categories = categories.clone();
-
-            // See if we have any of the categories set.
-            let kind_categories = categories
-                .filter(|&category| {
-                    ALL_TODO_KINDS
-                        .iter()
-                        .any(|kind| category == kind.category())
-                })
-                .collect::<Vec<_>>();
+        // `DTSTAMP` tracks meaningful changes to the item so that it only changes when the
+        // file would be rewritten anyways.
+        let last_modified = format!("{}", self.last_modified.format(DATE_TIME_FMT));
+        component.set(Property::new("DTSTAMP", &last_modified));
+        component.set(Property::new("LAST-MODIFIED", last_modified));
+
+        // Only the private classification is managed. Whether devtodo set it is recorded so that
+        // classifications set by other means are left alone.
+        let is_private = component
+            .get_only("CLASS")
+            .is_some_and(|class| class.value_as_string() == CLASS_PRIVATE);
+        if self.private {
+            if !is_private {
+                component.set(Property::new("CLASS", CLASS_PRIVATE));
+                component.set(Property::new("X-DEVTODO-CLASS", CLASS_PRIVATE));
+            }
+        } else if component.get_only("X-DEVTODO-CLASS").is_some() {
+            if is_private {
+                component.remove("CLASS");
+            }
+            component.remove("X-DEVTODO-CLASS");
+        }
 
-            // Check if we have the right category already set.
-            if kind_categories.len() == 1 && kind_categories[0] == self.kind.category() {
-                // OK
+        // Escalated categories are managed below, but the record of them is updated here.
+        let previous_escalation = Escalation::from_component(component);
+
+        // Escalations and the priority from the service are recorded so that they may be undone
+        // once they no longer apply. `PRIORITY` is only changed while it holds one of the
+        // recorded values so that priorities set by hand are kept. Escalations take precedence
+        // over the priority from the service.
+        let recorded = |name| {
+            component
+                .get_only(name)
+                .map(|prop: &Property| prop.value_as_string())
+        };
+        let current_priority = recorded("PRIORITY");
+        let manages_priority = current_priority.is_none()
+            || current_priority == recorded("X-DEVTODO-ESCALATED-PRIORITY")
+            || current_priority == recorded("X-DEVTODO-PRIORITY");
+        if manages_priority {
+            if let Some(priority) = self.escalation.priority.or(self.priority) {
+                component.set(Property::new("PRIORITY", format!("{}", priority)));
             } else {
-                let new_categories = all_categories
-                    .filter(|&category| {
-                        ALL_TODO_KINDS
-                            .iter()
-                            .all(|kind| category != kind.category())
-                    })
-                    .chain(iter::once(self.kind.category()))
-                    .format(",");
-                component.set(Property::new("CATEGORIES", format!("{}", new_categories)));
+                component.remove("PRIORITY");
             }
+        }
+        match self.escalation.priority {
+            Some(priority) if manages_priority => {
+                component.set(Property::new(
+                    "X-DEVTODO-ESCALATED-PRIORITY",
+                    format!("{}", priority),
+                ));
+            },
+            _ => {
+                component.remove("X-DEVTODO-ESCALATED-PRIORITY");
+            },
+        }
+        if let Some(priority) = self.priority {
+            component.set(Property::new("X-DEVTODO-PRIORITY", format!("{}", priority)));
         } else {
-            component.set(Property::new("CATEGORIES", self.kind));
+            component.remove("X-DEVTODO-PRIORITY");
+        }
+        if self.escalation.categories.is_empty() {
+            component.remove("X-DEVTODO-ESCALATED-CATEGORIES");
+        } else {
+            component.set(Property::new(
+                "X-DEVTODO-ESCALATED-CATEGORIES",
+                format!("{}", self.escalation.categories.iter().format(",")),
+            ));
+        }
+
+        if let Some(source_id) = self.source_id.as_ref() {
+            component.set(Property::new("X-DEVTODO-SOURCE-ID", source_id));
+        }
+        if let Some(milestone) = self.milestone.as_ref() {
+            component.set(Property::new("X-DEVTODO-MILESTONE", milestone));
+        } else {
+            component.remove("X-DEVTODO-MILESTONE");
+        }
+        // Relations are only managed for subtasks; others are left alone.
+        if let Some(related_to) = self.related_to.as_ref() {
+            component.set(Property::new("RELATED-TO", related_to));
+        }
+
+        // Extra properties are recorded so that they may be removed once they are not fetched.
+        for name in extra_property_names(component) {
+            if !self.extra_properties.contains_key(&name) {
+                component.remove(format!("X-{}", name));
+            }
+        }
+        for (name, value) in &self.extra_properties {
+            component.set(Property::new(format!("X-{}", name), value));
+        }
+        if self.extra_properties.is_empty() {
+            component.remove("X-DEVTODO-EXTRA-PROPERTIES");
+        } else {
+            component.set(Property::new(
+                "X-DEVTODO-EXTRA-PROPERTIES",
+                format!("{}", self.extra_properties.keys().format(",")),
+            ));
+        }
+
+        // Label categories are recorded so that they may be removed once the label is.
+        let previous_label_categories = label_categories(component);
+        if self.label_categories.is_empty() {
+            component.remove("X-DEVTODO-LABEL-CATEGORIES");
+        } else {
+            component.set(Property::new(
+                "X-DEVTODO-LABEL-CATEGORIES",
+                format!("{}", self.label_categories.iter().format(",")),
+            ));
+        }
+
+        let value = all_categories(component);
+        let categories = value.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        let wanted_categories = iter::once(self.kind.category())
+            .chain(self.escalation.categories.iter().map(AsRef::as_ref))
+            .chain(self.dependencies.categories())
+            .chain(Some(DELEGATED_CATEGORY).filter(|_| self.delegated))
+            .chain(Some(MOVED_CATEGORY).filter(|_| self.moved))
+            .chain(self.label_categories.iter().map(AsRef::as_ref))
+            .collect::<Vec<_>>();
+        let is_managed = |category: &str| {
+            ALL_TODO_KINDS
+                .iter()
+                .any(|kind| category == kind.category())
+                || category == BLOCKED_CATEGORY
+                || category == BLOCKING_CATEGORY
+                || category == DELEGATED_CATEGORY
+                || category == MOVED_CATEGORY
+                || previous_escalation
+                    .categories
+                    .iter()
+                    .any(|escalated| category == escalated)
+                || previous_label_categories
+                    .iter()
+                    .any(|label| category == label)
+        };
+
+        // Drop categories we manage which no longer apply and add any missing ones.
+        let mut new_categories = categories
+            .iter()
+            .copied()
+            .filter(|category| !is_managed(category) || wanted_categories.contains(category))
+            .unique()
+            .collect::<Vec<_>>();
+        for category in wanted_categories {
+            if !new_categories.contains(&category) {
+                new_categories.push(category);
+            }
+        }
+
+        // Setting the property replaces all existing `CATEGORIES` properties with a single list.
+        if new_categories != categories {
+            component.set(Property::new(
+                "CATEGORIES",
+                format!("{}", new_categories.iter().format(",")),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use chrono::NaiveDate;
+    use itertools::Itertools;
+    use uuid::Uuid;
+
+    use super::{
+        Attachment, Due, Escalation, Estimate, TodoFile, TodoItem, TodoKind, TodoStatus, Updated,
+        PRODID_PREFIX, PRODID_SUFFIX,
+    };
+
+    /// A file with a single `VTODO` with the given properties.
+    fn vtodo(prodid: &str, props: &[&str]) -> String {
+        let header = [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            &format!("PRODID:{}", prodid),
+            "BEGIN:VTODO",
+            "UID:test-uid",
+            "CREATED:20260101T000000Z",
+            "DTSTAMP:20260101T000000Z",
+            "LAST-MODIFIED:20260101T000000Z",
+            "STATUS:NEEDS-ACTION",
+            "URL:https://github.com/owner/repo/issues/1",
+            "SUMMARY:An issue",
+            "DESCRIPTION:",
+        ];
+        let footer = ["END:VTODO", "END:VCALENDAR", ""];
+        header.iter().chain(props).chain(&footer).join("\r\n")
+    }
+
+    /// Write contents to a new temporary file.
+    fn temp_file(contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("devtodo-test-{}.ics", Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Read a file, removing it afterwards.
+    fn read_file(contents: &str, claimed: &[String]) -> Option<TodoFile> {
+        let path = temp_file(contents);
+        let todo_file = TodoFile::from_path(&path, claimed);
+        fs::remove_file(&path).unwrap();
+        todo_file.unwrap()
+    }
+
+    #[test]
+    fn prodid_older_version_is_managed() {
+        let prodid = format!("{}0.0.1 vobject//EN", PRODID_PREFIX);
+        let todo_file = read_file(&vtodo(&prodid, &["CATEGORIES:issue"]), &[]);
+
+        assert_eq!(todo_file.unwrap().item.kind(), TodoKind::Issue);
+    }
+
+    #[test]
+    fn prodid_unrelated_is_skipped() {
+        let contents = vtodo("-//Example Corp.//Other Client//EN", &["CATEGORIES:issue"]);
+
+        assert!(read_file(&contents, &[]).is_none());
+    }
+
+    #[test]
+    fn prodid_claimed_is_managed() {
+        let contents = vtodo("-//Example Corp.//Other Client 2.1//EN", &[]);
+        let claimed = ["-//Example Corp.//Other Client".into()];
+        let todo_file = read_file(&contents, &claimed).unwrap();
+
+        // Other programs do not record the kind of the item.
+        assert_eq!(todo_file.item.kind(), TodoKind::Unknown);
+    }
+
+    /// The `DTSTAMP` of the item in a file.
+    fn dtstamp(todo_file: &TodoFile) -> String {
+        TodoFile::extract_component_as_ref(&todo_file.component)
+            .unwrap()
+            .get_only("DTSTAMP")
+            .unwrap()
+            .value_as_string()
+    }
+
+    /// A file written by the current version of devtodo.
+    fn managed_file() -> TodoFile {
+        let prodid = format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX);
+        let contents = vtodo(&prodid, &["CATEGORIES:issue", "DUE;VALUE=DATE:20260201"]);
+        read_file(&contents, &[]).unwrap()
+    }
+
+    /// A change to an item.
+    type Setter = fn(&mut TodoItem);
+
+    #[test]
+    fn dtstamp_refreshed_by_setters() {
+        let setters: &[(&str, Setter)] = &[
+            ("summary", |item| item.set_summary("Another issue")),
+            ("kind", |item| item.set_kind(TodoKind::AssignedIssue)),
+            ("description", |item| item.set_description("Details")),
+            ("status", |item| item.set_status(TodoStatus::InProcess)),
+            ("due", |item| {
+                item.set_due(Due::Date(NaiveDate::from_ymd_opt(2026, 3, 1).unwrap()))
+            }),
+            ("clear due", TodoItem::clear_due),
+            ("estimate", |item| {
+                item.set_estimate(Estimate::from_human("2h"))
+            }),
+            ("url", |item| {
+                item.set_url("https://github.com/owner/repo/issues/2")
+            }),
+            ("private", |item| item.set_private(true)),
+            ("milestone", |item| item.set_milestone(Some("v1.0".into()))),
+            ("priority", |item| item.set_priority(Some(1))),
+            ("delegated", |item| item.set_delegated(true)),
+            ("labels", |item| {
+                item.set_label_categories(vec!["bug".into()])
+            }),
+        ];
+
+        for (name, setter) in setters {
+            let mut todo_file = managed_file();
+            let before = dtstamp(&todo_file);
+            setter(&mut todo_file.item);
+
+            assert_eq!(todo_file.sync(), Updated::Yes, "{}", name);
+            assert_ne!(dtstamp(&todo_file), before, "{}", name);
+        }
+    }
+
+    #[test]
+    fn dtstamp_unchanged_by_noop_update() {
+        let mut todo_file = managed_file();
+        let before = dtstamp(&todo_file);
+        todo_file.item.set_summary("An issue");
+        todo_file.item.set_status(TodoStatus::NeedsAction);
+        todo_file
+            .item
+            .set_due(Due::Date(NaiveDate::from_ymd_opt(2026, 2, 1).unwrap()));
+
+        assert_eq!(todo_file.sync(), Updated::No);
+        assert_eq!(dtstamp(&todo_file), before);
+    }
+
+    #[test]
+    fn update_keeps_unknown_and_protected_properties() {
+        let prodid = format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX);
+        let contents = vtodo(
+            &prodid,
+            &[
+                "CATEGORIES:issue",
+                "X-FOO:bar",
+                "BEGIN:VALARM",
+                "ACTION:DISPLAY",
+                "TRIGGER:-PT15M",
+                "END:VALARM",
+            ],
+        );
+        let mut todo_file = read_file(&contents, &[]).unwrap();
+        todo_file.protect_properties(&["description".into()]);
+        todo_file.item.set_summary("Another issue");
+        todo_file.item.set_description("Details");
+        todo_file.item.set_status(TodoStatus::Completed);
+
+        assert_eq!(todo_file.sync(), Updated::Yes);
+        let vtodo = TodoFile::extract_component_as_ref(&todo_file.component).unwrap();
+        let value = |name| vtodo.get_only(name).unwrap().value_as_string();
+        assert_eq!(value("SUMMARY"), "Another issue");
+        // Syncing leaves the status of existing items alone.
+        assert_eq!(value("STATUS"), "NEEDS-ACTION");
+        assert_eq!(value("X-FOO"), "bar");
+        assert_eq!(value("DESCRIPTION"), "");
+        assert_eq!(vtodo.subcomponents.len(), 1);
+        assert_eq!(vtodo.subcomponents[0].name, "VALARM");
+        assert_eq!(
+            vtodo.subcomponents[0]
+                .get_only("TRIGGER")
+                .unwrap()
+                .value_as_string(),
+            "-PT15M",
+        );
+    }
+
+    #[test]
+    fn update_keeps_priority_set_by_hand() {
+        let prodid = format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX);
+        let priority = |props: &[&str], service: Option<u8>| {
+            let props = [&["CATEGORIES:issue"], props].concat();
+            let mut todo_file = read_file(&vtodo(&prodid, &props), &[]).unwrap();
+            todo_file.item.set_escalation(Escalation::default());
+            todo_file.item.set_priority(service);
+            todo_file.item.set_summary("Another issue");
+            todo_file.sync();
+            TodoFile::extract_component_as_ref(&todo_file.component)
+                .unwrap()
+                .get_only("PRIORITY")
+                .map(|prop| prop.value_as_string())
         };
+
+        // Lifting an escalation restores the priority from the service.
+        assert_eq!(
+            priority(
+                &[
+                    "PRIORITY:1",
+                    "X-DEVTODO-ESCALATED-PRIORITY:1",
+                    "X-DEVTODO-PRIORITY:5"
+                ],
+                Some(5),
+            ),
+            Some("5".into()),
+        );
+        assert_eq!(
+            priority(&["PRIORITY:1", "X-DEVTODO-ESCALATED-PRIORITY:1"], None),
+            None,
+        );
+        // Priorities changed by hand are kept.
+        assert_eq!(
+            priority(&["PRIORITY:3", "X-DEVTODO-ESCALATED-PRIORITY:1"], None),
+            Some("3".into()),
+        );
+        assert_eq!(
+            priority(&["PRIORITY:3", "X-DEVTODO-PRIORITY:5"], Some(4)),
+            Some("3".into()),
+        );
+        assert_eq!(
+            priority(&["PRIORITY:3", "X-DEVTODO-PRIORITY:5"], None),
+            Some("3".into()),
+        );
+        assert_eq!(priority(&["PRIORITY:3"], Some(4)), Some("3".into()));
+        // Priorities from the service are followed.
+        assert_eq!(
+            priority(&["PRIORITY:5", "X-DEVTODO-PRIORITY:5"], Some(4)),
+            Some("4".into()),
+        );
+        assert_eq!(priority(&[], Some(4)), Some("4".into()));
+    }
+
+    #[test]
+    fn attachment_contents_follow_their_url() {
+        let prodid = format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX);
+        let contents = vtodo(
+            &prodid,
+            &[
+                "CATEGORIES:issue",
+                "ATTACH;FMTTYPE=text/plain:https://example.com/a.txt",
+                "ATTACH;ENCODING=BASE64;FMTTYPE=text/plain;VALUE=BINARY:aGk=",
+                "ATTACH:https://example.com/b.png",
+            ],
+        );
+        let mut todo_file = read_file(&contents, &[]).unwrap();
+
+        let mut a = Attachment::new("https://example.com/a.txt").with_format("text/plain");
+        a.content = Some("aGk=".into());
+        let b = Attachment::new("https://example.com/b.png");
+        assert_eq!(todo_file.item.attachments, [a.clone(), b.clone()]);
+
+        // Dropping the contents removes the binary property.
+        a.content = None;
+        todo_file.item.set_attachments(vec![a, b]);
+        assert_eq!(todo_file.sync(), Updated::Yes);
+        let vtodo = TodoFile::extract_component_as_ref(&todo_file.component).unwrap();
+        let values = vtodo
+            .get_all("ATTACH")
+            .iter()
+            .map(|prop| prop.value_as_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            ["https://example.com/a.txt", "https://example.com/b.png"],
+        );
+    }
+
+    #[test]
+    fn update_keeps_class_set_by_hand() {
+        let prodid = format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX);
+        let class = |props: &[&str]| {
+            let props = [&["CATEGORIES:issue"], props].concat();
+            let mut todo_file = read_file(&vtodo(&prodid, &props), &[]).unwrap();
+            todo_file.item.set_private(false);
+            todo_file.item.set_summary("Another issue");
+            todo_file.sync();
+            TodoFile::extract_component_as_ref(&todo_file.component)
+                .unwrap()
+                .get_only("CLASS")
+                .map(|prop| prop.value_as_string())
+        };
+
+        assert_eq!(class(&["CLASS:PRIVATE", "X-DEVTODO-CLASS:PRIVATE"]), None);
+        assert_eq!(class(&["CLASS:PRIVATE"]), Some("PRIVATE".into()));
+    }
+
+    #[test]
+    fn estimate_human_parsing() {
+        let hours = |h| Some(Estimate(chrono::Duration::hours(h)));
+
+        assert_eq!(Estimate::from_human("2h"), hours(2));
+        assert_eq!(Estimate::from_human("1d4h"), hours(28));
+        assert_eq!(Estimate::from_human("1w"), hours(168));
+        assert_eq!(
+            Estimate::from_human("1h30m"),
+            Some(Estimate(chrono::Duration::minutes(90))),
+        );
+        assert_eq!(Estimate::from_human(""), None);
+        assert_eq!(Estimate::from_human("2"), None);
+        assert_eq!(Estimate::from_human("h"), None);
+        assert_eq!(Estimate::from_human("2x"), None);
+        assert_eq!(Estimate::from_human("2H"), None);
+    }
+
+    #[test]
+    fn estimate_icalendar_parsing() {
+        let hours = |h| Some(Estimate(chrono::Duration::hours(h)));
+
+        assert_eq!(Estimate::from_str("PT2H"), hours(2));
+        assert_eq!(Estimate::from_str("P1DT4H"), hours(28));
+        assert_eq!(Estimate::from_str("P1W"), hours(168));
+        assert_eq!(Estimate::from_str("PT0S"), hours(0));
+        assert_eq!(Estimate::from_str(""), None);
+        assert_eq!(Estimate::from_str("P"), None);
+        assert_eq!(Estimate::from_str("PT"), None);
+        assert_eq!(Estimate::from_str("P1DT"), None);
+        assert_eq!(Estimate::from_str("2H"), None);
+        assert_eq!(Estimate::from_str("P2H"), None);
+    }
+
+    #[test]
+    fn estimate_round_trips() {
+        for value in ["PT2H", "P1DT4H30M", "PT45S", "P3D", "PT0S"] {
+            let estimate = Estimate::from_str(value).unwrap();
+
+            assert_eq!(format!("{}", estimate), value);
+        }
+    }
+
+    #[test]
+    fn invalid_estimate_is_ignored() {
+        let prodid = format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX);
+        let contents = vtodo(&prodid, &["CATEGORIES:issue", "X-ESTIMATE:soon"]);
+        let todo_file = read_file(&contents, &[]).unwrap();
+
+        assert_eq!(todo_file.item.estimate, None);
+    }
+
+    #[test]
+    fn due_honors_tzid() {
+        let prodid = format!("{}{}", PRODID_PREFIX, PRODID_SUFFIX);
+        let read_due = |due: &str| {
+            let contents = vtodo(&prodid, &["CATEGORIES:issue", due]);
+            let mut todo_file = read_file(&contents, &[]).unwrap();
+            let due = todo_file.item.due();
+            todo_file.item.set_summary("Another issue");
+            todo_file.sync();
+            let written = TodoFile::extract_component_as_ref(&todo_file.component)
+                .unwrap()
+                .get_only("DUE")
+                .map(|prop| (prop.params.get("TZID").cloned(), prop.value_as_string()));
+            (due, written)
+        };
+        let utc = |h| {
+            Some(Due::DateTime(
+                NaiveDate::from_ymd_opt(2026, 3, 1)
+                    .unwrap()
+                    .and_hms_opt(h, 0, 0)
+                    .unwrap()
+                    .and_utc(),
+            ))
+        };
+
+        // The time zone is used to find the point in time and is kept when written back.
+        assert_eq!(
+            read_due("DUE;TZID=Europe/Berlin:20260301T120000"),
+            (
+                utc(11),
+                Some((Some("Europe/Berlin".into()), "20260301T120000".into())),
+            ),
+        );
+        assert_eq!(
+            read_due("DUE;TZID=America/New_York:20260301T120000"),
+            (
+                utc(17),
+                Some((Some("America/New_York".into()), "20260301T120000".into())),
+            ),
+        );
+        assert_eq!(
+            read_due("DUE:20260301T120000Z"),
+            (utc(12), Some((None, "20260301T120000Z".into()))),
+        );
+        // Floating times stay floating.
+        assert_eq!(
+            read_due("DUE:20260301T120000").1,
+            Some((None, "20260301T120000".into())),
+        );
     }
 }
@@ -0,0 +1,176 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use thiserror::Error;
+
+use crate::config::VdirMetadata;
+
+#[derive(Debug, Error)]
+#[error("failed to write collection metadata to {}", path.display())]
+pub struct MetadataError {
+    path: PathBuf,
+    source: io::Error,
+}
+
+impl MetadataError {
+    fn new(path: PathBuf, source: io::Error) -> Self {
+        Self {
+            path,
+            source,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LockError {
+    #[error("{} is locked by another process", path.display())]
+    Locked { path: PathBuf },
+    #[error("failed to create lock file {}", path.display())]
+    Create { path: PathBuf, source: io::Error },
+    #[error("failed to lock {}", path.display())]
+    Lock { path: PathBuf, source: io::Error },
+}
+
+impl LockError {
+    fn locked(path: PathBuf) -> Self {
+        LockError::Locked {
+            path,
+        }
+    }
+
+    fn create(path: PathBuf, source: io::Error) -> Self {
+        LockError::Create {
+            path,
+            source,
+        }
+    }
+
+    fn lock(path: PathBuf, source: io::Error) -> Self {
+        LockError::Lock {
+            path,
+            source,
+        }
+    }
+}
+
+/// The name of the lock file within a directory.
+pub const LOCK_FILE: &str = ".lock";
+
+/// A lock on a directory held through a `.lock` file.
+///
+/// The file is locked with an advisory lock held by the operating system, so a process which
+/// dies without cleaning up does not leave the directory locked. The file itself is left in
+/// place; removing it would allow another process to lock a new file while the old one is
+/// still locked. The lock is released when dropped.
+pub struct DirectoryLock {
+    _file: fs::File,
+}
+
+impl DirectoryLock {
+    /// Acquire the lock for a directory.
+    pub fn acquire(dir: &Path) -> Result<Self, LockError> {
+        let path = dir.join(LOCK_FILE);
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|err| LockError::create(path.clone(), err))?;
+        file.try_lock().map_err(|err| {
+            match err {
+                fs::TryLockError::WouldBlock => LockError::locked(dir.into()),
+                fs::TryLockError::Error(err) => LockError::lock(path, err),
+            }
+        })?;
+
+        Ok(Self {
+            _file: file,
+        })
+    }
+}
+
+/// Create a target directory which only the user may access.
+///
+/// Todo items may contain private information from services.
+pub fn create_directory(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+
+    info!("creating the target directory {}", dir.display());
+    builder.create(dir)
+}
+
+/// Write a metadata file if its contents differ.
+fn write_metadata_file(dir: &Path, name: &str, value: &str) -> Result<(), MetadataError> {
+    let path = dir.join(name);
+    let is_current = fs::read_to_string(&path)
+        .map(|contents| contents.trim_end() == value)
+        .unwrap_or(false);
+    if !is_current {
+        info!("updating the {} of {}", name, dir.display());
+        fs::write(&path, value).map_err(|err| MetadataError::new(path, err))?;
+    }
+
+    Ok(())
+}
+
+/// Create a collection directory with its metadata.
+pub fn create_collection(dir: &Path, metadata: &VdirMetadata) -> Result<(), MetadataError> {
+    fs::create_dir_all(dir).map_err(|err| MetadataError::new(dir.into(), err))?;
+    write_metadata(dir, metadata)
+}
+
+/// Write the metadata for a collection.
+///
+/// Tools such as `vdirsyncer` read collection properties from files within the collection
+/// directory.
+pub fn write_metadata(dir: &Path, metadata: &VdirMetadata) -> Result<(), MetadataError> {
+    if let Some(displayname) = metadata.displayname.as_ref() {
+        write_metadata_file(dir, "displayname", displayname)?;
+    }
+    if let Some(color) = metadata.color.as_ref() {
+        write_metadata_file(dir, "color", color)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use uuid::Uuid;
+
+    use super::{DirectoryLock, LockError};
+
+    #[test]
+    fn lock_is_exclusive_and_released() {
+        let dir = env::temp_dir().join(format!("devtodo-test-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+
+        let lock = DirectoryLock::acquire(&dir).unwrap();
+        assert!(matches!(
+            DirectoryLock::acquire(&dir),
+            Err(LockError::Locked { .. }),
+        ));
+        drop(lock);
+        // A lock file left behind does not keep the directory locked.
+        DirectoryLock::acquire(&dir).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
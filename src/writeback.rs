@@ -0,0 +1,119 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use log::{info, warn};
+
+use crate::account::{self, ItemError, ItemSource, SourceItem};
+use crate::config::{Profile, WriteBack};
+
+// The comment left on completed items if the profile does not specify one.
+const DEFAULT_COMMENT: &str = "Marked as done.";
+
+/// How to write back the completion of an item.
+struct Action {
+    account: String,
+    action: WriteBack,
+    comment: Option<String>,
+}
+
+impl Action {
+    /// The comment to leave on an item.
+    fn comment(&self, url: &str) -> String {
+        let template = self.comment.as_deref().unwrap_or(DEFAULT_COMMENT);
+        let reference = account::short_reference(url).unwrap_or_else(|| url.into());
+        template
+            .replace("{url}", url)
+            .replace("{reference}", &reference)
+    }
+}
+
+/// How items which are completed locally are reflected on their services.
+#[derive(Default)]
+pub struct WriteBacks {
+    /// The action to use for each item (by URL).
+    items: BTreeMap<String, Action>,
+}
+
+impl WriteBacks {
+    /// Note the items fetched for a profile which writes back completions.
+    ///
+    /// The first profile to fetch an item decides what happens to it.
+    pub fn add(&mut self, account: &str, profile: &Profile, items: &[SourceItem]) {
+        if profile.write_back == WriteBack::None {
+            return;
+        }
+
+        for item in items {
+            self.items.entry(item.url.clone()).or_insert_with(|| {
+                Action {
+                    account: account.into(),
+                    action: profile.write_back,
+                    comment: profile.completion_comment.clone(),
+                }
+            });
+        }
+    }
+
+    /// Write back the completion of items.
+    ///
+    /// `completions` holds whether items which were synced are completed locally while still
+    /// open on their service. Items in `written_back` have been handled by previous runs; the
+    /// updated set is returned. Items which are open locally again are removed from it so that
+    /// completing them again is written back as well.
+    pub async fn apply(
+        &self,
+        sources: &BTreeMap<String, Box<dyn ItemSource>>,
+        completions: &BTreeMap<String, bool>,
+        mut written_back: BTreeSet<String>,
+    ) -> BTreeSet<String> {
+        written_back.retain(|url| completions.get(url).copied().unwrap_or(true));
+
+        let pending = completions
+            .iter()
+            .filter(|&(url, &completed)| completed && !written_back.contains(url))
+            .map(|(url, _)| url)
+            .collect::<Vec<_>>();
+        for url in pending {
+            let action = if let Some(action) = self.items.get(url) {
+                action
+            } else {
+                continue;
+            };
+            let item_source = if let Some(item_source) = sources.get(&action.account) {
+                item_source
+            } else {
+                continue;
+            };
+
+            let comment = action.comment(url);
+            match item_source.write_back(url, action.action, &comment).await {
+                Ok(()) => {
+                    info!("wrote back the completion of {} ({:?})", url, action.action);
+                    written_back.insert(url.clone());
+                },
+                Err(
+                    err @ ItemError::Unsupported {
+                        ..
+                    },
+                ) => {
+                    // Trying again will not help.
+                    warn!("failed to write back the completion of {}: {}", url, err);
+                    written_back.insert(url.clone());
+                },
+                Err(err) => {
+                    warn!(
+                        "failed to write back the completion of {}: {}; retrying on the next run",
+                        url, err,
+                    );
+                },
+            }
+        }
+
+        written_back
+    }
+}